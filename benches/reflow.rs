@@ -0,0 +1,52 @@
+//! Compares reflowing a layout at a new width with rebuilding it.
+//!
+//! Run with `cargo bench --bench reflow`.
+
+use parley::layout::Alignment;
+use parley::style::{FontFamily, FontStack, StyleProperty};
+use parley::{FontContext, Layout, LayoutContext};
+use std::time::{Duration, Instant};
+
+const FONT_DATA: &[u8] = include_bytes!("../tests/assets/DejaVuSans.ttf");
+const ITERATIONS: u32 = 200;
+
+fn build(fcx: &mut FontContext, lcx: &mut LayoutContext<[u8; 4]>, text: &str) -> Layout<[u8; 4]> {
+    let mut builder = lcx.ranged_builder(fcx, text, 1.);
+    builder.push_default(&StyleProperty::FontStack(FontStack::Single(
+        FontFamily::Named("DejaVu Sans"),
+    )));
+    builder.push_default(&StyleProperty::FontSize(16.));
+    builder.build()
+}
+
+/// Returns the average time of the function over the widths.
+fn time(widths: &[f32], mut f: impl FnMut(f32)) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        for &width in widths {
+            f(width);
+        }
+    }
+    start.elapsed() / (ITERATIONS * widths.len() as u32)
+}
+
+fn main() {
+    let mut fcx = FontContext::new();
+    fcx.register_fonts(FONT_DATA.to_vec()).unwrap();
+    let mut lcx = LayoutContext::new();
+    let text = "The quick brown fox jumps over the lazy dog. ".repeat(40);
+    let widths = [200., 320., 480., 640., 800.];
+    let mut layout = build(&mut fcx, &mut lcx, &text);
+    layout.break_all_lines(Some(widths[0]), Alignment::Start);
+    let reflow = time(&widths, |width| layout.reflow(Some(width)));
+    let rebuild = time(&widths, |width| {
+        let mut layout = build(&mut fcx, &mut lcx, &text);
+        layout.break_all_lines(Some(width), Alignment::Start);
+    });
+    println!("reflow:  {:?} per layout", reflow);
+    println!("rebuild: {:?} per layout", rebuild);
+    println!(
+        "speedup: {:.1}x",
+        rebuild.as_secs_f64() / reflow.as_secs_f64()
+    );
+}
@@ -1,39 +1,332 @@
 //! Context for layout.
 
 use super::bidi;
-use super::font::FontContext;
-use super::layout::Layout;
+use super::font::{Font, FontContext, FontRequest};
+use super::layout::{Alignment, Cluster, Glyph, Layout, ParagraphLayout, Run};
 use super::resolve::range::*;
 use super::resolve::*;
 use super::style::*;
 
 use swash::shape::ShapeContext;
-use swash::text::cluster::CharInfo;
+use swash::text::cluster::{Boundary, CharInfo};
+use swash::text::{BidiClass, Codepoint, Language, LineBreak, Script};
 
+use core::fmt::Write;
 use std::cell::{RefCell, RefMut};
-use std::ops::{Deref, DerefMut, RangeBounds};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::ops::{Deref, DerefMut, Range, RangeBounds, RangeInclusive};
 use std::rc::Rc;
 
 /// Context for building a text layout.
 pub struct LayoutContext<B: Brush = [u8; 4]> {
     bidi: bidi::BidiResolver,
+    plaintext_bidi: bidi::BidiResolver,
+    levels: Vec<u8>,
+    paragraph_levels: Vec<(Range<usize>, u8)>,
     rcx: ResolveContext,
     styles: Vec<RangedStyle<B>>,
     rsb: RangedStyleBuilder<B>,
     info: Vec<(CharInfo, u16)>,
     scx: ShapeContext,
+    diagnostics: bool,
+    mark_handling: MarkHandling,
+    control_chars: ControlCharRendering,
+    coalesce_runs: bool,
+    spacing_floor: Option<f32>,
+    reveal_whitespace: bool,
+    base_direction: Option<Direction>,
+    forced_breaks_start_first_line: bool,
+    font_size_bounds: Option<(f32, f32)>,
+    advance_adjuster: Option<(Rc<AdvanceAdjuster<B>>, bool)>,
+    #[cfg(feature = "profiling")]
+    analysis_time: std::time::Duration,
 }
 
+/// Function that returns the extra advance to insert between two adjacent
+/// clusters, given the run containing the first cluster. The clusters are
+/// passed in logical order.
+pub type AdvanceAdjuster<B> = dyn for<'a> Fn(&Run<'a, B>, &Cluster<'a, B>, &Cluster<'a, B>) -> f32;
+
 impl<B: Brush> LayoutContext<B> {
     pub fn new() -> Self {
         Self {
             bidi: bidi::BidiResolver::new(),
+            plaintext_bidi: bidi::BidiResolver::new(),
+            levels: vec![],
+            paragraph_levels: vec![],
             rcx: ResolveContext::default(),
             styles: vec![],
             rsb: RangedStyleBuilder::default(),
             info: vec![],
             scx: ShapeContext::default(),
+            diagnostics: false,
+            mark_handling: MarkHandling::default(),
+            control_chars: ControlCharRendering::default(),
+            coalesce_runs: false,
+            spacing_floor: None,
+            reveal_whitespace: false,
+            base_direction: Some(Direction::LeftToRight),
+            forced_breaks_start_first_line: true,
+            font_size_bounds: None,
+            advance_adjuster: None,
+            #[cfg(feature = "profiling")]
+            analysis_time: Default::default(),
+        }
+    }
+
+    /// Sets a function that is invoked for each pair of adjacent clusters
+    /// after shaping and before line breaking, returning an advance that
+    /// is added between them. This can be used to apply custom kerning or
+    /// tracking tables.
+    ///
+    /// If `across_runs` is true, the function is also invoked for the last
+    /// cluster of a run and the first cluster of the following run when
+    /// both runs have the same bidi level.
+    pub fn set_advance_adjuster(
+        &mut self,
+        across_runs: bool,
+        adjuster: impl for<'a> Fn(&Run<'a, B>, &Cluster<'a, B>, &Cluster<'a, B>) -> f32 + 'static,
+    ) {
+        self.advance_adjuster = Some((Rc::new(adjuster), across_runs));
+    }
+
+    /// Removes the function set with
+    /// [`set_advance_adjuster`](Self::set_advance_adjuster).
+    pub fn clear_advance_adjuster(&mut self) {
+        self.advance_adjuster = None;
+    }
+
+    /// Enables or disables collection of shaping diagnostics for layouts
+    /// built with this context. Diagnostics are disabled by default to
+    /// avoid the overhead of inspecting every shaped cluster.
+    ///
+    /// See [`Layout::shaping_diagnostics`].
+    pub fn set_diagnostics_enabled(&mut self, enabled: bool) {
+        self.diagnostics = enabled;
+    }
+
+    /// Sets the treatment of combining marks that have no base character,
+    /// such as a mark at the start of a paragraph. The default is
+    /// [`MarkHandling::Keep`].
+    pub fn set_mark_handling(&mut self, handling: MarkHandling) {
+        self.mark_handling = handling;
+    }
+
+    /// Sets the rendering of control characters other than line breaks and
+    /// tabs, such as NUL or BEL. The default is
+    /// [`ControlCharRendering::Shaped`].
+    pub fn set_control_char_rendering(&mut self, rendering: ControlCharRendering) {
+        self.control_chars = rendering;
+    }
+
+    /// Sets the minimum advance of a cluster after word and letter spacing
+    /// are applied, in layout units.
+    ///
+    /// Spacing is added to the advances produced by shaping, so it is
+    /// applied after kerning. By default, negative spacing may reduce an
+    /// advance below zero, causing glyphs to overlap. With a floor, spacing
+    /// never reduces an advance below the floor, although an advance that
+    /// is already below it after kerning is not increased.
+    pub fn set_spacing_advance_floor(&mut self, floor: Option<f32>) {
+        self.spacing_floor = floor;
+    }
+
+    /// Enables or disables merging of adjacent runs that are drawn with the
+    /// same font, size, variations, bidi level and script after shaping,
+    /// such as runs split by a change of locale. This reduces the number of
+    /// runs produced by lines of mixed text. Merging is disabled by default.
+    pub fn set_run_coalescing_enabled(&mut self, enabled: bool) {
+        self.coalesce_runs = enabled;
+    }
+
+    /// Sets whether whitespace is revealed in subsequently built layouts,
+    /// as in the "show whitespace" mode of a code editor.
+    ///
+    /// Shaping and advances are unaffected. Instead, the whitespace clusters
+    /// of the layout report the marker to draw in their place through
+    /// [`Cluster::whitespace_marker`]. Disabled by default.
+    pub fn set_reveal_whitespace(&mut self, enabled: bool) {
+        self.reveal_whitespace = enabled;
+    }
+
+    /// Sets the base direction of the text for subsequently created
+    /// builders, or `None` to determine it from the first strong character
+    /// of the text as in the Unicode bidirectional algorithm. The resolved
+    /// direction is available from [`Layout::direction`]. Defaults to
+    /// left-to-right.
+    pub fn set_base_direction(&mut self, direction: Option<Direction>) {
+        self.base_direction = direction;
+    }
+
+    /// Sets whether the line following a forced line break, such as a
+    /// newline, is a first line in subsequently built layouts. Enabled by
+    /// default.
+    ///
+    /// First lines are reported by [`Line::is_first_line`] and an opening
+    /// bracket or quote at their start may hang, as configured by
+    /// [`HangingPunctuation::first`]. When disabled, only the first line of
+    /// the layout is a first line, as for a CSS block containing forced
+    /// breaks.
+    ///
+    /// [`Line::is_first_line`]: crate::layout::Line::is_first_line
+    /// [`HangingPunctuation::first`]: crate::style::HangingPunctuation::first
+    pub fn set_forced_breaks_start_first_line(&mut self, enabled: bool) {
+        self.forced_breaks_start_first_line = enabled;
+    }
+
+    /// Sets the range into which every font size of subsequently built
+    /// layouts is clamped, such as to enforce a minimum font size chosen by
+    /// the user, or `None` to use font sizes as specified.
+    ///
+    /// The bounds are in the units of [`StyleProperty::FontSize`], before
+    /// the scale of the builder is applied, and also apply to the default
+    /// font size. Line heights are relative to the font size, so they scale
+    /// proportionally with a clamped size, as do lengths specified in `em`.
+    /// Absolute lengths, such as letter spacing in pixels, are unaffected.
+    pub fn set_font_size_bounds(&mut self, bounds: Option<(f32, f32)>) {
+        self.font_size_bounds = bounds;
+    }
+
+    /// Shapes a single run of text with the specified font and parameters,
+    /// bypassing font selection, bidi resolution and line breaking.
+    pub fn shape_run(&mut self, params: &ShapeRunParams) -> ShapedRun {
+        let direction = match params.direction {
+            Direction::LeftToRight => swash::shape::Direction::LeftToRight,
+            Direction::RightToLeft => swash::shape::Direction::RightToLeft,
+        };
+        let mut shaper = self
+            .scx
+            .builder(params.font.as_ref())
+            .script(params.script)
+            .language(params.locale.and_then(Language::parse))
+            .direction(direction)
+            .size(params.size)
+            .features(params.features.iter().copied())
+            .variations(params.variations.iter().copied())
+            .build();
+        shaper.add_str(params.text);
+        let mut run = ShapedRun::default();
+        shaper.shape_with(|cluster| {
+            let glyph_start = run.glyphs.len();
+            run.glyphs.extend(cluster.glyphs.iter().map(|g| Glyph {
+                id: g.id,
+                style_index: 0,
+                source_offset: 0,
+                x: g.x,
+                y: g.y,
+                advance: g.advance,
+            }));
+            let advance = cluster.advance();
+            run.advance += advance;
+            run.clusters.push(ShapedCluster {
+                text_range: cluster.source.to_range(),
+                glyph_range: glyph_start..run.glyphs.len(),
+                component_count: cluster.components.len().max(1),
+                advance,
+            });
+        });
+        run
+    }
+
+    /// Shapes and breaks a single paragraph, returning a self-contained
+    /// result that can be cached and later combined with other paragraphs
+    /// using [`Layout::from_paragraphs`].
+    ///
+    /// The `styles` function is invoked with a builder for the paragraph
+    /// text to push style properties.
+    pub fn layout_paragraph(
+        &mut self,
+        fcx: &mut FontContext,
+        text: &str,
+        scale: f32,
+        max_advance: Option<f32>,
+        alignment: Alignment,
+        styles: impl FnOnce(&mut RangedBuilder<B, &str>),
+    ) -> ParagraphLayout<B> {
+        let mut builder = self.ranged_builder(fcx, text, scale);
+        styles(&mut builder);
+        let mut layout = builder.build();
+        layout.break_all_lines(max_advance, alignment);
+        ParagraphLayout::new(layout)
+    }
+
+    /// Lays out a marker, such as the bullet or number of a list item,
+    /// followed by a body with a hanging indent, so that the wrapped lines
+    /// of the body align under its first line. See
+    /// [`Layout::break_all_lines_with_marker`] for the placement of the
+    /// marker within a region at least `marker_width` wide.
+    ///
+    /// The layout covers the marker text followed by the body text, and the
+    /// `styles` function is invoked with a builder for the combined text to
+    /// push style properties, so the body begins at the offset of the length
+    /// of the marker text.
+    #[allow(clippy::too_many_arguments)]
+    pub fn layout_with_marker(
+        &mut self,
+        fcx: &mut FontContext,
+        marker_text: &str,
+        body_text: &str,
+        scale: f32,
+        marker_width: f32,
+        max_advance: Option<f32>,
+        alignment: Alignment,
+        styles: impl FnOnce(&mut RangedBuilder<B, &str>),
+    ) -> Layout<B> {
+        let text = [marker_text, body_text].concat();
+        let mut builder = self.ranged_builder(fcx, &text, scale);
+        styles(&mut builder);
+        let mut layout = builder.build();
+        layout.break_all_lines_with_marker(marker_text.len(), marker_width, max_advance, alignment);
+        layout
+    }
+
+    /// Returns the largest default font size within the specified range at
+    /// which the text fits within the maximum width and height without any
+    /// unbreakable segment overflowing and, if `max_lines` is specified,
+    /// within that number of lines. The minimum of the range is returned if
+    /// the text does not fit at any size.
+    ///
+    /// The `styles` function is invoked with a builder for each candidate
+    /// layout to push style properties. The default font size is set after
+    /// it returns, so sizes pushed for specific ranges are kept as is.
+    #[allow(clippy::too_many_arguments)]
+    pub fn fit_text(
+        &mut self,
+        fcx: &mut FontContext,
+        text: &str,
+        max_width: f32,
+        max_height: f32,
+        max_lines: Option<usize>,
+        size_range: RangeInclusive<f32>,
+        styles: impl Fn(&mut RangedBuilder<B, &str>),
+    ) -> f32 {
+        let mut layout = Layout::new();
+        let mut fits = |size: f32| {
+            let mut builder = self.ranged_builder(fcx, text, 1.);
+            styles(&mut builder);
+            builder.push_default(&StyleProperty::FontSize(size));
+            builder.build_into(&mut layout);
+            if layout.min_width_without_overflow() > max_width {
+                return false;
+            }
+            layout.break_all_lines(Some(max_width), Alignment::Start);
+            layout.height() <= max_height && layout.len() <= max_lines.unwrap_or(usize::MAX)
+        };
+        let (mut low, mut high) = (*size_range.start(), *size_range.end());
+        if fits(high) {
+            return high;
         }
+        // Binary search until the candidates are within a quarter of a unit.
+        while high - low > 0.25 {
+            let mid = (low + high) * 0.5;
+            if fits(mid) {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        low
     }
 
     pub fn ranged_builder<'a>(
@@ -52,7 +345,25 @@ impl<B: Brush> LayoutContext<B> {
         }
     }
 
+    /// Computes the ranged styles from the pushed properties, applying the
+    /// font size bounds.
+    fn finish_styles(&mut self, scale: f32) {
+        self.rsb.finish(&mut self.styles);
+        clamp_font_sizes(&mut self.styles, self.font_size_bounds, scale);
+    }
+
+    /// Computes the ranged styles from the properties pushed so far without
+    /// consuming them, so that more properties may be pushed afterwards.
+    fn peek_styles(&self, scale: f32) -> Vec<RangedStyle<B>> {
+        let mut styles = vec![];
+        self.rsb.clone().finish(&mut styles);
+        clamp_font_sizes(&mut styles, self.font_size_bounds, scale);
+        styles
+    }
+
     fn begin(&mut self, text: &str) {
+        #[cfg(feature = "profiling")]
+        let start = std::time::Instant::now();
         self.rcx.clear();
         self.styles.clear();
         self.rsb.begin(text.len());
@@ -63,14 +374,242 @@ impl<B: Brush> LayoutContext<B> {
         for x in a.by_ref() {
             self.info.push((CharInfo::new(x.0, x.1), 0));
         }
-        if a.needs_bidi_resolution() {
+        let base_level = self.base_direction.map(|direction| match direction {
+            Direction::LeftToRight => 0,
+            Direction::RightToLeft => 1,
+        });
+        if a.needs_bidi_resolution() || base_level == Some(1) {
             self.bidi.resolve(
                 text.chars()
                     .zip(self.info.iter().map(|info| info.0.bidi_class())),
-                Some(0),
+                base_level,
             );
         }
+        #[cfg(feature = "profiling")]
+        {
+            self.analysis_time = start.elapsed();
+        }
     }
+
+    /// Computes the final bidi levels, resolving each paragraph of
+    /// `unicode-bidi: plaintext` ranges in isolation with a direction
+    /// determined by its first strong character and then applying direction
+    /// overrides.
+    fn resolve_levels(&mut self, text: &str) {
+        self.levels.clear();
+        self.levels.extend_from_slice(self.bidi.levels());
+        self.paragraph_levels.clear();
+        if !self.levels.is_empty() {
+            self.resolve_plaintext_levels(text);
+        }
+        self.apply_direction_overrides(text);
+    }
+
+    /// Raises the levels of ranges with a direction override to the nearest
+    /// level of the requested direction. Raising (rather than replacing)
+    /// the levels keeps the overridden text nested within its surrounding
+    /// runs during reordering.
+    fn apply_direction_overrides(&mut self, text: &str) {
+        if self
+            .styles
+            .iter()
+            .all(|style| style.style.direction_override.is_none())
+        {
+            return;
+        }
+        if self.levels.is_empty() {
+            self.levels.resize(text.chars().count(), 0);
+        }
+        let mut char_start = 0;
+        for style in &self.styles {
+            let char_len = text[style.range.clone()].chars().count();
+            if let Some(direction) = style.style.direction_override {
+                let parity = match direction {
+                    Direction::LeftToRight => 0,
+                    Direction::RightToLeft => 1,
+                };
+                for level in &mut self.levels[char_start..char_start + char_len] {
+                    if *level & 1 != parity {
+                        *level += 1;
+                    }
+                }
+            }
+            char_start += char_len;
+        }
+    }
+
+    fn resolve_plaintext_levels(&mut self, text: &str) {
+        let mut char_start = 0;
+        let mut i = 0;
+        while i < self.styles.len() {
+            let start = self.styles[i].range.start;
+            let mut end = self.styles[i].range.end;
+            let is_plaintext = self.styles[i].style.unicode_bidi == UnicodeBidi::Plaintext;
+            i += 1;
+            if is_plaintext {
+                while i < self.styles.len()
+                    && self.styles[i].style.unicode_bidi == UnicodeBidi::Plaintext
+                {
+                    end = self.styles[i].range.end;
+                    i += 1;
+                }
+            }
+            let char_len = text[start..end].chars().count();
+            if !is_plaintext {
+                char_start += char_len;
+                continue;
+            }
+            let chars = text[start..end].chars();
+            let infos = &self.info[char_start..char_start + char_len];
+            let mut paragraph_start = 0;
+            let mut paragraph_offset = start;
+            let mut offset = start;
+            for (j, (info, ch)) in infos.iter().zip(chars.clone()).enumerate() {
+                offset += ch.len_utf8();
+                if info.0.bidi_class() != BidiClass::B && j + 1 != char_len {
+                    continue;
+                }
+                let paragraph = paragraph_start..j + 1;
+                self.plaintext_bidi.resolve(
+                    chars
+                        .clone()
+                        .skip(paragraph.start)
+                        .take(paragraph.len())
+                        .zip(
+                            infos[paragraph.clone()]
+                                .iter()
+                                .map(|info| info.0.bidi_class()),
+                        ),
+                    None,
+                );
+                self.levels[char_start + paragraph.start..char_start + paragraph.end]
+                    .copy_from_slice(self.plaintext_bidi.levels());
+                let level = self.plaintext_bidi.base_level();
+                if level != self.bidi.base_level() {
+                    self.paragraph_levels
+                        .push((paragraph_offset..offset, level));
+                }
+                paragraph_start = j + 1;
+                paragraph_offset = offset;
+            }
+            char_start += char_len;
+        }
+    }
+
+    /// Adds the line break opportunities allowed by the strictness of each
+    /// range to the character analysis.
+    fn tailor_line_breaks(&mut self, text: &str) {
+        if self.styles.iter().all(|style| {
+            matches!(
+                style.style.line_break,
+                LineBreakStrictness::Auto | LineBreakStrictness::Strict
+            )
+        }) {
+            return;
+        }
+        let mut prev: Option<(char, LineBreak)> = None;
+        for (ch, (info, style_index)) in text.chars().zip(self.info.iter_mut()) {
+            let class = ch.line_break();
+            if let Some((prev_ch, prev_class)) = prev {
+                let strictness = self.styles[*style_index as usize].style.line_break;
+                if info.boundary() != Boundary::Line
+                    && info.boundary() != Boundary::Mandatory
+                    && allows_tailored_break(strictness, prev_ch, prev_class, ch, class)
+                {
+                    *info = CharInfo::new(info.properties(), Boundary::Line);
+                }
+            }
+            prev = Some((ch, class));
+        }
+    }
+}
+
+/// Clamps the font sizes of the styles to the specified bounds, which are
+/// multiplied by the scale.
+fn clamp_font_sizes<B: Brush>(
+    styles: &mut [RangedStyle<B>],
+    bounds: Option<(f32, f32)>,
+    scale: f32,
+) {
+    if let Some((min, max)) = bounds {
+        for ranged in styles {
+            let size = &mut ranged.style.font_size;
+            *size = size.max(min * scale).min(max * scale);
+        }
+    }
+}
+
+/// Returns true if the strictness allows a break between the specified
+/// characters that UAX #14 prohibits.
+///
+/// See <https://drafts.csswg.org/css-text-3/#line-break-property>.
+fn allows_tailored_break(
+    strictness: LineBreakStrictness,
+    prev: char,
+    prev_class: LineBreak,
+    ch: char,
+    class: LineBreak,
+) -> bool {
+    let is_ideographic = |class| matches!(class, LineBreak::ID | LineBreak::CJ);
+    let normal = is_ideographic(prev_class)
+        && (class == LineBreak::CJ
+            || matches!(ch, '\u{2010}' | '\u{2013}' | '\u{301C}' | '\u{30A0}'));
+    match strictness {
+        LineBreakStrictness::Auto | LineBreakStrictness::Strict => false,
+        LineBreakStrictness::Normal => normal,
+        LineBreakStrictness::Loose => {
+            normal
+                || (is_ideographic(prev_class) || prev_class == LineBreak::IN)
+                    && class == LineBreak::IN
+                || is_ideographic(prev_class)
+                    && (is_iteration_mark(ch) || is_centered_punctuation(ch) || is_postfix(ch))
+                || is_prefix(prev) && is_ideographic(class)
+        }
+    }
+}
+
+fn is_iteration_mark(ch: char) -> bool {
+    matches!(
+        ch,
+        '\u{3005}' | '\u{303B}' | '\u{309D}' | '\u{309E}' | '\u{30FD}' | '\u{30FE}'
+    )
+}
+
+fn is_centered_punctuation(ch: char) -> bool {
+    matches!(
+        ch,
+        '\u{30FB}'
+            | '\u{FF1A}'
+            | '\u{FF1B}'
+            | '\u{FF65}'
+            | '\u{203C}'
+            | '\u{2047}'
+            | '\u{2048}'
+            | '\u{2049}'
+            | '\u{FF01}'
+            | '\u{FF1F}'
+    )
+}
+
+fn is_postfix(ch: char) -> bool {
+    matches!(
+        ch,
+        '%' | '\u{A2}'
+            | '\u{B0}'
+            | '\u{2030}'
+            | '\u{2032}'
+            | '\u{2033}'
+            | '\u{2103}'
+            | '\u{FF05}'
+            | '\u{FFE0}'
+    )
+}
+
+fn is_prefix(ch: char) -> bool {
+    matches!(
+        ch,
+        '$' | '\u{A3}' | '\u{A5}' | '\u{20AC}' | '\u{2116}' | '\u{FF04}' | '\u{FFE1}' | '\u{FFE5}'
+    )
 }
 
 impl<B: Brush> Default for LayoutContext<B> {
@@ -81,11 +620,102 @@ impl<B: Brush> Default for LayoutContext<B> {
 
 impl<B: Brush> Clone for LayoutContext<B> {
     fn clone(&self) -> Self {
-        // None of the internal state is visible so just return a new instance.
-        Self::new()
+        // None of the internal state is visible so just return a new instance
+        // with the same configuration.
+        let mut lcx = Self::new();
+        lcx.diagnostics = self.diagnostics;
+        lcx.mark_handling = self.mark_handling;
+        lcx.control_chars = self.control_chars;
+        lcx.coalesce_runs = self.coalesce_runs;
+        lcx.spacing_floor = self.spacing_floor;
+        lcx.reveal_whitespace = self.reveal_whitespace;
+        lcx.base_direction = self.base_direction;
+        lcx.forced_breaks_start_first_line = self.forced_breaks_start_first_line;
+        lcx.font_size_bounds = self.font_size_bounds;
+        lcx.advance_adjuster = self.advance_adjuster.clone();
+        lcx
     }
 }
 
+/// Treatment of combining marks that have no base character.
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub enum MarkHandling {
+    /// Marks are shaped as they are, without a base.
+    #[default]
+    Keep,
+    /// A dotted circle (U+25CC) is inserted as the base of the marks, as
+    /// recommended by Unicode, if the font supports it.
+    InsertDottedCircle,
+    /// Marks are not rendered. The marks remain in the layout as clusters
+    /// with no glyphs and zero advance.
+    Drop,
+}
+
+/// Rendering of control characters other than line breaks and tabs.
+///
+/// Substituted characters are shaped in place of the control characters, so
+/// each control character remains a single cluster covering its source
+/// text.
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub enum ControlCharRendering {
+    /// Control characters are shaped with the font, which usually renders
+    /// them as invisible or as the missing glyph.
+    #[default]
+    Shaped,
+    /// Control characters are rendered as the corresponding symbol from the
+    /// Control Pictures block, such as U+2400 for NUL. Controls without a
+    /// picture are rendered as a box.
+    Pictures,
+    /// Control characters are rendered as a white square (U+25A1).
+    Box,
+}
+
+/// Parameters for [`LayoutContext::shape_run`].
+#[derive(Copy, Clone)]
+pub struct ShapeRunParams<'a> {
+    /// Text to shape.
+    pub text: &'a str,
+    /// Font used for every cluster.
+    pub font: &'a Font,
+    /// Font size.
+    pub size: f32,
+    /// Direction of the run.
+    pub direction: Direction,
+    /// Script of the run.
+    pub script: Script,
+    /// Language tag of the run.
+    pub locale: Option<&'a str>,
+    /// Font features.
+    pub features: &'a [FontFeature],
+    /// Font variation settings.
+    pub variations: &'a [FontVariation],
+}
+
+/// Result of shaping a single run of text.
+#[derive(Clone, Default, Debug)]
+pub struct ShapedRun {
+    /// Glyphs in logical order of the clusters.
+    pub glyphs: Vec<Glyph>,
+    /// Clusters in logical order.
+    pub clusters: Vec<ShapedCluster>,
+    /// Total advance of the run.
+    pub advance: f32,
+}
+
+/// Cluster produced by [`LayoutContext::shape_run`].
+#[derive(Clone, Debug)]
+pub struct ShapedCluster {
+    /// Range of the source text.
+    pub text_range: Range<usize>,
+    /// Range of the glyphs in [`ShapedRun::glyphs`].
+    pub glyph_range: Range<usize>,
+    /// Number of components of the cluster. This is greater than one if
+    /// the cluster was formed by a ligature spanning multiple characters.
+    pub component_count: usize,
+    /// Advance of the cluster.
+    pub advance: f32,
+}
+
 #[doc(hidden)]
 #[derive(Clone)]
 pub struct RcLayoutContext<B: Brush> {
@@ -138,7 +768,95 @@ impl<'a, B: Brush, T: TextSource> RangedBuilder<'a, B, T> {
         lcx.rsb.push(resolved, range);
     }
 
+    /// Returns the fonts that will be selected for the text, including
+    /// fallback fonts, without shaping or building a layout. This can be
+    /// used to ensure that each font is available before laying out.
+    ///
+    /// Each request covers a range of source text mapped to a single font,
+    /// in logical order. The pushed styles are kept, so more styles may be
+    /// pushed and the builder built afterwards.
+    pub fn required_fonts(&mut self) -> Vec<FontRequest> {
+        let mut lcx = self.lcx.borrow_mut();
+        let lcx = &mut *lcx;
+        let text = self.text.as_str();
+        let mut fcx = self.fcx.borrow_mut();
+        let styles = lcx.peek_styles(self.scale);
+        // The style indices are assigned again when the layout is built.
+        let mut char_index = 0;
+        for (i, style) in styles.iter().enumerate() {
+            for _ in text[style.range.clone()].chars() {
+                lcx.info[char_index].1 = i as u16;
+                char_index += 1;
+            }
+        }
+        super::shape::required_fonts(
+            &lcx.rcx,
+            &mut fcx,
+            &styles,
+            &lcx.info,
+            lcx.control_chars,
+            text,
+        )
+    }
+
+    /// Returns a hash of the inputs that determine the layout produced by
+    /// building with the pushed styles and breaking all lines with the
+    /// specified maximum advance and alignment. This can be used as the key
+    /// of a cache of layouts to avoid building a layout when the inputs have
+    /// not changed.
+    ///
+    /// The hash covers the text, the scale, the resolved styles including
+    /// their brushes and the layout options of the context other than the
+    /// advance adjuster. Brushes are hashed through
+    /// their [`Debug`](core::fmt::Debug) representation. The hash is only
+    /// stable for layouts built with the same font context within a single
+    /// run of a program.
+    ///
+    /// The pushed styles are kept, so more styles may be pushed and the
+    /// builder built afterwards.
+    pub fn layout_key(&mut self, max_advance: Option<f32>, alignment: Alignment) -> u64 {
+        let lcx = self.lcx.borrow_mut();
+        let styles = lcx.peek_styles(self.scale);
+        let mut hasher = DefaultHasher::new();
+        self.text.as_str().hash(&mut hasher);
+        self.scale.to_bits().hash(&mut hasher);
+        max_advance.map(f32::to_bits).hash(&mut hasher);
+        alignment.hash(&mut hasher);
+        let mut writer = HashWriter(&mut hasher);
+        // Resolved handles are indices into the resolve context, so the
+        // lists they refer to are hashed instead.
+        let _ = write!(
+            writer,
+            "{:?}",
+            (
+                lcx.diagnostics,
+                lcx.mark_handling,
+                lcx.control_chars,
+                lcx.coalesce_runs,
+                lcx.spacing_floor,
+                lcx.reveal_whitespace,
+                lcx.base_direction,
+                lcx.forced_breaks_start_first_line,
+            )
+        );
+        for ranged in &styles {
+            let style = &ranged.style;
+            let _ = write!(
+                writer,
+                "{:?}{:?}{:?}{:?}{:?}",
+                ranged.range,
+                style,
+                lcx.rcx.stack(style.font_stack),
+                lcx.rcx.variations(style.font_variations),
+                lcx.rcx.features(style.font_features),
+            );
+        }
+        hasher.finish()
+    }
+
     pub fn build_into(&mut self, layout: &mut Layout<B>) {
+        #[cfg(feature = "profiling")]
+        let start = std::time::Instant::now();
         layout.data.clear();
         layout.data.scale = self.scale;
         let mut lcx = self.lcx.borrow_mut();
@@ -149,11 +867,13 @@ impl<'a, B: Brush, T: TextSource> RangedBuilder<'a, B, T> {
             // Force a layout to have at least one line.
             text = " ";
         }
-        layout.data.has_bidi = !lcx.bidi.levels().is_empty();
         layout.data.base_level = lcx.bidi.base_level();
         layout.data.text_len = text.len();
+        layout.data.reveal_whitespace = lcx.reveal_whitespace;
+        layout.data.mark_handling = lcx.mark_handling;
+        layout.data.forced_breaks_start_first_line = lcx.forced_breaks_start_first_line;
         let mut fcx = self.fcx.borrow_mut();
-        lcx.rsb.finish(&mut lcx.styles);
+        lcx.finish_styles(self.scale);
         let mut char_index = 0;
         for (i, style) in lcx.styles.iter().enumerate() {
             for _ in text[style.range.clone()].chars() {
@@ -161,6 +881,18 @@ impl<'a, B: Brush, T: TextSource> RangedBuilder<'a, B, T> {
                 char_index += 1;
             }
         }
+        lcx.resolve_levels(text);
+        lcx.tailor_line_breaks(text);
+        layout.data.has_bidi = !lcx.levels.is_empty();
+        layout
+            .data
+            .paragraph_levels
+            .extend_from_slice(&lcx.paragraph_levels);
+        #[cfg(feature = "profiling")]
+        let start = {
+            layout.data.profile.analysis = lcx.analysis_time + start.elapsed();
+            std::time::Instant::now()
+        };
         use super::layout::{Decoration, Style};
         fn conv_deco<B: Brush>(
             deco: &ResolvedDecoration<B>,
@@ -176,13 +908,26 @@ impl<'a, B: Brush, T: TextSource> RangedBuilder<'a, B, T> {
                 None
             }
         }
-        layout.data.styles.extend(lcx.styles.iter().map(|s| {
-            let s = &s.style;
+        layout.data.styles.extend(lcx.styles.iter().map(|r| {
+            let s = &r.style;
             Style {
                 brush: s.brush.clone(),
+                font_palette: s.font_palette.clone(),
+                font_palette_overrides: s.font_palette_overrides.clone(),
                 underline: conv_deco(&s.underline, &s.brush),
                 strikethrough: conv_deco(&s.strikethrough, &s.brush),
+                text_shadow: s.text_shadow.clone(),
+                user_data: s.user_data,
+                font_size: s.font_size,
+                font_weight: s.font_weight,
+                font_style: s.font_style,
+                font_stretch: s.font_stretch,
+                text_range: r.range.clone(),
                 line_height: s.line_height,
+                wrap: s.wrap,
+                overflow_wrap: s.overflow_wrap,
+                hanging_punctuation: s.hanging_punctuation,
+                optical_alignment: s.optical_alignment,
             }
         }));
         super::shape::shape_text(
@@ -190,12 +935,33 @@ impl<'a, B: Brush, T: TextSource> RangedBuilder<'a, B, T> {
             &mut fcx,
             &lcx.styles,
             &lcx.info,
-            lcx.bidi.levels(),
+            &lcx.levels,
             &mut lcx.scx,
+            lcx.diagnostics,
+            lcx.mark_handling,
+            lcx.control_chars,
             text,
             layout,
         );
-        layout.data.finish();
+        layout.data.finish(lcx.spacing_floor);
+        if let Some((adjuster, across_runs)) = &lcx.advance_adjuster {
+            if !is_empty {
+                layout.data.adjust_advances(&**adjuster, *across_runs);
+            }
+        }
+        if lcx.coalesce_runs && !is_empty {
+            layout.data.coalesce_runs();
+        }
+        if lcx
+            .styles
+            .iter()
+            .any(|s| s.style.hanging_punctuation != Default::default())
+        {
+            layout.data.mark_hanging_punctuation(text);
+        }
+        if lcx.reveal_whitespace && text.contains('\t') {
+            layout.data.mark_tabs(text);
+        }
         if is_empty {
             layout.data.text_len = 0;
             let run = &mut layout.data.runs[0];
@@ -203,6 +969,13 @@ impl<'a, B: Brush, T: TextSource> RangedBuilder<'a, B, T> {
             run.text_range.end = 0;
             layout.data.clusters.clear();
         }
+        #[cfg(feature = "profiling")]
+        {
+            let data = &mut layout.data;
+            data.profile.shaping = start.elapsed();
+            data.profile.runs = data.runs.len();
+            data.profile.glyphs = data.clusters.iter().map(|c| c.glyph_count()).sum();
+        }
     }
 
     pub fn build(&mut self) -> Layout<B> {
@@ -212,6 +985,16 @@ impl<'a, B: Brush, T: TextSource> RangedBuilder<'a, B, T> {
     }
 }
 
+/// Adapter that feeds formatted output to a hasher.
+struct HashWriter<'a, H: Hasher>(&'a mut H);
+
+impl<'a, H: Hasher> Write for HashWriter<'a, H> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.0.write(s.as_bytes());
+        Ok(())
+    }
+}
+
 #[doc(hidden)]
 pub trait TextSource {
     fn as_str(&self) -> &str;
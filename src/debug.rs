@@ -0,0 +1,34 @@
+//! Introspection into the inputs of the layout algorithms.
+
+use swash::text::Codepoint;
+
+pub use swash::text::LineBreak as LineBreakClass;
+
+/// Returns the UAX #14 line break class of each character in the text.
+pub fn line_break_classes(text: &str) -> Vec<(char, LineBreakClass)> {
+    text.chars().map(|ch| (ch, ch.line_break())).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classes() {
+        use LineBreakClass::*;
+        let classes = line_break_classes("a-1 (\u{5B57}\u{301}\u{A0})");
+        let classes: Vec<_> = classes.into_iter().map(|(_, class)| class).collect();
+        assert_eq!(classes, vec![AL, HY, NU, SP, OP, ID, CM, GL, CP]);
+    }
+
+    #[test]
+    fn chars_are_kept() {
+        let text = "x\u{200B}y";
+        let chars: Vec<_> = line_break_classes(text)
+            .into_iter()
+            .map(|(ch, _)| ch)
+            .collect();
+        assert_eq!(chars, text.chars().collect::<Vec<_>>());
+        assert_eq!(line_break_classes(text)[1].1, LineBreakClass::ZW);
+    }
+}
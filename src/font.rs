@@ -1,21 +1,25 @@
 //! Font management.
 
 use super::fount::{
-    FamilyId, FontContext as FountContext, FontData, FontId, GenericFamily, Library, Locale,
-    SourceId,
+    FamilyId, FontContext as FountContext, FontData, GenericFamily, Library, Locale, SourceId,
 };
+use super::style::{FontFamily, FontVariation};
 use std::collections::HashMap;
+use std::ops::Range;
 use swash::proxy::CharmapProxy;
 use swash::text::cluster::*;
 use swash::text::Script;
 use swash::{Attributes, CacheKey, FontRef, Synthesis};
 
+pub use super::fount::FontId;
+
 // Make this configurable?
 const RETAINED_SOURCE_COUNT: usize = 12;
 
 /// Shared handle to a font.
 #[derive(Clone)]
 pub struct Font {
+    id: FontId,
     data: FontData,
     index: u32,
     offset: u32,
@@ -23,6 +27,11 @@ pub struct Font {
 }
 
 impl Font {
+    /// Returns the identifier of the font in its font context.
+    pub fn id(&self) -> FontId {
+        self.id
+    }
+
     /// Returns a reference to the font.
     pub fn as_ref(&self) -> FontRef {
         FontRef {
@@ -40,6 +49,55 @@ impl Font {
     pub fn index(&self) -> u32 {
         self.index
     }
+
+    /// Returns metrics for the font at the specified size, read directly
+    /// from the font tables without shaping any text.
+    pub fn metrics(&self, size: f32) -> FontMetrics {
+        let font = self.as_ref();
+        let metrics = font.metrics(&[]).scale(size);
+        let space_advance = match font.charmap().map(' ') {
+            0 => metrics.average_width,
+            glyph_id => font.glyph_metrics(&[]).scale(size).advance_width(glyph_id),
+        };
+        FontMetrics {
+            size,
+            ascent: metrics.ascent,
+            descent: metrics.descent,
+            leading: metrics.leading,
+            cap_height: metrics.cap_height,
+            x_height: metrics.x_height,
+            average_width: metrics.average_width,
+            max_width: metrics.max_width,
+            space_advance,
+            is_monospace: metrics.is_monospace,
+        }
+    }
+}
+
+/// Metrics information for a font at a particular size.
+#[derive(Copy, Clone, Default, Debug)]
+pub struct FontMetrics {
+    /// Font size (the size of the em square).
+    pub size: f32,
+    /// Typographic ascent.
+    pub ascent: f32,
+    /// Typographic descent.
+    pub descent: f32,
+    /// Typographic leading.
+    pub leading: f32,
+    /// Distance from the baseline to the top of a typical capital letter.
+    pub cap_height: f32,
+    /// Distance from the baseline to the top of a typical lowercase letter.
+    pub x_height: f32,
+    /// Average advance of all characters in the font.
+    pub average_width: f32,
+    /// Maximum advance of all characters in the font.
+    pub max_width: f32,
+    /// Advance of the space character. This is the average advance if the
+    /// font does not map a space.
+    pub space_advance: f32,
+    /// True if the font is monospace.
+    pub is_monospace: bool,
 }
 
 impl PartialEq for Font {
@@ -48,6 +106,21 @@ impl PartialEq for Font {
     }
 }
 
+/// Font selected for a range of text without shaping.
+///
+/// See [`RangedBuilder::required_fonts`](crate::context::RangedBuilder::required_fonts).
+#[derive(Clone)]
+pub struct FontRequest {
+    /// Range of source text mapped to the font.
+    pub text_range: Range<usize>,
+    /// Name of the family containing the font.
+    pub family: String,
+    /// Attributes of the font.
+    pub attributes: Attributes,
+    /// The font.
+    pub font: Font,
+}
+
 /// Context for font selection and fallback.
 #[derive(Clone)]
 pub struct FontContext {
@@ -66,6 +139,24 @@ impl FontContext {
         self.cache.context.family_by_name(name).is_some()
     }
 
+    /// Returns the font in the specified family that most closely matches
+    /// the given attributes.
+    pub fn query_font(&mut self, family: FontFamily, attributes: Attributes) -> Option<Font> {
+        let cache = &mut self.cache;
+        let family_id = match family {
+            FontFamily::Named(name) => cache.context.family_by_name(name)?.id(),
+            FontFamily::Generic(family) => *cache.context.generic_families(family).first()?,
+        };
+        let font_id = cache.context.family(family_id)?.query(attributes)?;
+        self.font(font_id)
+    }
+
+    /// Returns the font with the specified identifier.
+    pub fn font(&mut self, id: FontId) -> Option<Font> {
+        let cache = &mut self.cache;
+        cache.sources.get(&cache.context, id).map(|(font, _)| font)
+    }
+
     /// Registers the fonts in the specified font data. Returns the family name
     /// for the first registerd font.
     ///
@@ -77,6 +168,69 @@ impl FontContext {
         let family = self.cache.context.family(*first_family)?;
         Some(family.name().to_owned())
     }
+
+    /// Registers the fonts in the specified font data with a set of default
+    /// variation settings. Returns the family name for the first registered
+    /// font.
+    ///
+    /// The defaults are applied whenever one of the registered fonts is
+    /// selected during layout. Variation settings specified with
+    /// [`StyleProperty::FontVariations`](crate::style::StyleProperty::FontVariations)
+    /// and variations synthesized to match the requested attributes take
+    /// precedence over the defaults. The defaults are carried in the
+    /// synthesis of each run, which holds at most four variation settings.
+    pub fn register_fonts_with_variations(
+        &mut self,
+        data: Vec<u8>,
+        variations: &[FontVariation],
+    ) -> Option<String> {
+        let reg = self.cache.context.register_fonts(data)?;
+        for font_id in &reg.fonts {
+            if let Some(font) = self.cache.context.font(*font_id) {
+                self.cache
+                    .default_variations
+                    .insert(font.cache_key(), variations.to_vec());
+            }
+        }
+        let first_family = reg.families.first()?;
+        let family = self.cache.context.family(*first_family)?;
+        Some(family.name().to_owned())
+    }
+
+    /// Returns the fonts in the family with the specified name, such as to
+    /// restrict fallback to the fonts of a registered family.
+    pub fn family_fonts(&self, name: &str) -> Vec<FontId> {
+        self.cache
+            .context
+            .family_by_name(name)
+            .map(|family| family.fonts().collect())
+            .unwrap_or_default()
+    }
+
+    /// Restricts font fallback to the specified fonts, in order of
+    /// preference, or removes the restriction if `None`.
+    ///
+    /// When restricted, characters that are not supported by the selected
+    /// font stack are only mapped to the given fonts and the system
+    /// fallback and emoji fonts are never consulted. The fonts are used as
+    /// given, so a fallback font that does not match the requested weight
+    /// or style is synthesized as allowed by the style. Characters that no
+    /// font supports are shaped with the `.notdef` glyph of the first font
+    /// in the stack or, failing that, of the first restricted font. This
+    /// makes layout independent of the fonts installed on the system as
+    /// long as the font stack only names registered families. Identifiers
+    /// that do not match a font in the context are ignored.
+    pub fn set_fallback_restriction(&mut self, fonts: Option<Vec<FontId>>) {
+        let cache = &mut self.cache;
+        cache.fallback_restriction = fonts.map(|fonts| {
+            fonts
+                .into_iter()
+                .filter(|id| cache.context.font(*id).is_some())
+                .collect()
+        });
+        cache.fallback_params = (Script::Unknown, None, Attributes::default());
+        cache.fallback_fonts.clear();
+    }
 }
 
 #[derive(Clone)]
@@ -87,8 +241,10 @@ pub(crate) struct FontCache {
     selected_fonts: Vec<CachedFont>,
     fallback_params: (Script, Option<Locale>, Attributes),
     fallback_fonts: Vec<CachedFont>,
+    fallback_restriction: Option<Vec<FontId>>,
     emoji_font: Option<CachedFont>,
     attrs: Attributes,
+    default_variations: HashMap<CacheKey, Vec<FontVariation>>,
 }
 
 impl FontCache {
@@ -100,8 +256,10 @@ impl FontCache {
             selected_fonts: vec![],
             fallback_params: (Script::Unknown, None, Attributes::default()),
             fallback_fonts: vec![],
+            fallback_restriction: None,
             emoji_font: None,
             attrs: Attributes::default(),
+            default_variations: HashMap::new(),
         }
     }
 
@@ -136,18 +294,47 @@ impl FontCache {
             self.fallback_params = (script, locale, attrs);
             self.fallback_fonts.clear();
             let context = &self.context;
-            let fallback_families = context.fallback_families(script, locale);
-            self.fallback_fonts.extend(
-                fallback_families
-                    .iter()
-                    .filter_map(|id| context.family(*id))
-                    .filter_map(|family| family.query(attrs))
-                    .map(CachedFont::new),
-            );
+            match &self.fallback_restriction {
+                Some(fonts) => self
+                    .fallback_fonts
+                    .extend(fonts.iter().copied().map(CachedFont::new)),
+                None => self.fallback_fonts.extend(
+                    context
+                        .fallback_families(script, locale)
+                        .iter()
+                        .filter_map(|id| context.family(*id))
+                        .filter_map(|family| family.query(attrs))
+                        .map(CachedFont::new),
+                ),
+            }
             self.attrs = attrs;
         }
     }
 
+    /// Returns the default variation settings registered for the font.
+    pub fn default_variations(&self, font: &Font) -> &[FontVariation] {
+        self.default_variations
+            .get(&font.key)
+            .map(|variations| variations.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Returns a request for the specified font covering the given range.
+    pub fn request(&self, font: &Font, text_range: Range<usize>) -> FontRequest {
+        let entry = self.context.font(font.id);
+        let family = entry
+            .as_ref()
+            .and_then(|entry| self.context.family(entry.family()))
+            .map(|family| family.name().to_owned())
+            .unwrap_or_default();
+        FontRequest {
+            text_range,
+            family,
+            attributes: entry.map(|entry| entry.attributes()).unwrap_or_default(),
+            font: font.clone(),
+        }
+    }
+
     pub fn map_cluster(&mut self, cluster: &mut CharCluster) -> Option<(Font, Synthesis)> {
         let mut best = None;
         if map_cluster(
@@ -159,7 +346,7 @@ impl FontCache {
         ) {
             return best.map(|(font, attrs)| (font, attrs.synthesize(self.attrs)));
         }
-        if cluster.info().is_emoji() {
+        if cluster.info().is_emoji() && self.fallback_restriction.is_none() {
             if self.emoji_font.is_none() {
                 self.emoji_font = self
                     .context
@@ -189,6 +376,16 @@ impl FontCache {
             cluster,
             &mut best,
         );
+        if best.is_none() {
+            // No font supports any part of the cluster. Select the first
+            // available font so the cluster is shaped with `.notdef` rather
+            // than terminating the shaping of the remaining text.
+            best = self
+                .selected_fonts
+                .iter()
+                .chain(&self.fallback_fonts)
+                .find_map(|font| Some((font.font.as_ref()?.0.clone(), font.attrs)));
+        }
         best.map(|(font, attrs)| (font, attrs.synthesize(self.attrs)))
     }
 }
@@ -250,6 +447,7 @@ impl SourceCache {
         let offset = font_ref.offset;
         Some((
             Font {
+                id,
                 data,
                 index: entry.index(),
                 offset,
@@ -12,6 +12,9 @@ use swash::{Attributes, CacheKey, FontDataRef, FontRef, Stretch, StringId};
 pub struct ScannedFont {
     pub name: String,
     pub lowercase_name: String,
+    /// Name of another family that the font is also a member of, or empty.
+    pub alias: String,
+    pub lowercase_alias: String,
     pub index: u32,
     pub attributes: Attributes,
     pub cache_key: CacheKey,
@@ -44,6 +47,8 @@ impl FontScanner {
     ) -> Option<()> {
         self.font.name.clear();
         self.font.lowercase_name.clear();
+        self.font.alias.clear();
+        self.font.lowercase_alias.clear();
         self.font.index = index;
         self.font.attributes = Attributes::default();
         self.font.scripts.clear();
@@ -102,6 +107,22 @@ impl FontScanner {
             };
             self.font.scripts.insert(script);
         }
+        // Static fonts with a non-normal stretch are usually named with the
+        // width in the standard family name. Also add them to the
+        // typographic family so that the stretch attribute can select them.
+        if !is_var && self.font.attributes.stretch() != Stretch::NORMAL {
+            if let Some(name) = strings.find_by_id(StringId::TypographicFamily, Some("en")) {
+                self.font.alias.extend(name.chars());
+            } else if let Some(name) = strings.find_by_id(StringId::TypographicFamily, None) {
+                self.font.alias.extend(name.chars());
+            }
+            if self.font.alias == self.font.name {
+                self.font.alias.clear();
+            }
+            self.font
+                .lowercase_alias
+                .extend(self.font.alias.chars().flat_map(|ch| ch.to_lowercase()));
+        }
         f(&self.font);
         Some(())
     }
@@ -125,30 +146,13 @@ impl CollectionData {
             } else {
                 return;
             };
-            let family_id =
-                if let Some(family_id) = self.family_map.get(font.lowercase_name.as_str()) {
-                    *family_id
-                } else {
-                    if let Some(family_id) = FamilyId::alloc(self.families.len(), is_user) {
-                        let family = FamilyData {
-                            name: font.name.as_str().into(),
-                            has_stretch: false,
-                            fonts: Vec::new(),
-                        };
-                        self.families.push(Arc::new(family));
-                        self.family_map
-                            .insert(font.lowercase_name.as_str().into(), family_id);
-                        family_id
-                    } else {
-                        return;
-                    }
-                };
-            let family = Arc::make_mut(self.families.get_mut(family_id.to_usize()).unwrap());
-            let (stretch, weight, style) = font.attributes.parts();
-            for font in &family.fonts {
-                if font.1 == stretch && font.2 == weight && font.3 == style {
-                    return;
-                }
+            let family_id = match self.get_or_add_family(&font.name, &font.lowercase_name, is_user)
+            {
+                Some(family_id) => family_id,
+                None => return,
+            };
+            if self.has_font(family_id, font.attributes) {
+                return;
             }
             if !added_source {
                 self.sources.push(SourceData {
@@ -157,69 +161,81 @@ impl CollectionData {
                 });
                 added_source = true;
             }
-            if stretch != Stretch::NORMAL {
-                family.has_stretch = true;
-            }
-            match family.fonts.binary_search_by(|probe| probe.2.cmp(&weight)) {
-                Ok(index) | Err(index) => family
-                    .fonts
-                    .insert(index, (font_id, stretch, weight, style)),
+            self.add_font_to_family(family_id, font_id, font.attributes);
+            // The font is listed in both families under a single identifier.
+            let alias_id = if font.alias.is_empty() {
+                None
+            } else {
+                self.get_or_add_family(&font.alias, &font.lowercase_alias, is_user)
+                    .filter(|alias_id| !self.has_font(*alias_id, font.attributes))
+            };
+            if let Some(alias_id) = alias_id {
+                self.add_font_to_family(alias_id, font_id, font.attributes);
             }
             if let Some(reg) = reg.as_mut() {
-                if !reg.families.contains(&family_id) {
-                    reg.families.push(family_id);
+                for family_id in core::iter::once(family_id).chain(alias_id) {
+                    if !reg.families.contains(&family_id) {
+                        reg.families.push(family_id);
+                    }
                 }
                 reg.fonts.push(font_id);
             }
             if let Some(fallback) = fallback.as_mut() {
-                for (script, cjk) in &font.scripts {
-                    if *script == Script::Han {
-                        let entry = &mut fallback.cjk_families[*cjk as usize];
+                let families = core::iter::once((family_id, &font.lowercase_name))
+                    .chain(alias_id.map(|alias_id| (alias_id, &font.lowercase_alias)));
+                for (family_id, lowercase_name) in families {
+                    for (script, cjk) in &font.scripts {
+                        if *script == Script::Han {
+                            let entry = &mut fallback.cjk_families[*cjk as usize];
+                            if !entry.contains(&family_id) {
+                                entry.push(family_id);
+                            }
+                        } else {
+                            let tag = super::script_tags::script_tag(*script);
+                            let entry = fallback.script_fallbacks.entry(tag).or_default();
+                            if !entry.contains(&family_id) {
+                                entry.push(family_id);
+                            }
+                        }
+                    }
+                    if lowercase_name.contains("serif") {
+                        let entry = &mut fallback.generic_families[GenericFamily::Serif as usize];
                         if !entry.contains(&family_id) {
                             entry.push(family_id);
                         }
-                    } else {
-                        let tag = super::script_tags::script_tag(*script);
-                        let entry = fallback.script_fallbacks.entry(tag).or_default();
+                    }
+                    if lowercase_name.contains("sans") {
+                        let entry =
+                            &mut fallback.generic_families[GenericFamily::SansSerif as usize];
                         if !entry.contains(&family_id) {
                             entry.push(family_id);
                         }
                     }
-                }
-                if font.lowercase_name.contains("serif") {
-                    let entry = &mut fallback.generic_families[GenericFamily::Serif as usize];
-                    if !entry.contains(&family_id) {
-                        entry.push(family_id);
-                    }
-                }
-                if font.lowercase_name.contains("sans") {
-                    let entry = &mut fallback.generic_families[GenericFamily::SansSerif as usize];
-                    if !entry.contains(&family_id) {
-                        entry.push(family_id);
-                    }
-                }
-                if font.lowercase_name.contains("mono") {
-                    let entry = &mut fallback.generic_families[GenericFamily::Monospace as usize];
-                    if !entry.contains(&family_id) {
-                        entry.push(family_id);
+                    if lowercase_name.contains("mono") {
+                        let entry =
+                            &mut fallback.generic_families[GenericFamily::Monospace as usize];
+                        if !entry.contains(&family_id) {
+                            entry.push(family_id);
+                        }
                     }
-                }
-                if font.lowercase_name.contains("ui") {
-                    let entry = &mut fallback.generic_families[GenericFamily::SystemUi as usize];
-                    if !entry.contains(&family_id) {
-                        entry.push(family_id);
+                    if lowercase_name.contains("ui") {
+                        let entry =
+                            &mut fallback.generic_families[GenericFamily::SystemUi as usize];
+                        if !entry.contains(&family_id) {
+                            entry.push(family_id);
+                        }
                     }
-                }
-                if font.lowercase_name.contains("cursive") {
-                    let entry = &mut fallback.generic_families[GenericFamily::Cursive as usize];
-                    if !entry.contains(&family_id) {
-                        entry.push(family_id);
+                    if lowercase_name.contains("cursive") {
+                        let entry = &mut fallback.generic_families[GenericFamily::Cursive as usize];
+                        if !entry.contains(&family_id) {
+                            entry.push(family_id);
+                        }
                     }
-                }
-                if font.lowercase_name.contains("emoji") {
-                    let entry = &mut fallback.generic_families[GenericFamily::Emoji as usize];
-                    if !entry.contains(&family_id) {
-                        entry.push(family_id);
+                    if lowercase_name.contains("emoji") {
+                        let entry = &mut fallback.generic_families[GenericFamily::Emoji as usize];
+                        if !entry.contains(&family_id) {
+                            entry.push(family_id);
+                        }
                     }
                 }
             }
@@ -234,6 +250,51 @@ impl CollectionData {
         });
         Some(count)
     }
+
+    /// Returns the family with the specified name, adding it if it does
+    /// not exist.
+    fn get_or_add_family(
+        &mut self,
+        name: &str,
+        lowercase_name: &str,
+        is_user: bool,
+    ) -> Option<FamilyId> {
+        if let Some(family_id) = self.family_map.get(lowercase_name) {
+            return Some(*family_id);
+        }
+        let family_id = FamilyId::alloc(self.families.len(), is_user)?;
+        let family = FamilyData {
+            name: name.into(),
+            has_stretch: false,
+            fonts: Vec::new(),
+        };
+        self.families.push(Arc::new(family));
+        self.family_map.insert(lowercase_name.into(), family_id);
+        Some(family_id)
+    }
+
+    /// Returns true if the family already has a font with the specified
+    /// attributes.
+    fn has_font(&self, family_id: FamilyId, attributes: Attributes) -> bool {
+        let (stretch, weight, style) = attributes.parts();
+        self.families[family_id.to_usize()]
+            .fonts
+            .iter()
+            .any(|font| font.1 == stretch && font.2 == weight && font.3 == style)
+    }
+
+    fn add_font_to_family(&mut self, family_id: FamilyId, font_id: FontId, attributes: Attributes) {
+        let family = Arc::make_mut(self.families.get_mut(family_id.to_usize()).unwrap());
+        let (stretch, weight, style) = attributes.parts();
+        if stretch != Stretch::NORMAL {
+            family.has_stretch = true;
+        }
+        match family.fonts.binary_search_by(|probe| probe.2.cmp(&weight)) {
+            Ok(index) | Err(index) => family
+                .fonts
+                .insert(index, (font_id, stretch, weight, style)),
+        }
+    }
 }
 
 pub(crate) fn scan_path(
@@ -0,0 +1,144 @@
+//! Baselines from the font `BASE` table.
+
+use super::*;
+use swash::{tag_from_bytes, FontRef, Tag};
+
+/// Baseline used to align runs of text in different scripts.
+///
+/// <https://developer.mozilla.org/en-US/docs/Web/CSS/dominant-baseline>
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub enum Baseline {
+    /// The baseline of Latin and most other alphabetic scripts.
+    #[default]
+    Alphabetic,
+    /// The hanging baseline of scripts such as Devanagari and Tibetan.
+    Hanging,
+    /// The bottom of the ideographic em box.
+    Ideographic,
+    /// The top of the ideographic em box.
+    IdeographicTop,
+    /// The center of the ideographic em box.
+    Central,
+    /// The baseline of mathematical characters.
+    Mathematical,
+}
+
+impl<'a, B: Brush> Run<'a, B> {
+    /// Returns the offset of the specified baseline above the alphabetic
+    /// baseline of the run, in layout units.
+    ///
+    /// The offset is read from the `BASE` table of the font for the script
+    /// of the run, falling back to the default script of the table. If the
+    /// font does not define the baseline, it is approximated from the
+    /// ascent and descent of the run.
+    ///
+    /// Runs of mixed scripts are aligned on a dominant baseline by moving
+    /// each run down by the difference between its offset and the offset
+    /// of a reference run, such as the first run of the line.
+    pub fn baseline_offset(&self, dominant: Baseline) -> f32 {
+        let font = self.font().as_ref();
+        let script = self.data.script.to_opentype();
+        let scale = self.data.font_size / font.metrics(&[]).units_per_em as f32;
+        let coord = |baseline| base_coord(&font, script, baseline).map(|c| c as f32 * scale);
+        let metrics = &self.data.metrics;
+        match dominant {
+            Baseline::Alphabetic => coord(Baseline::Alphabetic).unwrap_or(0.),
+            Baseline::Hanging => coord(Baseline::Hanging).unwrap_or(metrics.ascent * 0.8),
+            Baseline::Ideographic => coord(Baseline::Ideographic).unwrap_or(-metrics.descent),
+            Baseline::IdeographicTop => coord(Baseline::IdeographicTop).unwrap_or(metrics.ascent),
+            Baseline::Central => {
+                match (
+                    coord(Baseline::Ideographic),
+                    coord(Baseline::IdeographicTop),
+                ) {
+                    (Some(bottom), Some(top)) => (bottom + top) * 0.5,
+                    _ => (metrics.ascent - metrics.descent) * 0.5,
+                }
+            }
+            Baseline::Mathematical => coord(Baseline::Mathematical).unwrap_or(metrics.ascent * 0.5),
+        }
+    }
+}
+
+const BASE: Tag = tag_from_bytes(b"BASE");
+const DFLT: Tag = tag_from_bytes(b"DFLT");
+
+/// Returns the tag of the baseline in the `BASE` table, or `None` for the
+/// central baseline, which is derived from the ideographic baselines.
+fn baseline_tag(baseline: Baseline) -> Option<Tag> {
+    Some(tag_from_bytes(match baseline {
+        Baseline::Alphabetic => b"romn",
+        Baseline::Hanging => b"hang",
+        Baseline::Ideographic => b"ideo",
+        Baseline::IdeographicTop => b"idtp",
+        Baseline::Mathematical => b"math",
+        Baseline::Central => return None,
+    }))
+}
+
+/// Returns the coordinate of the baseline for the script from the
+/// horizontal axis of the `BASE` table, in font units.
+fn base_coord(font: &FontRef, script: Tag, baseline: Baseline) -> Option<i16> {
+    let tag = baseline_tag(baseline)?;
+    let data = font.table(BASE)?;
+    let axis = read_u16(data, 4)? as usize;
+    if axis == 0 {
+        return None;
+    }
+    let tag_list = axis + read_u16(data, axis)? as usize;
+    let script_list = axis + read_u16(data, axis + 2)? as usize;
+    let tag_count = read_u16(data, tag_list)? as usize;
+    let tag_index = (0..tag_count).find(|i| read_u32(data, tag_list + 2 + i * 4) == Some(tag))?;
+    let script_count = read_u16(data, script_list)? as usize;
+    let find_script = |script| {
+        (0..script_count).find_map(|i| {
+            let record = script_list + 2 + i * 6;
+            if read_u32(data, record)? != script {
+                return None;
+            }
+            Some(script_list + read_u16(data, record + 4)? as usize)
+        })
+    };
+    let base_script = find_script(script)
+        .or_else(|| legacy_script_tag(script).and_then(find_script))
+        .or_else(|| find_script(DFLT))?;
+    let values = read_u16(data, base_script)? as usize;
+    if values == 0 {
+        return None;
+    }
+    let values = base_script + values;
+    if tag_index >= read_u16(data, values + 2)? as usize {
+        return None;
+    }
+    let coord = values + read_u16(data, values + 4 + tag_index * 2)? as usize;
+    // All formats of a base coordinate begin with the coordinate.
+    read_u16(data, coord + 2).map(|c| c as i16)
+}
+
+/// Returns the original tag of an Indic script with a second version tag,
+/// which fonts may use in the `BASE` table instead.
+fn legacy_script_tag(tag: Tag) -> Option<Tag> {
+    Some(tag_from_bytes(match &tag.to_be_bytes() {
+        b"dev2" => b"deva",
+        b"bng2" => b"beng",
+        b"gjr2" => b"gujr",
+        b"gur2" => b"guru",
+        b"knd2" => b"knda",
+        b"mlm2" => b"mlym",
+        b"ory2" => b"orya",
+        b"tml2" => b"taml",
+        b"tel2" => b"telu",
+        b"mym2" => b"mymr",
+        _ => return None,
+    }))
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
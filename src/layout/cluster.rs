@@ -1,4 +1,5 @@
 use super::*;
+use swash::text::cluster::Whitespace;
 
 impl<'a, B: Brush> Cluster<'a, B> {
     /// Returns the range of text that defines the cluster.
@@ -6,11 +7,18 @@ impl<'a, B: Brush> Cluster<'a, B> {
         self.data.text_range(self.run.data)
     }
 
-    /// Returns the advance of the cluster.
+    /// Returns the advance of the cluster, including any extra space added
+    /// by justification.
     pub fn advance(&self) -> f32 {
         self.data.advance
     }
 
+    /// Returns the natural advance of the cluster, excluding any extra
+    /// space added by justification.
+    pub fn natural_advance(&self) -> f32 {
+        self.data.advance - self.data.justification
+    }
+
     /// Returns true if the cluster is the beginning of a ligature.
     pub fn is_ligature_start(&self) -> bool {
         self.data.is_ligature_start()
@@ -36,18 +44,92 @@ impl<'a, B: Brush> Cluster<'a, B> {
         self.data.info.boundary() == Boundary::Mandatory
     }
 
+    /// Returns true if a line may be broken after the cluster. This is false
+    /// for the last cluster in the layout and inside no-wrap ranges, and
+    /// true after every space in [`Wrap::BreakSpaces`] ranges.
+    ///
+    /// [`Wrap::BreakSpaces`]: crate::style::Wrap::BreakSpaces
+    pub fn is_line_break_opportunity_after(&self) -> bool {
+        self.run.layout.can_break_after(self.index)
+    }
+
+    /// Returns true if a mandatory line break follows the cluster, for
+    /// example, when the cluster is a newline character.
+    pub fn is_mandatory_break_after(&self) -> bool {
+        self.run
+            .layout
+            .clusters
+            .get(self.index + 1)
+            .map(|next| next.info.boundary() == Boundary::Mandatory)
+            .unwrap_or(false)
+    }
+
     /// Returns true if the cluster is a space or no-break space.
     pub fn is_space_or_nbsp(&self) -> bool {
         self.data.info.whitespace().is_space_or_nbsp()
     }
 
+    /// Returns true if the cluster is whitespace of any kind.
+    pub fn is_whitespace(&self) -> bool {
+        self.data.info.is_whitespace()
+    }
+
+    /// Returns the marker to draw for the cluster if whitespace is revealed
+    /// (see [`LayoutContext::set_reveal_whitespace`]) and the cluster is a
+    /// space, tab or line break.
+    ///
+    /// The marker should be drawn within the advance of the cluster, which
+    /// is unchanged when whitespace is revealed, so it spans the full width
+    /// of a tab.
+    ///
+    /// [`LayoutContext::set_reveal_whitespace`]: crate::LayoutContext::set_reveal_whitespace
+    pub fn whitespace_marker(&self) -> Option<WhitespaceMarker> {
+        if !self.run.layout.reveal_whitespace {
+            return None;
+        }
+        if self.data.has_flag(ClusterData::TAB) {
+            return Some(WhitespaceMarker::Tab);
+        }
+        match self.data.info.whitespace() {
+            Whitespace::Space => Some(WhitespaceMarker::Space),
+            Whitespace::NoBreakSpace => Some(WhitespaceMarker::NoBreakSpace),
+            Whitespace::Tab => Some(WhitespaceMarker::Tab),
+            Whitespace::Newline => Some(WhitespaceMarker::Newline),
+            _ => None,
+        }
+    }
+
+    /// Returns the range of the glyphs of the cluster in the sequence of
+    /// glyphs of its run, as produced by iterating the glyphs of each of
+    /// the clusters returned by [`Run::clusters`] in logical order.
+    ///
+    /// A cluster may produce several glyphs, such as a base with a
+    /// reordered vowel sign, and all of them are covered by the range even
+    /// if shaping reorders them. The continuation clusters of a ligature
+    /// have an empty range, as their glyph belongs to the first cluster of
+    /// the ligature.
+    pub fn glyph_range(&self) -> Range<usize> {
+        let cluster_start = self.run.data.cluster_range.start;
+        let start = self.run.layout.clusters[cluster_start..self.index]
+            .iter()
+            .map(|cluster| cluster.glyph_count())
+            .sum();
+        start..start + self.data.glyph_count()
+    }
+
     /// Returns an iterator over the glyphs in the cluster.
     pub fn glyphs(&self) -> impl Iterator<Item = Glyph> + 'a + Clone {
+        let (before, after) = if self.run.layout.rubies.is_empty() {
+            (0., 0.)
+        } else {
+            self.run.layout.ruby_padding(self.run.data, self.index)
+        };
         if self.data.glyph_len == 0xFF {
             GlyphIter::Single(Some(Glyph {
                 id: self.data.glyph_offset,
                 style_index: self.data.style_index,
-                x: 0.,
+                source_offset: 0,
+                x: before,
                 y: 0.,
                 advance: self.data.advance,
             }))
@@ -55,6 +137,8 @@ impl<'a, B: Brush> Cluster<'a, B> {
             let start = self.run.data.glyph_start + self.data.glyph_offset as usize;
             GlyphIter::Slice(
                 self.run.layout.glyphs[start..start + self.data.glyph_len as usize].iter(),
+                before,
+                after,
             )
         }
     }
@@ -67,7 +151,8 @@ impl<'a, B: Brush> Cluster<'a, B> {
 #[derive(Clone)]
 enum GlyphIter<'a> {
     Single(Option<Glyph>),
-    Slice(core::slice::Iter<'a, Glyph>),
+    /// Glyphs with space added before the first and after the last glyph.
+    Slice(core::slice::Iter<'a, Glyph>, f32, f32),
 }
 
 impl<'a> Iterator for GlyphIter<'a> {
@@ -76,8 +161,14 @@ impl<'a> Iterator for GlyphIter<'a> {
     fn next(&mut self) -> Option<Self::Item> {
         match self {
             Self::Single(glyph) => glyph.take(),
-            Self::Slice(iter) => {
-                let glyph = *iter.next()?;
+            Self::Slice(iter, before, after) => {
+                let mut glyph = *iter.next()?;
+                glyph.x += *before;
+                glyph.advance += *before;
+                *before = 0.;
+                if iter.len() == 0 {
+                    glyph.advance += *after;
+                }
                 Some(glyph)
             }
         }
@@ -1,6 +1,8 @@
 //! Hit testing.
 
 use super::*;
+use peniko::kurbo::Rect;
+use swash::scale::ScaleContext;
 
 /// Represents a position within a layout.
 #[derive(Copy, Clone, Default, Debug)]
@@ -35,7 +37,7 @@ impl Cursor {
         let last_line = layout.data.lines.len().saturating_sub(1);
         for (line_index, line) in layout.lines().enumerate() {
             let line_metrics = line.metrics();
-            if y > line_metrics.baseline + line_metrics.descent + line_metrics.leading * 0.5 {
+            if y > line_metrics.extent().1 {
                 if line_index != last_line {
                     continue;
                 }
@@ -60,23 +62,30 @@ impl Cursor {
                     } else {
                         cluster_index
                     };
+                    // The logical start of a cluster is on its right
+                    // edge in a right-to-left run.
+                    let (left, right) = if result.is_rtl {
+                        (range.end, range.start)
+                    } else {
+                        (range.start, range.end)
+                    };
                     if x >= last_edge {
                         let advance = cluster.advance();
                         let next_edge = last_edge + advance;
                         result.offset = next_edge;
-                        result.insert_point = range.end;
+                        result.insert_point = right;
                         if x >= next_edge {
                             last_edge = next_edge;
                             continue;
                         }
                         result.advance = advance;
                         if x <= (last_edge + next_edge) * 0.5 {
-                            result.insert_point = range.start;
+                            result.insert_point = left;
                             result.offset = last_edge;
                         }
                     } else {
                         result.is_inside = false;
-                        result.insert_point = range.start;
+                        result.insert_point = left;
                         result.offset = line_metrics.offset;
                     }
                     return result;
@@ -133,7 +142,11 @@ impl Cursor {
                     };
                     let advance = cluster.advance();
                     if range.contains(&position) {
-                        if !is_leading || !result.is_inside {
+                        // The caret is on the right edge of the cluster if it
+                        // follows the cluster in a left-to-right run or
+                        // precedes it in a right-to-left run.
+                        let is_trailing = !is_leading || !result.is_inside;
+                        if is_trailing != result.is_rtl {
                             result.offset += advance;
                         }
                         result.insert_point = if is_leading { range.start } else { range.end };
@@ -151,6 +164,92 @@ impl Cursor {
         result
     }
 
+    /// Creates a new cursor for the caret at the specified source offset,
+    /// the inverse of hit testing.
+    ///
+    /// An offset inside a cluster snaps to the start of the cluster. At a
+    /// soft line break, the affinity selects the end of the preceding line
+    /// or the start of the following line. Following a hard line break, the
+    /// cursor is always placed at the start of the following line. Line
+    /// breaking must have been performed.
+    pub fn from_offset<B: Brush>(layout: &Layout<B>, offset: usize, affinity: Affinity) -> Self {
+        let downstream = Self::from_position(layout, offset, true);
+        if affinity == Affinity::Downstream || offset == 0 {
+            return downstream;
+        }
+        let inside_cluster = downstream.is_inside && downstream.text_start != offset;
+        let after_hard_break = downstream.is_inside
+            && downstream
+                .path
+                .cluster(layout)
+                .map(|cluster| cluster.is_hard_line_break())
+                .unwrap_or(false);
+        if inside_cluster || after_hard_break {
+            return downstream;
+        }
+        Self::from_position(layout, offset - 1, false)
+    }
+
+    /// Returns the geometry of a caret drawn at the insert point of the
+    /// cursor as a zero width rectangle.
+    ///
+    /// The vertical extent covers the full height of the containing line,
+    /// including leading. The horizontal position is the edge of the target
+    /// cluster at the insert point: the left edge of a cluster in a
+    /// left-to-right run if the cursor is leading, and the right edge if it
+    /// is trailing, with the edges swapped in right-to-left runs. This
+    /// matches the offset of a cursor created from a point. If the cursor is
+    /// not inside the layout or has no target cluster, the offset of the
+    /// cursor is used.
+    pub fn caret_rect<B: Brush>(&self, layout: &Layout<B>) -> Rect {
+        let line = match self.path.line(layout) {
+            Some(line) => line,
+            None => return Rect::new(0., 0., 0., 0.),
+        };
+        let (top, bottom) = line.metrics().extent();
+        let x = self.caret_edge(&line).unwrap_or(self.offset);
+        Rect::new(x as f64, top as f64, x as f64, bottom as f64)
+    }
+
+    /// Returns the geometry of a caret of the specified width and shape
+    /// drawn at the insert point of the cursor. The vertical extent and
+    /// insertion edge are the same as for [`caret_rect`](Self::caret_rect).
+    pub fn caret_rect_with_shape<B: Brush>(
+        &self,
+        layout: &Layout<B>,
+        width: f32,
+        shape: CaretShape,
+    ) -> Rect {
+        shape.apply(self.caret_rect(layout), width)
+    }
+
+    /// Returns the visual edge of the target cluster at the insert point.
+    fn caret_edge<B: Brush>(&self, line: &Line<B>) -> Option<f32> {
+        if !self.is_inside || self.text_start == self.text_end {
+            return None;
+        }
+        let mut x = line.metrics().offset;
+        for run in line.runs() {
+            let range = run.text_range();
+            if !(range.start <= self.text_start && self.text_end <= range.end) {
+                // Sum the clusters rather than using the run advance so that
+                // justification is included.
+                x += run.clusters().map(|cluster| cluster.advance()).sum::<f32>();
+                continue;
+            }
+            for cluster in run.visual_clusters() {
+                let advance = cluster.advance();
+                if cluster.text_range().start == self.text_start {
+                    let is_left = self.is_leading() != run.is_rtl();
+                    return Some(if is_left { x } else { x + advance });
+                }
+                x += advance;
+            }
+            return None;
+        }
+        None
+    }
+
     /// Returns true if the cursor is on the leading edge of the target
     /// cluster.
     pub fn is_leading(&self) -> bool {
@@ -164,6 +263,93 @@ impl Cursor {
     }
 }
 
+/// Horizontal extent of a caret relative to the insertion edge.
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub enum CaretShape {
+    /// A zero width rectangle on the insertion edge, to be drawn as a line.
+    #[default]
+    Line,
+    /// A rectangle centered on the insertion edge.
+    Centered,
+    /// A rectangle that ends at the insertion edge, extending to the left.
+    Left,
+}
+
+impl CaretShape {
+    /// Returns the rectangle of a caret of this shape with the specified
+    /// width, given a zero width caret rectangle on the insertion edge.
+    pub fn apply(self, caret: Rect, width: f32) -> Rect {
+        let x = caret.x0;
+        let width = width.max(0.) as f64;
+        let (x0, x1) = match self {
+            Self::Line => (x, x),
+            Self::Centered => (x - width * 0.5, x + width * 0.5),
+            Self::Left => (x - width, x),
+        };
+        Rect::new(x0, caret.y0, x1, caret.y1)
+    }
+}
+
+/// Range of text selected between a fixed anchor and a movable focus, both
+/// source offsets.
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub struct Selection {
+    /// Offset at which the selection was started.
+    pub anchor: usize,
+    /// Offset to which the selection was extended.
+    pub focus: usize,
+}
+
+impl Selection {
+    /// Creates an empty selection at the specified offset.
+    pub fn collapsed(offset: usize) -> Self {
+        Self {
+            anchor: offset,
+            focus: offset,
+        }
+    }
+
+    /// Creates an empty selection at the insert point nearest to the
+    /// specified point, such as where a mouse drag begins.
+    pub fn from_point<B: Brush>(layout: &Layout<B>, x: f32, y: f32) -> Self {
+        Self::collapsed(offset_at_point(layout, x, y))
+    }
+
+    /// Moves the focus to the insert point nearest to the specified point,
+    /// keeping the anchor, such as while a mouse drag continues.
+    ///
+    /// The focus is the logical offset at the visual edge of the cluster
+    /// nearest to the point, which for a cluster in a right-to-left run is
+    /// the end of the cluster on its left half. Dragging across runs of
+    /// mixed direction therefore selects the logical range between the
+    /// anchor and the focus, which may be visually discontiguous.
+    pub fn extend_to_point<B: Brush>(&mut self, layout: &Layout<B>, x: f32, y: f32) {
+        self.focus = offset_at_point(layout, x, y);
+    }
+
+    /// Returns true if the selection is empty.
+    pub fn is_collapsed(&self) -> bool {
+        self.anchor == self.focus
+    }
+
+    /// Returns the selected range of source text.
+    pub fn text_range(&self) -> Range<usize> {
+        self.anchor.min(self.focus)..self.anchor.max(self.focus)
+    }
+
+    /// Returns the rectangles to highlight for the selection. See
+    /// [`Layout::rects_for_source_range`] for details.
+    pub fn geometry<B: Brush>(&self, layout: &Layout<B>) -> Vec<Rect> {
+        layout.rects_for_source_range(self.text_range())
+    }
+}
+
+/// Returns the source offset at the visual edge of the cluster nearest to
+/// the specified point.
+fn offset_at_point<B: Brush>(layout: &Layout<B>, x: f32, y: f32) -> usize {
+    Cursor::from_point(layout, x, y).insert_point
+}
+
 /// Index based path to a cluster.
 #[derive(Copy, Clone, Default, Debug)]
 pub struct CursorPath {
@@ -191,3 +377,89 @@ impl CursorPath {
         self.run(layout)?.get(self.cluster_index)
     }
 }
+
+/// Individual glyph found by hit testing.
+#[derive(Copy, Clone, Debug)]
+pub struct GlyphHit {
+    /// Path to the cluster containing the glyph.
+    pub path: CursorPath,
+    /// Index of the glyph within the cluster.
+    pub glyph_index: usize,
+    /// The glyph, with offsets relative to its origin.
+    pub glyph: Glyph,
+    /// Horizontal position of the glyph, including its offset.
+    pub x: f32,
+    /// Vertical position of the glyph, including its offset.
+    pub y: f32,
+}
+
+impl GlyphHit {
+    /// Returns the glyph in the specified layout at the given point.
+    ///
+    /// Within a cluster of several glyphs, such as a base with a combining
+    /// mark, the glyph whose outline spans the point is selected, preferring
+    /// later glyphs since they are drawn on top. If no outline spans the
+    /// point, the glyph whose advance contains it is selected. Returns
+    /// `None` if the point is outside the layout or on a cluster without
+    /// glyphs. The outlines are scaled with the specified context, which can
+    /// be reused across calls.
+    pub fn from_point<B: Brush>(
+        layout: &Layout<B>,
+        scx: &mut ScaleContext,
+        x: f32,
+        y: f32,
+    ) -> Option<Self> {
+        let cursor = Cursor::from_point(layout, x, y);
+        if !cursor.is_inside {
+            return None;
+        }
+        let line = cursor.path.line(layout)?;
+        let mut pen = line.metrics().offset;
+        for (run_index, run) in line.runs().enumerate() {
+            for cluster in run.visual_clusters() {
+                let range = cluster.text_range();
+                if run_index != cursor.path.run_index
+                    || range.start != cursor.text_start
+                    || range.end != cursor.text_end
+                {
+                    pen += cluster.advance();
+                    continue;
+                }
+                let mut scaler = scx
+                    .builder(run.font().as_ref())
+                    .size(run.font_size())
+                    .normalized_coords(run.normalized_coords())
+                    .build();
+                let mut by_advance = None;
+                let mut by_outline = None;
+                for (glyph_index, glyph) in cluster.glyphs().enumerate() {
+                    let origin = pen + glyph.x;
+                    if by_advance.is_none() && x < pen + glyph.advance {
+                        by_advance = Some((glyph_index, glyph, origin));
+                    }
+                    if let Some(bounds) = scaler.scale_outline(glyph.id).map(|o| o.bounds()) {
+                        if bounds.min.x < bounds.max.x
+                            && origin + bounds.min.x <= x
+                            && x < origin + bounds.max.x
+                        {
+                            by_outline = Some((glyph_index, glyph, origin));
+                        }
+                    }
+                    pen += glyph.advance;
+                }
+                let (glyph_index, glyph, origin) = by_outline.or(by_advance).or_else(|| {
+                    let glyph = cluster.glyphs().next()?;
+                    Some((0, glyph, pen - cluster.advance() + glyph.x))
+                })?;
+                return Some(Self {
+                    path: cursor.path,
+                    glyph_index,
+                    glyph,
+                    x: origin,
+                    y: line.metrics().baseline + glyph.y,
+                });
+            }
+        }
+        None
+    }
+}
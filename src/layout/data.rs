@@ -1,11 +1,27 @@
+use crate::context::{AdvanceAdjuster, MarkHandling};
 use crate::font::Font;
-use crate::layout::{Alignment, Decoration, Glyph, LineMetrics, RunMetrics, Style};
-use crate::style::Brush;
+#[cfg(feature = "profiling")]
+use crate::layout::LayoutProfile;
+use crate::layout::{
+    Alignment, Cluster, Decoration, Glyph, LastLineAlignment, Layout, LineMetrics, Run, RunMetrics,
+    ShapingIssue, Style,
+};
+use crate::style::{Brush, FontFeature, HangingEnd, OverflowWrap, Wrap};
 use crate::util::*;
 use core::ops::Range;
-use swash::shape::Shaper;
-use swash::text::cluster::{Boundary, ClusterInfo};
-use swash::Synthesis;
+use swash::shape::{Direction, ShapeContext, Shaper};
+use swash::text::cluster::{Boundary, CharCluster, ClusterInfo, Parser, Token, Whitespace};
+use swash::text::{Category, Codepoint, Language, Script};
+use swash::{tag_from_bytes, Synthesis, Tag};
+
+/// Optional ligature features, which are disabled when splitting
+/// ligatures.
+const LIGATURE_FEATURES: [Tag; 4] = [
+    tag_from_bytes(b"liga"),
+    tag_from_bytes(b"clig"),
+    tag_from_bytes(b"dlig"),
+    tag_from_bytes(b"hlig"),
+];
 
 #[derive(Copy, Clone)]
 pub struct ClusterData {
@@ -20,12 +36,21 @@ pub struct ClusterData {
     pub glyph_offset: u16,
     pub text_offset: u16,
     pub advance: f32,
+    /// Extra advance added to the cluster by justification.
+    pub justification: f32,
 }
 
 impl ClusterData {
     pub const LIGATURE_START: u16 = 1;
     pub const LIGATURE_COMPONENT: u16 = 2;
     pub const DIVERGENT_STYLES: u16 = 4;
+    pub const HANGING_OPEN: u16 = 8;
+    pub const HANGING_CLOSE: u16 = 16;
+    pub const HANGING_STOP: u16 = 32;
+    pub const TAB: u16 = 64;
+    /// Flags that are set when the cluster is shaped.
+    pub const SHAPING_FLAGS: u16 =
+        Self::LIGATURE_START | Self::LIGATURE_COMPONENT | Self::DIVERGENT_STYLES;
 
     pub fn is_ligature_start(&self) -> bool {
         self.flags & Self::LIGATURE_START != 0
@@ -39,10 +64,22 @@ impl ClusterData {
         self.flags & Self::DIVERGENT_STYLES != 0
     }
 
+    pub fn has_flag(&self, flag: u16) -> bool {
+        self.flags & flag != 0
+    }
+
     pub fn text_range(&self, run: &RunData) -> Range<usize> {
         let start = run.text_range.start + self.text_offset as usize;
         start..start + self.text_len as usize
     }
+
+    pub fn glyph_count(&self) -> usize {
+        if self.glyph_len == 0xFF {
+            1
+        } else {
+            self.glyph_len as usize
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -53,12 +90,18 @@ pub struct RunData {
     pub font_size: f32,
     /// Synthesis information for the font.
     pub synthesis: Synthesis,
+    /// Horizontal scale factor for synthesized stretch.
+    pub stretch: f32,
     /// Range of normalized coordinates in the layout data.
     pub coords_range: Range<usize>,
+    /// Range of applied font features in the layout data.
+    pub features_range: Range<usize>,
     /// Range of the source text.
     pub text_range: Range<usize>,
     /// Bidi level for the run.
     pub bidi_level: u8,
+    /// Script of the text of the run.
+    pub script: Script,
     /// True if the run ends with a newline.
     pub ends_with_newline: bool,
     /// Range of clusters.
@@ -75,20 +118,37 @@ pub struct RunData {
     pub advance: f32,
 }
 
-#[derive(Copy, Clone, PartialEq)]
+impl RunData {
+    /// Returns true if the following run can be merged into this run
+    /// without changing how either is drawn.
+    fn can_coalesce(&self, next: &RunData, coords: &[i16], features: &[FontFeature]) -> bool {
+        !self.ends_with_newline
+            && self.cluster_range.end == next.cluster_range.start
+            && self.text_range.end == next.text_range.start
+            && self.font_index == next.font_index
+            && self.font_size == next.font_size
+            && self.stretch == next.stretch
+            && self.bidi_level == next.bidi_level
+            && self.script == next.script
+            && self.word_spacing == next.word_spacing
+            && self.letter_spacing == next.letter_spacing
+            && self.synthesis.embolden() == next.synthesis.embolden()
+            && self.synthesis.skew() == next.synthesis.skew()
+            && self.synthesis.variations() == next.synthesis.variations()
+            && coords[self.coords_range.clone()] == coords[next.coords_range.clone()]
+            && features[self.features_range.clone()] == features[next.features_range.clone()]
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Default)]
 pub enum BreakReason {
+    #[default]
     None,
     Regular,
     Explicit,
     Emergency,
 }
 
-impl Default for BreakReason {
-    fn default() -> Self {
-        Self::None
-    }
-}
-
 #[derive(Clone, Default)]
 pub struct LineData {
     /// Range of the source text.
@@ -101,16 +161,48 @@ pub struct LineData {
     pub break_reason: BreakReason,
     /// Alignment.
     pub alignment: Alignment,
+    /// Alignment of the last line of the paragraph.
+    pub last_line_alignment: LastLineAlignment,
     /// Maximum advance for the line.
     pub max_advance: f32,
     /// Number of justified clusters on the line.
     pub num_spaces: usize,
+    /// Advance of punctuation hanging outside the line box.
+    pub hanging: f32,
+    /// Advance of the line that is aligned, excluding trailing whitespace
+    /// and hanging punctuation or side bearings.
+    pub aligned_advance: f32,
+    /// Offset added to the line by alignment.
+    pub alignment_offset: f32,
+    /// True if the line is a first line for the purpose of indentation and
+    /// hanging punctuation.
+    pub is_first_line: bool,
 }
 
 impl LineData {
     pub fn size(&self) -> f32 {
         self.metrics.ascent + self.metrics.descent + self.metrics.leading
     }
+
+    /// Returns the alignment that applies to the line, accounting for the
+    /// alignment of the last line of the paragraph, and true if the last
+    /// line alignment was applied.
+    pub fn effective_alignment(&self) -> (Alignment, bool) {
+        match self.last_line_alignment.alignment {
+            Some(alignment) if self.is_last_line() => (alignment, true),
+            _ => (self.alignment, false),
+        }
+    }
+
+    /// Returns true if the line is the last line of a paragraph for the
+    /// purposes of alignment.
+    pub fn is_last_line(&self) -> bool {
+        match self.break_reason {
+            BreakReason::None => true,
+            BreakReason::Explicit => self.last_line_alignment.forced_breaks,
+            _ => false,
+        }
+    }
 }
 
 #[derive(Clone, Default)]
@@ -151,6 +243,17 @@ impl LineRunData {
     }
 }
 
+/// Characters of a word that was shaped with ligatures, retained so that
+/// the word can be shaped again without them.
+#[derive(Clone)]
+pub struct LigatureWordData {
+    /// Range of the source text, which lies within a single run.
+    pub text_range: Range<usize>,
+    pub locale: Option<Language>,
+    /// Characters of the word as they were passed to the shaper.
+    pub tokens: Vec<Token>,
+}
+
 #[derive(Clone)]
 pub struct StyleData<B: Brush> {
     pub brush: B,
@@ -158,23 +261,49 @@ pub struct StyleData<B: Brush> {
     pub strikethrough: Option<Decoration<B>>,
 }
 
+/// Ruby annotation attached to a range of base clusters. The annotations
+/// of a layout are ordered by their bases, which do not overlap.
+#[derive(Clone)]
+pub struct RubyData<B: Brush> {
+    pub text_range: Range<usize>,
+    pub cluster_range: Range<usize>,
+    /// Space added on either side of the base so that it is centered under
+    /// a wider annotation.
+    pub padding: f32,
+    pub annotation: Layout<B>,
+}
+
 #[derive(Clone)]
 pub struct LayoutData<B: Brush> {
     pub scale: f32,
     pub has_bidi: bool,
     pub base_level: u8,
+    /// Text ranges of paragraphs with a base level that differs from that of
+    /// the layout, such as those in `unicode-bidi: plaintext` ranges. Later
+    /// entries take precedence.
+    pub paragraph_levels: Vec<(Range<usize>, u8)>,
     pub text_len: usize,
     pub width: f32,
     pub full_width: f32,
     pub height: f32,
+    pub overflow_offset: Option<usize>,
+    pub reveal_whitespace: bool,
+    pub forced_breaks_start_first_line: bool,
     pub fonts: Vec<Font>,
     pub coords: Vec<i16>,
+    pub features: Vec<FontFeature>,
     pub styles: Vec<Style<B>>,
     pub runs: Vec<RunData>,
     pub clusters: Vec<ClusterData>,
     pub glyphs: Vec<Glyph>,
     pub lines: Vec<LineData>,
     pub line_runs: Vec<LineRunData>,
+    pub diagnostics: Vec<ShapingIssue>,
+    pub ligature_words: Vec<LigatureWordData>,
+    pub mark_handling: MarkHandling,
+    pub rubies: Vec<RubyData<B>>,
+    #[cfg(feature = "profiling")]
+    pub profile: LayoutProfile,
 }
 
 impl<B: Brush> Default for LayoutData<B> {
@@ -183,51 +312,426 @@ impl<B: Brush> Default for LayoutData<B> {
             scale: 1.,
             has_bidi: false,
             base_level: 0,
+            paragraph_levels: Vec::new(),
             text_len: 0,
             width: 0.,
             full_width: 0.,
             height: 0.,
+            overflow_offset: None,
+            reveal_whitespace: false,
+            forced_breaks_start_first_line: true,
             fonts: Vec::new(),
             coords: Vec::new(),
+            features: Vec::new(),
             styles: Vec::new(),
             runs: Vec::new(),
             clusters: Vec::new(),
             glyphs: Vec::new(),
             lines: Vec::new(),
             line_runs: Vec::new(),
+            diagnostics: Vec::new(),
+            ligature_words: Vec::new(),
+            mark_handling: MarkHandling::default(),
+            rubies: Vec::new(),
+            #[cfg(feature = "profiling")]
+            profile: LayoutProfile::default(),
         }
     }
 }
 
 impl<B: Brush> LayoutData<B> {
+    /// Returns true if the cluster at the specified index has a style that
+    /// suppresses soft line breaks.
+    pub fn is_no_wrap(&self, cluster_index: usize) -> bool {
+        self.clusters
+            .get(cluster_index)
+            .and_then(|cluster| self.styles.get(cluster.style_index as usize))
+            .map(|style| style.wrap == Wrap::NoWrap)
+            .unwrap_or(false)
+    }
+
+    /// Returns true if the cluster at the specified index has a style that
+    /// allows breaks after every space.
+    pub fn is_break_spaces(&self, cluster_index: usize) -> bool {
+        self.clusters
+            .get(cluster_index)
+            .and_then(|cluster| self.styles.get(cluster.style_index as usize))
+            .map(|style| style.wrap == Wrap::BreakSpaces)
+            .unwrap_or(false)
+    }
+
+    /// Returns true if a line may be broken before the cluster at the
+    /// specified index when the word containing it does not fit on a line
+    /// by itself. This requires a style that allows overflowing words to
+    /// be broken and is never allowed within a ligature or where soft
+    /// breaks are suppressed.
+    pub fn allows_emergency_break(&self, cluster_index: usize) -> bool {
+        let allows_overflow_wrap = self
+            .clusters
+            .get(cluster_index)
+            .filter(|cluster| !cluster.is_ligature_component())
+            .and_then(|cluster| self.styles.get(cluster.style_index as usize))
+            .map(|style| style.overflow_wrap != OverflowWrap::Normal)
+            .unwrap_or(false);
+        allows_overflow_wrap && self.allows_soft_break(cluster_index)
+    }
+
+    /// Returns true if the cluster at the specified index follows a space
+    /// with a style that allows breaks after every space.
+    pub fn follows_breaking_space(&self, cluster_index: usize) -> bool {
+        cluster_index != 0
+            && self.clusters[cluster_index - 1].info.whitespace() == Whitespace::Space
+            && self.is_break_spaces(cluster_index - 1)
+    }
+
+    /// Returns true if a soft line break is allowed before the cluster at
+    /// the specified index. Breaks are suppressed between two clusters that
+    /// are both in no-wrap ranges and within the base of a ruby annotation.
+    pub fn allows_soft_break(&self, cluster_index: usize) -> bool {
+        if let Some(ruby) = self.ruby_at(cluster_index) {
+            if ruby.cluster_range.start < cluster_index {
+                return false;
+            }
+        }
+        cluster_index == 0 || !self.is_no_wrap(cluster_index) || !self.is_no_wrap(cluster_index - 1)
+    }
+
+    /// Returns true if a line may be broken between the cluster at the
+    /// specified index and the following cluster. This includes mandatory
+    /// breaks and breaks after spaces with a style that allows breaks after
+    /// every space. Breaks are never allowed within a ligature or after the
+    /// last cluster.
+    pub fn can_break_after(&self, cluster_index: usize) -> bool {
+        let index = cluster_index + 1;
+        match self.clusters.get(index) {
+            Some(next) if !next.is_ligature_component() => match next.info.boundary() {
+                Boundary::Mandatory => true,
+                Boundary::Line => self.allows_soft_break(index),
+                _ => self.follows_breaking_space(index) && self.allows_soft_break(index),
+            },
+            _ => false,
+        }
+    }
+
+    /// Returns the index of the first ruby annotation with a base that ends
+    /// after the cluster at the specified index.
+    fn ruby_index(&self, cluster_index: usize) -> usize {
+        self.rubies
+            .partition_point(|ruby| ruby.cluster_range.end <= cluster_index)
+    }
+
+    /// Returns the ruby annotation with a base containing the cluster at the
+    /// specified index.
+    pub fn ruby_at(&self, cluster_index: usize) -> Option<&RubyData<B>> {
+        self.rubies
+            .get(self.ruby_index(cluster_index))
+            .filter(|ruby| ruby.cluster_range.start <= cluster_index)
+    }
+
+    /// Attaches a ruby annotation, replacing any annotations with bases
+    /// that overlap its base. The space added around the bases is adjusted
+    /// in the advances of the clusters and runs.
+    pub fn insert_ruby(&mut self, mut ruby: RubyData<B>) {
+        let clusters = ruby.cluster_range.clone();
+        let start = self.ruby_index(clusters.start);
+        let mut end = start;
+        while end < self.rubies.len() && self.rubies[end].cluster_range.start < clusters.end {
+            end += 1;
+        }
+        let removed: Vec<_> = self.rubies.drain(start..end).collect();
+        for old in &removed {
+            self.pad_ruby_base(old.cluster_range.clone(), -old.padding);
+        }
+        let advance: f32 = self.clusters[clusters.clone()]
+            .iter()
+            .map(|cluster| cluster.advance)
+            .sum();
+        ruby.padding = ((ruby.annotation.width() - advance) * 0.5).max(0.);
+        self.pad_ruby_base(clusters, ruby.padding);
+        self.rubies.insert(start, ruby);
+    }
+
+    /// Adds space to the first and last clusters of a ruby base and to the
+    /// runs containing them.
+    fn pad_ruby_base(&mut self, clusters: Range<usize>, padding: f32) {
+        for &index in &[clusters.start, clusters.end - 1] {
+            self.clusters[index].advance += padding;
+            let run = self
+                .runs
+                .partition_point(|run| run.cluster_range.end <= index);
+            if let Some(run) = self.runs.get_mut(run) {
+                run.advance += padding;
+            }
+        }
+    }
+
+    /// Returns the space added by ruby annotations before and after the
+    /// glyphs of the cluster at the specified index, in visual order.
+    pub fn ruby_padding(&self, run: &RunData, cluster_index: usize) -> (f32, f32) {
+        let padding = match self.ruby_at(cluster_index) {
+            Some(ruby) => {
+                let before = if ruby.cluster_range.start == cluster_index {
+                    ruby.padding
+                } else {
+                    0.
+                };
+                let after = if ruby.cluster_range.end == cluster_index + 1 {
+                    ruby.padding
+                } else {
+                    0.
+                };
+                (before, after)
+            }
+            None => (0., 0.),
+        };
+        if run.bidi_level & 1 != 0 {
+            (padding.1, padding.0)
+        } else {
+            padding
+        }
+    }
+
+    /// Returns the largest ascent of the runs containing the base of the
+    /// ruby annotation.
+    pub fn ruby_base_ascent(&self, ruby: &RubyData<B>) -> f32 {
+        self.runs
+            .iter()
+            .filter(|run| {
+                run.cluster_range.start < ruby.cluster_range.end
+                    && ruby.cluster_range.start < run.cluster_range.end
+            })
+            .map(|run| run.metrics.ascent)
+            .fold(0., f32::max)
+    }
+
     pub fn clear(&mut self) {
         self.scale = 1.;
         self.has_bidi = false;
         self.base_level = 0;
+        self.paragraph_levels.clear();
         self.text_len = 0;
         self.width = 0.;
         self.full_width = 0.;
         self.height = 0.;
+        self.overflow_offset = None;
+        self.reveal_whitespace = false;
+        self.forced_breaks_start_first_line = true;
         self.fonts.clear();
         self.coords.clear();
+        self.features.clear();
         self.styles.clear();
         self.runs.clear();
         self.clusters.clear();
         self.glyphs.clear();
         self.lines.clear();
         self.line_runs.clear();
+        self.diagnostics.clear();
+        self.ligature_words.clear();
+        self.mark_handling = MarkHandling::default();
+        self.rubies.clear();
+        #[cfg(feature = "profiling")]
+        {
+            self.profile = LayoutProfile::default();
+        }
+    }
+
+    /// Appends the runs and lines of another layout below the lines of this
+    /// layout, offsetting source ranges by the specified amount.
+    pub fn append(&mut self, other: LayoutData<B>, text_offset: usize) {
+        let font_map: Vec<usize> = other
+            .fonts
+            .into_iter()
+            .map(|font| match self.fonts.iter().position(|f| *f == font) {
+                Some(index) => index,
+                None => {
+                    self.fonts.push(font);
+                    self.fonts.len() - 1
+                }
+            })
+            .collect();
+        let coords_base = self.coords.len();
+        let features_base = self.features.len();
+        // Style indices are stored as u16, so combining layouts with more
+        // styles than that would corrupt the indices of the appended styles.
+        assert!(
+            self.styles.len() + other.styles.len() <= 1 << 16,
+            "appended layout exceeds the maximum of {} styles",
+            1 << 16
+        );
+        let style_base = self.styles.len() as u16;
+        let run_base = self.runs.len();
+        let cluster_base = self.clusters.len();
+        let glyph_base = self.glyphs.len();
+        let line_run_base = self.line_runs.len();
+        let shift = |range: &Range<usize>| range.start + text_offset..range.end + text_offset;
+        self.coords.extend(other.coords);
+        self.features.extend(other.features);
+        self.styles
+            .extend(other.styles.into_iter().map(|mut style| {
+                style.text_range = shift(&style.text_range);
+                style
+            }));
+        self.runs.extend(other.runs.into_iter().map(|mut run| {
+            run.font_index = font_map[run.font_index];
+            run.coords_range =
+                run.coords_range.start + coords_base..run.coords_range.end + coords_base;
+            run.features_range =
+                run.features_range.start + features_base..run.features_range.end + features_base;
+            run.text_range = shift(&run.text_range);
+            run.cluster_range =
+                run.cluster_range.start + cluster_base..run.cluster_range.end + cluster_base;
+            run.glyph_start += glyph_base;
+            run
+        }));
+        self.clusters
+            .extend(other.clusters.into_iter().map(|mut cluster| {
+                cluster.style_index += style_base;
+                cluster
+            }));
+        self.glyphs
+            .extend(other.glyphs.into_iter().map(|mut glyph| {
+                glyph.style_index += style_base;
+                glyph
+            }));
+        self.line_runs
+            .extend(other.line_runs.into_iter().map(|mut line_run| {
+                line_run.run_index += run_base;
+                line_run.cluster_range = line_run.cluster_range.start + cluster_base
+                    ..line_run.cluster_range.end + cluster_base;
+                line_run.text_range = shift(&line_run.text_range);
+                line_run
+            }));
+        let y = self.height;
+        self.lines.extend(other.lines.into_iter().map(|mut line| {
+            line.text_range = shift(&line.text_range);
+            line.run_range =
+                line.run_range.start + line_run_base..line.run_range.end + line_run_base;
+            line.metrics.baseline += y;
+            line
+        }));
+        self.diagnostics
+            .extend(other.diagnostics.into_iter().map(|mut issue| {
+                issue.text_range = shift(&issue.text_range);
+                issue
+            }));
+        self.ligature_words
+            .extend(other.ligature_words.into_iter().map(|mut word| {
+                word.text_range = shift(&word.text_range);
+                for token in &mut word.tokens {
+                    token.offset += text_offset as u32;
+                    token.data = token_data(token.data as u16 + style_base, token.offset as usize);
+                }
+                word
+            }));
+        if other.base_level != self.base_level {
+            self.paragraph_levels
+                .push((shift(&(0..other.text_len)), other.base_level));
+        }
+        self.paragraph_levels.extend(
+            other
+                .paragraph_levels
+                .iter()
+                .map(|(range, level)| (shift(range), *level)),
+        );
+        self.rubies.extend(other.rubies.into_iter().map(|mut ruby| {
+            ruby.text_range = shift(&ruby.text_range);
+            ruby.cluster_range =
+                ruby.cluster_range.start + cluster_base..ruby.cluster_range.end + cluster_base;
+            ruby
+        }));
+        #[cfg(feature = "profiling")]
+        self.profile.add(&other.profile);
+        self.has_bidi |= other.has_bidi;
+        self.text_len = self.text_len.max(text_offset + other.text_len);
+        self.width = self.width.max(other.width);
+        self.full_width = self.full_width.max(other.full_width);
+        self.height += other.height;
+        if self.overflow_offset.is_none() {
+            self.overflow_offset = other.overflow_offset.map(|offset| offset + text_offset);
+        }
     }
 
-    #[allow(unused_assignments)]
+    /// Marks single character clusters that contain punctuation which may
+    /// hang outside the line box.
+    pub fn mark_hanging_punctuation(&mut self, text: &str) {
+        let clusters = &mut self.clusters;
+        for run in &self.runs {
+            for cluster in &mut clusters[run.cluster_range.clone()] {
+                let mut chars = text[cluster.text_range(run)].chars();
+                if let (Some(ch), None) = (chars.next(), chars.next()) {
+                    cluster.flags |= hanging_punctuation_flags(ch);
+                }
+            }
+        }
+    }
+
+    /// Flags clusters consisting of a tab, which the cluster analysis
+    /// treats as a control character rather than whitespace.
+    pub fn mark_tabs(&mut self, text: &str) {
+        let clusters = &mut self.clusters;
+        for run in &self.runs {
+            for cluster in &mut clusters[run.cluster_range.clone()] {
+                if &text[cluster.text_range(run)] == "\t" {
+                    cluster.flags |= ClusterData::TAB;
+                }
+            }
+        }
+    }
+
+    /// Returns true if the cluster at the specified index is punctuation
+    /// that may hang past the end of the line.
+    pub fn can_hang_at_end(&self, cluster_index: usize) -> bool {
+        let cluster = &self.clusters[cluster_index];
+        let hanging = self.styles[cluster.style_index as usize].hanging_punctuation;
+        if cluster.has_flag(ClusterData::HANGING_STOP) && hanging.end != HangingEnd::None {
+            return true;
+        }
+        hanging.last
+            && cluster.has_flag(ClusterData::HANGING_CLOSE)
+            && self
+                .clusters
+                .get(cluster_index + 1)
+                .map(|next| {
+                    next.info.boundary() == Boundary::Mandatory
+                        || next.info.whitespace() == Whitespace::Newline
+                })
+                .unwrap_or(true)
+    }
+
+    /// Returns the text ranges of clusters in the specified runs that were
+    /// mapped to the `.notdef` glyph.
+    pub fn notdef_clusters(&self, runs: Range<usize>) -> impl Iterator<Item = Range<usize>> + '_ {
+        self.runs[runs].iter().flat_map(move |run| {
+            self.clusters[run.cluster_range.clone()]
+                .iter()
+                .filter(move |cluster| {
+                    if cluster.glyph_len == 0xFF {
+                        cluster.glyph_offset == 0
+                    } else {
+                        let start = run.glyph_start + cluster.glyph_offset as usize;
+                        self.glyphs[start..start + cluster.glyph_len as usize]
+                            .iter()
+                            .any(|glyph| glyph.id == 0)
+                    }
+                })
+                .map(move |cluster| cluster.text_range(run))
+        })
+    }
+
+    #[allow(unused_assignments, clippy::too_many_arguments)]
     pub fn push_run(
         &mut self,
         font: Font,
         font_size: f32,
         synthesis: Synthesis,
+        stretch: f32,
         shaper: Shaper,
+        features: &[FontFeature],
         bidi_level: u8,
+        script: Script,
         word_spacing: f32,
         letter_spacing: f32,
+        drop_broken: bool,
     ) {
         let font_index = self
             .fonts
@@ -243,13 +747,27 @@ impl<B: Brush> LayoutData<B> {
         let coords_start = self.coords.len();
         self.coords.extend_from_slice(shaper.normalized_coords());
         let coords_end = self.coords.len();
+        let features_start = self.features.len();
+        if !features.is_empty() {
+            // Features that the font does not provide have no effect.
+            let font_features = self.fonts[font_index].as_ref().features();
+            self.features.extend(features.iter().filter(|feature| {
+                font_features
+                    .clone()
+                    .any(|font_feature| font_feature.tag() == feature.tag)
+            }));
+        }
+        let features_end = self.features.len();
         let mut run = RunData {
             font_index,
             font_size,
             synthesis,
+            stretch,
             coords_range: coords_start..coords_end,
+            features_range: features_start..features_end,
             text_range: 0..0,
             bidi_level,
+            script,
             ends_with_newline: false,
             cluster_range,
             glyph_start: self.glyphs.len(),
@@ -305,16 +823,23 @@ impl<B: Brush> LayoutData<B> {
                 flush_run!();
             }
             let text_len = source_range.len();
-            let glyph_len = cluster.glyphs.len();
-            let advance = cluster.advance();
+            // Clusters of marks without a base are kept with no glyphs.
+            let is_dropped = drop_broken && cluster.info.is_broken();
+            let glyph_len = if is_dropped { 0 } else { cluster.glyphs.len() };
+            let advance = if is_dropped {
+                0.
+            } else {
+                cluster.advance() * stretch
+            };
             run.advance += advance;
             let mut cluster_data = ClusterData {
                 info: cluster.info,
                 flags: 0,
-                style_index: cluster.data as _,
+                style_index: cluster.data as u16,
                 glyph_len: glyph_len as u8,
                 text_len: text_len as u8,
                 advance,
+                justification: 0.,
                 text_offset: (text_offset - run.text_range.start) as u16,
                 glyph_offset: 0,
             };
@@ -366,12 +891,16 @@ impl<B: Brush> LayoutData<B> {
                 if cluster_data.style_index != style_index {
                     cluster_data.flags |= ClusterData::DIVERGENT_STYLES;
                 }
+                // Characters are within a cluster of the glyph's source
+                // character, so the truncated offset is sufficient.
+                let source_offset = ((g.data >> 16) as u16).wrapping_sub(source_range.start as u16);
                 Glyph {
                     id: g.id,
                     style_index,
-                    x: g.x,
+                    source_offset: source_offset as u8,
+                    x: g.x * stretch,
                     y: g.y,
-                    advance: g.advance,
+                    advance: g.advance * stretch,
                 }
             }));
             glyph_count += glyph_len;
@@ -380,7 +909,118 @@ impl<B: Brush> LayoutData<B> {
         flush_run!();
     }
 
-    pub fn finish(&mut self) {
+    /// Returns the base level of the paragraph containing the specified
+    /// source offset.
+    pub fn paragraph_level(&self, offset: usize) -> u8 {
+        self.paragraph_levels
+            .iter()
+            .rev()
+            .find(|(range, _)| range.contains(&offset))
+            .map(|(_, level)| *level)
+            .unwrap_or(self.base_level)
+    }
+
+    /// Merges adjacent runs that are drawn with the same font, size,
+    /// variations, bidi level and script, so that they produce a single glyph run
+    /// when they share a style and line.
+    pub fn coalesce_runs(&mut self) {
+        let mut runs: Vec<RunData> = Vec::with_capacity(self.runs.len());
+        for run in core::mem::take(&mut self.runs) {
+            if let Some(prev) = runs.last_mut() {
+                if prev.can_coalesce(&run, &self.coords, &self.features) {
+                    let clusters = &mut self.clusters[run.cluster_range.clone()];
+                    let glyph_shift = run.glyph_start - prev.glyph_start;
+                    let glyph_count: usize = clusters
+                        .iter()
+                        .filter(|cluster| cluster.glyph_len != 0xFF)
+                        .map(|cluster| cluster.glyph_len as usize)
+                        .sum();
+                    let text_shift = run.text_range.start - prev.text_range.start;
+                    // Offsets within the merged run must fit in a u16.
+                    const MAX_LEN: usize = u16::MAX as usize;
+                    if glyph_shift + glyph_count <= MAX_LEN
+                        && text_shift + run.text_range.len() <= MAX_LEN
+                    {
+                        for cluster in clusters {
+                            if cluster.glyph_len != 0 && cluster.glyph_len != 0xFF {
+                                cluster.glyph_offset += glyph_shift as u16;
+                            }
+                            cluster.text_offset += text_shift as u16;
+                        }
+                        prev.text_range.end = run.text_range.end;
+                        prev.cluster_range.end = run.cluster_range.end;
+                        prev.ends_with_newline = run.ends_with_newline;
+                        prev.advance += run.advance;
+                        continue;
+                    }
+                }
+            }
+            runs.push(run);
+        }
+        self.runs = runs;
+    }
+
+    /// Adds the advances returned by the adjuster between adjacent clusters.
+    pub fn adjust_advances(&mut self, adjuster: &AdvanceAdjuster<B>, across_runs: bool) {
+        let mut adjustments = vec![];
+        let mut prev: Option<(usize, usize)> = None;
+        for (run_index, run_data) in self.runs.iter().enumerate() {
+            let run = Run::new(self, run_data, None);
+            for index in run_data.cluster_range.clone() {
+                let cluster = &self.clusters[index];
+                if cluster.is_ligature_component() {
+                    continue;
+                }
+                let cluster = Cluster {
+                    run: run.clone(),
+                    index,
+                    data: cluster,
+                };
+                if let Some((prev_run_index, prev_index)) = prev {
+                    let prev_run_data = &self.runs[prev_run_index];
+                    let same_run = prev_run_index == run_index;
+                    if same_run || (across_runs && prev_run_data.bidi_level == run_data.bidi_level)
+                    {
+                        let prev_run = Run::new(self, prev_run_data, None);
+                        let prev_cluster = Cluster {
+                            run: prev_run.clone(),
+                            index: prev_index,
+                            data: &self.clusters[prev_index],
+                        };
+                        let delta = adjuster(&prev_run, &prev_cluster, &cluster);
+                        if delta != 0. {
+                            // Adjust the cluster on the left side of the gap.
+                            let target = if run_data.bidi_level & 1 != 0 {
+                                (run_index, index)
+                            } else {
+                                (prev_run_index, prev_index)
+                            };
+                            adjustments.push((target, delta));
+                        }
+                    }
+                }
+                prev = Some((run_index, index));
+            }
+        }
+        for ((run_index, index), delta) in adjustments {
+            let run = &mut self.runs[run_index];
+            let cluster = &mut self.clusters[index];
+            cluster.advance += delta;
+            run.advance += delta;
+            if cluster.glyph_len != 0xFF {
+                let start = run.glyph_start + cluster.glyph_offset as usize;
+                let end = start + cluster.glyph_len as usize;
+                if let Some(last) = self.glyphs[start..end].last_mut() {
+                    last.advance += delta;
+                }
+            }
+        }
+    }
+
+    /// Applies word and letter spacing to the shaped, and so kerned,
+    /// clusters. If a floor is specified, spacing does not reduce an advance
+    /// below it.
+    pub fn finish(&mut self, spacing_floor: Option<f32>) {
         for run in &self.runs {
             let word = run.word_spacing;
             let letter = run.letter_spacing;
@@ -390,9 +1030,17 @@ impl<B: Brush> LayoutData<B> {
             let clusters = &mut self.clusters[run.cluster_range.clone()];
             for cluster in clusters {
                 let mut spacing = letter;
+                // Word spacing only applies to the word separators U+0020
+                // and U+00A0.
                 if !nearly_zero(word) && cluster.info.whitespace().is_space_or_nbsp() {
                     spacing += word;
                 }
+                if let Some(floor) = spacing_floor {
+                    // Advances that are already below the floor are not
+                    // raised to it.
+                    let advance = (cluster.advance + spacing).max(floor.min(cluster.advance));
+                    spacing = advance - cluster.advance;
+                }
                 if !nearly_zero(spacing) {
                     cluster.advance += spacing;
                     if cluster.glyph_len != 0xFF {
@@ -407,4 +1055,321 @@ impl<B: Brush> LayoutData<B> {
             }
         }
     }
+
+    /// Retains the characters of the words of the specified runs that were
+    /// shaped with ligatures. Words are delimited by whitespace and by the
+    /// bounds of the runs.
+    ///
+    /// The runs and the tokens are in logical order.
+    pub fn push_ligature_words(
+        &mut self,
+        run_range: Range<usize>,
+        tokens: impl Iterator<Item = Token>,
+        locale: Option<Language>,
+    ) {
+        let mut tokens = tokens.peekable();
+        for run in &self.runs[run_range] {
+            let mut word: Option<Range<usize>> = None;
+            let mut has_ligature = false;
+            let clusters = &self.clusters[run.cluster_range.clone()];
+            for (i, cluster) in clusters.iter().enumerate() {
+                let is_last = i + 1 == clusters.len();
+                if cluster.info.whitespace() == Whitespace::None {
+                    let start = run.text_range.start + cluster.text_offset as usize;
+                    let end = start + cluster.text_len as usize;
+                    let range = word.get_or_insert(start..end);
+                    range.start = range.start.min(start);
+                    range.end = range.end.max(end);
+                    has_ligature |= cluster.is_ligature_start();
+                    if !is_last {
+                        continue;
+                    }
+                }
+                if let Some(range) = word.take() {
+                    if has_ligature {
+                        self.ligature_words.push(LigatureWordData {
+                            text_range: range.clone(),
+                            locale,
+                            tokens: core::iter::from_fn(|| {
+                                while (tokens.peek()?.offset as usize) < range.start {
+                                    tokens.next();
+                                }
+                                tokens.next_if(|token| (token.offset as usize) < range.end)
+                            })
+                            .collect(),
+                        });
+                    }
+                }
+                has_ligature = false;
+            }
+        }
+    }
+
+    /// Shapes the words with ligatures spanning any of the specified source
+    /// offsets again with the optional ligature features disabled.
+    pub fn split_ligatures_at(&mut self, scx: &mut ShapeContext, offsets: &[usize]) {
+        let mut index = 0;
+        while index < self.ligature_words.len() {
+            let range = self.ligature_words[index].text_range.clone();
+            let run_index = self.runs.iter().position(|run| {
+                run.text_range.start <= range.start && range.end <= run.text_range.end
+            });
+            match run_index {
+                Some(run_index)
+                    if offsets
+                        .iter()
+                        .any(|offset| self.splits_ligature(run_index, &range, *offset)) =>
+                {
+                    let word = self.ligature_words.remove(index);
+                    self.unligate_word(scx, run_index, &word);
+                }
+                _ => index += 1,
+            }
+        }
+    }
+
+    /// Returns true if a ligature within the specified source range of a
+    /// run spans the offset.
+    fn splits_ligature(&self, run_index: usize, range: &Range<usize>, offset: usize) -> bool {
+        let run = &self.runs[run_index];
+        let mut ligature: Option<Range<usize>> = None;
+        for cluster in &self.clusters[run.cluster_range.clone()] {
+            let start = run.text_range.start + cluster.text_offset as usize;
+            let end = start + cluster.text_len as usize;
+            if !range.contains(&start) {
+                continue;
+            }
+            if cluster.is_ligature_component() {
+                if let Some(range) = ligature.as_mut() {
+                    range.start = range.start.min(start);
+                    range.end = range.end.max(end);
+                    continue;
+                }
+            }
+            if let Some(range) = ligature.take() {
+                if range.start < offset && offset < range.end {
+                    return true;
+                }
+            }
+            if cluster.is_ligature_start() {
+                ligature = Some(start..end);
+            }
+        }
+        ligature
+            .map(|range| range.start < offset && offset < range.end)
+            .unwrap_or(false)
+    }
+
+    /// Replaces the clusters of a word with the word shaped without
+    /// ligatures.
+    ///
+    /// Space added to the clusters after shaping, such as letter spacing
+    /// and ruby padding, is measured against the word shaped again with the
+    /// original features and kept.
+    fn unligate_word(&mut self, scx: &mut ShapeContext, run_index: usize, word: &LigatureWordData) {
+        let run = self.runs[run_index].clone();
+        let features = &self.features[run.features_range.clone()];
+        let unligated_features: Vec<FontFeature> = features
+            .iter()
+            .filter(|feature| !LIGATURE_FEATURES.contains(&feature.tag))
+            .copied()
+            .chain(LIGATURE_FEATURES.iter().map(|tag| FontFeature {
+                tag: *tag,
+                value: 0,
+            }))
+            .collect();
+        let original = self.shape_word(scx, &run, word, features);
+        let unligated = self.shape_word(scx, &run, word, &unligated_features);
+        let word_clusters: Vec<usize> = run
+            .cluster_range
+            .clone()
+            .filter(|index| {
+                let offset = run.text_range.start + self.clusters[*index].text_offset as usize;
+                word.text_range.contains(&offset)
+            })
+            .collect();
+        // The clusters are formed from the same characters, so they only
+        // differ if the word was originally shaped in a different context.
+        let matches = |shaped: &LayoutData<B>| {
+            shaped.clusters.len() == word_clusters.len()
+                && shaped.runs.len() == 1
+                && shaped
+                    .clusters
+                    .iter()
+                    .zip(&word_clusters)
+                    .all(|(cluster, index)| {
+                        let old = &self.clusters[*index];
+                        shaped.runs[0].text_range.start + cluster.text_offset as usize
+                            == run.text_range.start + old.text_offset as usize
+                            && cluster.text_len == old.text_len
+                    })
+        };
+        if !matches(&original) || !matches(&unligated) {
+            return;
+        }
+        let glyph_end = run
+            .cluster_range
+            .clone()
+            .map(|index| &self.clusters[index])
+            .filter(|cluster| cluster.glyph_len != 0xFF)
+            .map(|cluster| {
+                run.glyph_start + cluster.glyph_offset as usize + cluster.glyph_len as usize
+            })
+            .max()
+            .unwrap_or(run.glyph_start);
+        let mut clusters = Vec::with_capacity(run.cluster_range.len());
+        let mut glyphs = Vec::with_capacity(glyph_end - run.glyph_start);
+        let mut advance = run.advance;
+        for index in run.cluster_range.clone() {
+            let old = self.clusters[index];
+            let (mut cluster, source) = match word_clusters.iter().position(|i| *i == index) {
+                Some(i) => {
+                    let shaped = unligated.clusters[i];
+                    let extra = old.advance - original.clusters[i].advance;
+                    let mut cluster = ClusterData {
+                        info: shaped.info,
+                        flags: (old.flags & !ClusterData::SHAPING_FLAGS)
+                            | (shaped.flags & ClusterData::SHAPING_FLAGS),
+                        glyph_len: shaped.glyph_len,
+                        glyph_offset: shaped.glyph_offset,
+                        advance: shaped.advance + extra,
+                        ..old
+                    };
+                    advance += cluster.advance - old.advance;
+                    if cluster.glyph_len != 0xFF {
+                        let start = shaped.glyph_offset as usize;
+                        let end = start + shaped.glyph_len as usize;
+                        cluster.glyph_offset = glyphs.len() as u16;
+                        glyphs.extend_from_slice(&unligated.glyphs[start..end]);
+                        if let Some(last) = glyphs[cluster.glyph_offset as usize..].last_mut() {
+                            last.advance += extra;
+                        }
+                    }
+                    (cluster, None)
+                }
+                None => (old, Some(run.glyph_start + old.glyph_offset as usize)),
+            };
+            if let Some(start) = source.filter(|_| cluster.glyph_len != 0xFF) {
+                let end = start + cluster.glyph_len as usize;
+                cluster.glyph_offset = glyphs.len() as u16;
+                glyphs.extend_from_slice(&self.glyphs[start..end]);
+            }
+            clusters.push(cluster);
+        }
+        if glyphs.len() > u16::MAX as usize {
+            return;
+        }
+        self.clusters[run.cluster_range.clone()].copy_from_slice(&clusters);
+        let glyph_count = glyphs.len();
+        self.glyphs.splice(run.glyph_start..glyph_end, glyphs);
+        for next in &mut self.runs[run_index + 1..] {
+            next.glyph_start = next.glyph_start + glyph_count + run.glyph_start - glyph_end;
+        }
+        self.runs[run_index].advance = advance;
+    }
+
+    /// Shapes a word with the font, size and variations of the run that
+    /// contains it.
+    fn shape_word(
+        &self,
+        scx: &mut ShapeContext,
+        run: &RunData,
+        word: &LigatureWordData,
+        features: &[FontFeature],
+    ) -> LayoutData<B> {
+        let font = &self.fonts[run.font_index];
+        let font_ref = font.as_ref();
+        let is_rtl = run.bidi_level & 1 != 0;
+        let mut shaper = scx
+            .builder(font_ref)
+            .script(run.script)
+            .language(word.locale)
+            .direction(if is_rtl {
+                Direction::RightToLeft
+            } else {
+                Direction::LeftToRight
+            })
+            .size(run.font_size)
+            .features(features.iter().copied())
+            .normalized_coords(&self.coords[run.coords_range.clone()])
+            .insert_dotted_circles(self.mark_handling == MarkHandling::InsertDottedCircle)
+            .build();
+        let charmap = font_ref.charmap();
+        let tokens = word.tokens.iter().map(|token| {
+            let mut token = *token;
+            if is_rtl {
+                token.ch = token.ch.mirror().unwrap_or(token.ch);
+            }
+            token
+        });
+        let mut parser = Parser::new(run.script, tokens);
+        let mut cluster = CharCluster::new();
+        while parser.next(&mut cluster) {
+            cluster.map(|ch| charmap.map(ch));
+            shaper.add_cluster(&cluster);
+        }
+        let mut shaped = LayoutData::default();
+        shaped.push_run(
+            font.clone(),
+            run.font_size,
+            run.synthesis,
+            run.stretch,
+            shaper,
+            features,
+            run.bidi_level,
+            run.script,
+            0.,
+            0.,
+            self.mark_handling == MarkHandling::Drop,
+        );
+        shaped
+    }
+}
+
+/// Returns the user data of a token, which carries the low bits of the
+/// source offset with the style index to recover the source character of
+/// each glyph.
+pub fn token_data(style_index: u16, offset: usize) -> u32 {
+    style_index as u32 | (offset as u32) << 16
+}
+
+fn hanging_punctuation_flags(ch: char) -> u16 {
+    let is_quote = ch == '\'' || ch == '"';
+    let category = ch.category();
+    let mut flags = 0;
+    if is_quote
+        || matches!(
+            category,
+            Category::OpenPunctuation | Category::InitialPunctuation | Category::FinalPunctuation
+        )
+    {
+        flags |= ClusterData::HANGING_OPEN;
+    }
+    if is_quote
+        || matches!(
+            category,
+            Category::ClosePunctuation | Category::InitialPunctuation | Category::FinalPunctuation
+        )
+    {
+        flags |= ClusterData::HANGING_CLOSE;
+    }
+    // Stops and commas from <https://drafts.csswg.org/css-text-3/#hanging-punctuation-property>
+    if matches!(
+        ch,
+        ',' | '.'
+            | '\u{060C}'
+            | '\u{06D4}'
+            | '\u{3001}'
+            | '\u{3002}'
+            | '\u{FF0C}'
+            | '\u{FF0E}'
+            | '\u{FE50}'
+            | '\u{FE51}'
+            | '\u{FE52}'
+            | '\u{FF61}'
+            | '\u{FF64}'
+    ) {
+        flags |= ClusterData::HANGING_STOP;
+    }
+    flags
 }
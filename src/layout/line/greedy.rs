@@ -1,7 +1,9 @@
 //! Greedy line breaking.
 
 use crate::layout::*;
-use crate::style::Brush;
+use crate::style::{Brush, HangingEnd};
+use swash::scale::ScaleContext;
+use swash::text::cluster::Whitespace;
 
 use core::ops::Range;
 
@@ -24,6 +26,8 @@ pub struct BreakLines<'a, B: Brush> {
     lines: LineLayout,
     state: BreakerState,
     prev_state: Option<BreakerState>,
+    last_line_alignment: LastLineAlignment,
+    finished: FinishState,
     done: bool,
 }
 
@@ -32,6 +36,12 @@ impl<'a, B: Brush> BreakLines<'a, B> {
         unjustify(layout);
         layout.width = 0.;
         layout.height = 0.;
+        layout.overflow_offset = None;
+        #[cfg(feature = "profiling")]
+        {
+            layout.profile.line_breaking = Default::default();
+            layout.profile.alignment = Default::default();
+        }
         let mut lines = LineLayout::default();
         lines.swap(layout);
         lines.lines.clear();
@@ -41,13 +51,33 @@ impl<'a, B: Brush> BreakLines<'a, B> {
             lines,
             state: BreakerState::default(),
             prev_state: None,
+            last_line_alignment: LastLineAlignment::default(),
+            finished: FinishState::default(),
             done: false,
         }
     }
 
+    /// Sets the alignment of the last line of the paragraph, overriding the
+    /// alignment passed to [`break_next`](Self::break_next) for that line.
+    /// This is applied when the lines are finalized.
+    pub fn set_last_line_alignment(&mut self, alignment: LastLineAlignment) {
+        self.last_line_alignment = alignment;
+    }
+
     /// Computes the next line in the paragraph. Returns the advance and size
     /// (width and height for horizontal layouts) of the line.
     pub fn break_next(&mut self, max_advance: f32, alignment: Alignment) -> Option<(f32, f32)> {
+        #[cfg(feature = "profiling")]
+        let start = std::time::Instant::now();
+        let result = self.break_line(max_advance, alignment);
+        #[cfg(feature = "profiling")]
+        {
+            self.layout.profile.line_breaking += start.elapsed();
+        }
+        result
+    }
+
+    fn break_line(&mut self, max_advance: f32, alignment: Alignment) -> Option<(f32, f32)> {
         if self.done {
             return None;
         }
@@ -62,8 +92,12 @@ impl<'a, B: Brush> BreakLines<'a, B> {
                 let cluster = run.get(self.state.j - cluster_start).unwrap();
                 let is_ligature_continuation = cluster.is_ligature_continuation();
                 let is_space = cluster.info().whitespace().is_space_or_nbsp();
-                let boundary = cluster.info().boundary();
-                match boundary {
+                // Only regular spaces may hang past the line end; a no-break
+                // space must stay attached to the following cluster.
+                let can_hang = cluster.info().whitespace() == Whitespace::Space
+                    && !self.layout.is_no_wrap(self.state.j)
+                    && !self.layout.is_break_spaces(self.state.j);
+                match cluster.info().boundary() {
                     Boundary::Mandatory => {
                         if !self.state.line.skip_mandatory_break {
                             self.state.prev_boundary = None;
@@ -87,21 +121,20 @@ impl<'a, B: Brush> BreakLines<'a, B> {
                             }
                         }
                     }
-                    Boundary::Line => {
-                        if !is_ligature_continuation {
-                            self.state.prev_boundary = Some(PrevBoundaryState {
-                                i: self.state.i,
-                                j: self.state.j,
-                                state: self.state.line.clone(),
-                            });
-                        }
+                    _ if self.state.j > 0 && self.layout.can_break_after(self.state.j - 1) => {
+                        self.state.prev_boundary = Some(PrevBoundaryState {
+                            i: self.state.i,
+                            j: self.state.j,
+                            state: self.state.line.clone(),
+                        });
                     }
                     _ => {}
                 }
                 self.state.line.skip_mandatory_break = false;
+                let allows_emergency_break = self.layout.allows_emergency_break(self.state.j);
                 let mut advance = cluster.advance();
                 if cluster.is_ligature_start() {
-                    while let Some(cluster) = run.get(self.state.j + 1) {
+                    while let Some(cluster) = run.get(self.state.j + 1 - cluster_start) {
                         if !cluster.is_ligature_continuation() {
                             break;
                         } else {
@@ -111,8 +144,23 @@ impl<'a, B: Brush> BreakLines<'a, B> {
                     }
                 }
                 let next_x = self.state.line.x + advance;
-                if next_x > max_advance {
-                    if is_space {
+                // Qualifying punctuation may hang past the line end as long as
+                // the preceding content fits.
+                let can_hang_punctuation = self.state.line.x <= max_advance
+                    && !is_ligature_continuation
+                    && self.layout.can_hang_at_end(self.state.j);
+                // Content that cannot be moved to the next line at a previous
+                // break opportunity is allowed to overflow, unless the style
+                // allows the overflowing word to be broken.
+                let must_overflow = !allows_emergency_break
+                    && self
+                        .state
+                        .prev_boundary
+                        .as_ref()
+                        .map(|prev| prev.state.x == 0.)
+                        .unwrap_or(true);
+                if next_x > max_advance && (!must_overflow || can_hang) && !can_hang_punctuation {
+                    if can_hang {
                         // Hang overflowing whitespace
                         self.state.line.runs.end = self.state.i + 1;
                         self.state.line.clusters.end = self.state.j + 1;
@@ -134,49 +182,31 @@ impl<'a, B: Brush> BreakLines<'a, B> {
                             self.state.j += 1;
                             return Some((line.metrics.advance, line.size()));
                         }
-                    } else if let Some(prev) = self.state.prev_boundary.take() {
-                        if prev.state.x == 0. {
-                            // This will cycle if we try to rewrap. Accept the overflowing fragment.
-                            self.state.line.runs.end = self.state.i + 1;
-                            self.state.line.clusters.end = self.state.j + 1;
-                            self.state.line.x = next_x;
-                            self.state.j += 1;
-                            if commit_line(
-                                self.layout,
-                                &mut self.lines,
-                                &mut self.state.line,
-                                max_advance,
-                                alignment,
-                                BreakReason::Emergency,
-                                false,
-                            ) {
-                                self.state.runs = self.lines.runs.len();
-                                self.state.lines = self.lines.lines.len();
-                                self.state.line.x = 0.;
-                                let line = self.lines.lines.last().unwrap();
-                                self.state.prev_boundary = None;
-                                self.state.j += 1;
-                                return Some((line.metrics.advance, line.size()));
-                            }
-                        } else {
-                            self.state.line = prev.state;
-                            if commit_line(
-                                self.layout,
-                                &mut self.lines,
-                                &mut self.state.line,
-                                max_advance,
-                                alignment,
-                                BreakReason::Regular,
-                                false,
-                            ) {
-                                self.state.runs = self.lines.runs.len();
-                                self.state.lines = self.lines.lines.len();
-                                self.state.line.x = 0.;
-                                let line = self.lines.lines.last().unwrap();
-                                self.state.i = prev.i;
-                                self.state.j = prev.j;
-                                return Some((line.metrics.advance, line.size()));
-                            }
+                    } else if let Some(prev) = self
+                        .state
+                        .prev_boundary
+                        .take()
+                        // Rewrapping at a break opportunity at the start of
+                        // the line would cycle, so the word is broken instead.
+                        .filter(|prev| prev.state.x != 0.)
+                    {
+                        self.state.line = prev.state;
+                        if commit_line(
+                            self.layout,
+                            &mut self.lines,
+                            &mut self.state.line,
+                            max_advance,
+                            alignment,
+                            BreakReason::Regular,
+                            false,
+                        ) {
+                            self.state.runs = self.lines.runs.len();
+                            self.state.lines = self.lines.lines.len();
+                            self.state.line.x = 0.;
+                            let line = self.lines.lines.last().unwrap();
+                            self.state.i = prev.i;
+                            self.state.j = prev.j;
+                            return Some((line.metrics.advance, line.size()));
                         }
                     } else {
                         if self.state.line.x == 0. {
@@ -202,7 +232,6 @@ impl<'a, B: Brush> BreakLines<'a, B> {
                             self.state.line.x = 0.;
                             let line = self.lines.lines.last().unwrap();
                             self.state.prev_boundary = None;
-                            self.state.j += 1;
                             return Some((line.metrics.advance, line.size()));
                         }
                     }
@@ -253,38 +282,162 @@ impl<'a, B: Brush> BreakLines<'a, B> {
 
     /// Breaks all remaining lines with the specified maximum advance. This
     /// consumes the line breaker.
-    pub fn break_remaining(mut self, max_advance: f32, alignment: Alignment) {
+    pub fn break_remaining(self, max_advance: f32, alignment: Alignment) {
+        self.break_remaining_within(max_advance, f32::MAX, alignment);
+    }
+
+    /// Breaks all remaining lines with the specified maximum advance,
+    /// keeping only the lines that fit within the maximum height. This
+    /// consumes the line breaker.
+    ///
+    /// See [`finish_within`](Self::finish_within) for details on how lines
+    /// are discarded.
+    pub fn break_remaining_within(
+        mut self,
+        max_advance: f32,
+        max_height: f32,
+        alignment: Alignment,
+    ) {
         while self.break_next(max_advance, alignment).is_some() {}
+        self.finish_within(max_height);
+    }
+
+    /// Breaks all remaining lines, using the widths in order as the maximum
+    /// advances of successive lines. This consumes the line breaker.
+    ///
+    /// The overflow behavior determines how lines beyond the provided
+    /// widths are handled.
+    pub fn break_remaining_with_widths(
+        mut self,
+        widths: &[f32],
+        overflow: LineWidthsOverflow,
+        alignment: Alignment,
+    ) {
+        for index in 0.. {
+            let max_advance = match (widths.get(index), overflow) {
+                (Some(width), _) => *width,
+                (None, LineWidthsOverflow::RepeatLast) => *widths.last().unwrap_or(&f32::MAX),
+                (None, LineWidthsOverflow::Truncate) => {
+                    // Break one more line to determine whether any text
+                    // remains and, if so, where it begins, then discard the
+                    // line before it is finished.
+                    let width = *widths.last().unwrap_or(&f32::MAX);
+                    if self.break_next(width, alignment).is_some() {
+                        let line = self.lines.lines.last().unwrap();
+                        let offset = self.lines.runs[line.run_range.clone()]
+                            .iter()
+                            .map(|run| run.text_range.start)
+                            .min();
+                        self.revert();
+                        self.layout.overflow_offset = offset;
+                    }
+                    break;
+                }
+            };
+            if self.break_next(max_advance, alignment).is_none() {
+                break;
+            }
+        }
         self.finish();
     }
 
+    /// Breaks all remaining lines with the specified maximum advance,
+    /// invoking the callback with each line as soon as it has been computed
+    /// and aligned. This consumes the line breaker.
+    pub fn break_remaining_streaming(
+        mut self,
+        max_advance: f32,
+        alignment: Alignment,
+        mut f: impl FnMut(Line<B>),
+    ) {
+        loop {
+            let has_more = self.break_next(max_advance, alignment).is_some();
+            let start = self.finished.lines;
+            self.finish_lines(f32::MAX);
+            if self.finished.lines != start {
+                // Lines are only visible through the layout once swapped in.
+                self.lines.swap(self.layout);
+                for data in &self.layout.lines[start..self.finished.lines] {
+                    f(Line {
+                        layout: self.layout,
+                        data,
+                    });
+                }
+                self.lines.swap(self.layout);
+            }
+            if !has_more {
+                break;
+            }
+        }
+    }
+
     /// Consumes the line breaker and finalizes all line computations.
-    pub fn finish(mut self) {
-        for run in &mut self.lines.runs {
-            run.is_whitespace = true;
-            if run.bidi_level & 1 != 0 {
-                // RTL runs check for "trailing" whitespace at the front.
-                for cluster in self.layout.clusters[run.cluster_range.clone()].iter() {
-                    if cluster.info.is_whitespace() {
-                        run.has_trailing_whitespace = true;
-                    } else {
-                        run.is_whitespace = false;
-                        break;
+    pub fn finish(self) {
+        self.finish_within(f32::MAX);
+    }
+
+    /// Consumes the line breaker and finalizes all line computations,
+    /// discarding every line that extends below the maximum height.
+    ///
+    /// The first line is always kept, even if it does not fit, so that
+    /// content always makes progress when laid out into a sequence of
+    /// regions. The source offset of the first discarded line is available
+    /// from [`Layout::overflow_offset`].
+    pub fn finish_within(mut self, max_height: f32) {
+        self.finish_lines(max_height);
+        if let Some(line_index) = self.finished.overflow_line {
+            let line = &self.lines.lines[line_index];
+            self.layout.overflow_offset = Some(line.text_range.start);
+            self.lines.runs.truncate(line.run_range.start);
+            self.lines.lines.truncate(line_index);
+        }
+    }
+
+    /// Finalizes the lines computed since the last call, stopping at the
+    /// first line that extends below the maximum height.
+    fn finish_lines(&mut self, max_height: f32) {
+        #[cfg(feature = "profiling")]
+        let start = std::time::Instant::now();
+        self.finish_lines_within(max_height);
+        #[cfg(feature = "profiling")]
+        {
+            self.layout.profile.alignment += start.elapsed();
+        }
+    }
+
+    fn finish_lines_within(&mut self, max_height: f32) {
+        if self.finished.overflow_line.is_some() {
+            return;
+        }
+        let start = self.finished.lines;
+        for (line_index, line) in self.lines.lines.iter_mut().enumerate().skip(start) {
+            for run in &mut self.lines.runs[line.run_range.clone()] {
+                run.is_whitespace = true;
+                if run.bidi_level & 1 != 0 {
+                    // RTL runs check for "trailing" whitespace at the front.
+                    for cluster in self.layout.clusters[run.cluster_range.clone()].iter() {
+                        if cluster.info.is_whitespace() {
+                            run.has_trailing_whitespace = true;
+                        } else {
+                            run.is_whitespace = false;
+                            break;
+                        }
                     }
-                }
-            } else {
-                for cluster in self.layout.clusters[run.cluster_range.clone()].iter().rev() {
-                    if cluster.info.is_whitespace() {
-                        run.has_trailing_whitespace = true;
-                    } else {
-                        run.is_whitespace = false;
-                        break;
+                } else {
+                    for cluster in self.layout.clusters[run.cluster_range.clone()].iter().rev() {
+                        if cluster.info.is_whitespace() {
+                            run.has_trailing_whitespace = true;
+                        } else {
+                            run.is_whitespace = false;
+                            break;
+                        }
                     }
                 }
             }
-        }
-        let mut y = 0.;
-        for line in &mut self.lines.lines {
+            line.is_first_line = line_index == 0
+                || (self.layout.forced_breaks_start_first_line
+                    && self.finished.prev_break_reason == BreakReason::Explicit);
+            self.finished.prev_break_reason = line.break_reason;
             let run_base = line.run_range.start;
             let run_count = line.run_range.end - run_base;
             line.metrics.ascent = 0.;
@@ -315,14 +468,49 @@ impl<'a, B: Brush> BreakLines<'a, B> {
                 line.metrics.leading = line.metrics.leading.max(run.metrics.leading * line_height);
                 have_metrics = true;
             }
+            if !have_metrics {
+                // Line consisting entirely of whitespace?
+                if !line.run_range.is_empty() {
+                    let line_run = &self.lines.runs[line.run_range.start];
+                    let run = &self.layout.runs[line_run.run_index];
+                    line.metrics.ascent = run.metrics.ascent;
+                    line.metrics.descent = run.metrics.descent;
+                    line.metrics.leading = run.metrics.leading;
+                }
+            }
+            // Ruby annotations are stacked above the ascent of their base.
+            for ruby in &self.layout.rubies {
+                if ruby.text_range.start < line.text_range.end
+                    && line.text_range.start < ruby.text_range.end
+                {
+                    let ascent = self.layout.ruby_base_ascent(ruby) + ruby.annotation.height();
+                    line.metrics.ascent = line.metrics.ascent.max(ascent);
+                }
+            }
+            line.metrics.ascent = line.metrics.ascent.round();
+            line.metrics.descent = line.metrics.descent.round();
+            line.metrics.leading = (line.metrics.leading * 0.5).round() * 2.;
+            let above = (line.metrics.ascent + line.metrics.leading * 0.5).round();
+            let below = (line.metrics.descent + line.metrics.leading * 0.5).round();
+            line.metrics.baseline = self.finished.y + above;
+            self.finished.y = line.metrics.baseline + below;
+            if line_index != 0 && self.finished.y > max_height {
+                self.finished.overflow_line = Some(line_index);
+                return;
+            }
+            self.finished.lines = line_index + 1;
             if needs_reorder && run_count > 1 {
                 reorder_runs(&mut self.lines.runs[line.run_range.clone()]);
             }
             let trailing_whitespace = if !line.run_range.is_empty() {
                 let last_run = &self.lines.runs[line.run_range.end - 1];
                 if !last_run.cluster_range.is_empty() {
-                    let cluster = &self.layout.clusters[last_run.cluster_range.end - 1];
-                    if cluster.info.whitespace().is_space_or_nbsp() {
+                    let index = last_run.cluster_range.end - 1;
+                    let cluster = &self.layout.clusters[index];
+                    // Spaces that do not hang occupy the width of the line.
+                    if cluster.info.whitespace().is_space_or_nbsp()
+                        && !self.layout.is_break_spaces(index)
+                    {
                         cluster.advance
                     } else {
                         0.
@@ -334,74 +522,97 @@ impl<'a, B: Brush> BreakLines<'a, B> {
                 0.
             };
             line.metrics.trailing_whitespace = trailing_whitespace;
-            if line.alignment != Alignment::Start
+            let (hang_start, hang_end) = hanging_punctuation(self.layout, &self.lines.runs, line);
+            line.hanging = hang_start + hang_end;
+            let (bearing_start, bearing_end) = if hang_start == 0. || hang_end == 0. {
+                side_bearings(self.layout, &mut self.finished.scx, &self.lines.runs, line)
+            } else {
+                (0., 0.)
+            };
+            let hang_start = if hang_start == 0. {
+                bearing_start
+            } else {
+                hang_start
+            };
+            let hang_end = if hang_end == 0. {
+                bearing_end
+            } else {
+                hang_end
+            };
+            line.metrics.offset -= hang_start;
+            line.last_line_alignment = self.last_line_alignment;
+            line.aligned_advance =
+                line.metrics.advance - trailing_whitespace - hang_start - hang_end;
+            line.alignment_offset = 0.;
+            let is_last_line = line.is_last_line();
+            let (alignment, is_last_line_alignment) = line.effective_alignment();
+            // The last line is only justified when requested explicitly.
+            let justify = is_last_line_alignment || !is_last_line;
+            // Lines that are not justified are aligned to the start edge,
+            // which is on the right in a right-to-left paragraph.
+            let alignment = match alignment {
+                Alignment::Justified if !justify || line.num_spaces == 0 => Alignment::Start,
+                _ => alignment,
+            };
+            let alignment = if self.layout.paragraph_level(line.text_range.start) & 1 != 0 {
+                match alignment {
+                    Alignment::Start => Alignment::End,
+                    Alignment::End => Alignment::Start,
+                    _ => alignment,
+                }
+            } else {
+                alignment
+            };
+            if alignment != Alignment::Start
                 && line.max_advance.is_finite()
                 && line.max_advance < f32::MAX
             {
-                let extra = line.max_advance - line.metrics.advance + trailing_whitespace;
+                let extra = line.max_advance - line.aligned_advance;
                 if extra > 0. {
-                    let offset = if line.alignment == Alignment::Middle {
+                    let offset = if alignment == Alignment::Middle {
                         extra * 0.5
                     } else {
                         extra
                     };
-                    if line.alignment == Alignment::Justified {
-                        if line.break_reason != BreakReason::None && line.num_spaces != 0 {
-                            let adjustment = extra / line.num_spaces as f32;
-                            let mut applied = 0;
-                            for line_run in &self.lines.runs[line.run_range.clone()] {
-                                if line_run.bidi_level & 1 != 0 {
-                                    for cluster in self.layout.clusters
-                                        [line_run.cluster_range.clone()]
+                    if alignment == Alignment::Justified {
+                        let adjustment = extra / line.num_spaces as f32;
+                        let mut applied = 0;
+                        for line_run in &self.lines.runs[line.run_range.clone()] {
+                            if line_run.bidi_level & 1 != 0 {
+                                for cluster in self.layout.clusters[line_run.cluster_range.clone()]
                                     .iter_mut()
                                     .rev()
-                                    {
-                                        if applied == line.num_spaces {
-                                            break;
-                                        }
-                                        if cluster.info.whitespace().is_space_or_nbsp() {
-                                            cluster.advance += adjustment;
-                                            applied += 1;
-                                        }
+                                {
+                                    if applied == line.num_spaces {
+                                        break;
                                     }
-                                } else {
-                                    for cluster in self.layout.clusters
-                                        [line_run.cluster_range.clone()]
-                                    .iter_mut()
-                                    {
-                                        if applied == line.num_spaces {
-                                            break;
-                                        }
-                                        if cluster.info.whitespace().is_space_or_nbsp() {
-                                            cluster.advance += adjustment;
-                                            applied += 1;
-                                        }
+                                    if cluster.info.whitespace().is_space_or_nbsp() {
+                                        cluster.advance += adjustment;
+                                        cluster.justification = adjustment;
+                                        applied += 1;
+                                    }
+                                }
+                            } else {
+                                for cluster in
+                                    self.layout.clusters[line_run.cluster_range.clone()].iter_mut()
+                                {
+                                    if applied == line.num_spaces {
+                                        break;
+                                    }
+                                    if cluster.info.whitespace().is_space_or_nbsp() {
+                                        cluster.advance += adjustment;
+                                        cluster.justification = adjustment;
+                                        applied += 1;
                                     }
                                 }
                             }
                         }
                     } else {
-                        line.metrics.offset = offset;
+                        line.metrics.offset += offset;
+                        line.alignment_offset = offset;
                     }
                 }
             }
-            if !have_metrics {
-                // Line consisting entirely of whitespace?
-                if !line.run_range.is_empty() {
-                    let line_run = &self.lines.runs[line.run_range.start];
-                    let run = &self.layout.runs[line_run.run_index];
-                    line.metrics.ascent = run.metrics.ascent;
-                    line.metrics.descent = run.metrics.descent;
-                    line.metrics.leading = run.metrics.leading;
-                }
-            }
-            line.metrics.ascent = line.metrics.ascent.round();
-            line.metrics.descent = line.metrics.descent.round();
-            line.metrics.leading = (line.metrics.leading * 0.5).round() * 2.;
-            let above = (line.metrics.ascent + line.metrics.leading * 0.5).round();
-            let below = (line.metrics.descent + line.metrics.leading * 0.5).round();
-            line.metrics.baseline = y + above;
-            y = line.metrics.baseline + below;
         }
     }
 }
@@ -412,57 +623,139 @@ impl<'a, B: Brush> Drop for BreakLines<'a, B> {
         let mut full_width = 0f32;
         let mut height = 0f32;
         for line in &self.lines.lines {
-            width = width.max(line.metrics.advance - line.metrics.trailing_whitespace);
+            width =
+                width.max(line.metrics.advance - line.metrics.trailing_whitespace - line.hanging);
             full_width = full_width.max(line.metrics.advance);
             height += line.metrics.size();
         }
         self.layout.width = width;
         self.layout.full_width = full_width;
         self.layout.height = height;
+        #[cfg(feature = "profiling")]
+        {
+            self.layout.profile.lines = self.lines.lines.len();
+        }
         self.lines.swap(self.layout);
     }
 }
 
 /// Removes previous justification applied to clusters.
 fn unjustify<B: Brush>(layout: &mut LayoutData<B>) {
-    for line in &layout.lines {
-        if line.alignment == Alignment::Justified
-            && line.max_advance.is_finite()
-            && line.max_advance < f32::MAX
-        {
-            let extra = line.max_advance - line.metrics.advance + line.metrics.trailing_whitespace;
-            if line.break_reason != BreakReason::None && line.num_spaces != 0 {
-                let adjustment = extra / line.num_spaces as f32;
-                let mut applied = 0;
-                for line_run in &layout.line_runs[line.run_range.clone()] {
-                    if line_run.bidi_level & 1 != 0 {
-                        for cluster in layout.clusters[line_run.cluster_range.clone()]
-                            .iter_mut()
-                            .rev()
-                        {
-                            if applied == line.num_spaces {
-                                break;
-                            }
-                            if cluster.info.whitespace().is_space_or_nbsp() {
-                                cluster.advance -= adjustment;
-                                applied += 1;
-                            }
-                        }
-                    } else {
-                        for cluster in layout.clusters[line_run.cluster_range.clone()].iter_mut() {
-                            if applied == line.num_spaces {
-                                break;
-                            }
-                            if cluster.info.whitespace().is_space_or_nbsp() {
-                                cluster.advance -= adjustment;
-                                applied += 1;
-                            }
-                        }
-                    }
+    for cluster in &mut layout.clusters {
+        cluster.advance -= cluster.justification;
+        cluster.justification = 0.;
+    }
+}
+
+/// Returns the advances of punctuation hanging at the start and end of the
+/// line.
+fn hanging_punctuation<B: Brush>(
+    layout: &LayoutData<B>,
+    runs: &[LineRunData],
+    line: &LineData,
+) -> (f32, f32) {
+    let runs = &runs[line.run_range.clone()];
+    let mut hang_start = 0.;
+    if line.is_first_line {
+        if let Some(run) = runs.first().filter(|run| run.bidi_level & 1 == 0) {
+            if let Some(cluster) = layout
+                .clusters
+                .get(run.cluster_range.clone())
+                .and_then(|c| c.first())
+            {
+                let style = &layout.styles[cluster.style_index as usize];
+                if style.hanging_punctuation.first && cluster.has_flag(ClusterData::HANGING_OPEN) {
+                    hang_start = cluster.advance;
                 }
             }
         }
     }
+    let mut hang_end = 0.;
+    if let Some(run) = runs.last().filter(|run| run.bidi_level & 1 == 0) {
+        let clusters = &layout.clusters[run.cluster_range.clone()];
+        if let Some(cluster) = clusters.iter().rev().find(|c| !c.info.is_whitespace()) {
+            let hanging = layout.styles[cluster.style_index as usize].hanging_punctuation;
+            let content_advance = line.metrics.advance - line.metrics.trailing_whitespace;
+            let is_stop = cluster.has_flag(ClusterData::HANGING_STOP)
+                && match hanging.end {
+                    HangingEnd::None => false,
+                    HangingEnd::Force => true,
+                    HangingEnd::Allow => content_advance > line.max_advance,
+                };
+            let is_paragraph_end =
+                matches!(line.break_reason, BreakReason::None | BreakReason::Explicit);
+            let is_close =
+                hanging.last && is_paragraph_end && cluster.has_flag(ClusterData::HANGING_CLOSE);
+            if is_stop || is_close {
+                hang_end = cluster.advance;
+            }
+        }
+    }
+    (hang_start, hang_end)
+}
+
+/// Returns the side bearings of the first and last glyphs of the line for
+/// styles that request optical alignment.
+fn side_bearings<B: Brush>(
+    layout: &LayoutData<B>,
+    scx: &mut Option<ScaleContext>,
+    runs: &[LineRunData],
+    line: &LineData,
+) -> (f32, f32) {
+    let runs = &runs[line.run_range.clone()];
+    let mut bearings = (0., 0.);
+    let mut glyph_bounds = |line_run: &LineRunData, cluster: &ClusterData, last: bool| {
+        let run = &layout.runs[line_run.run_index];
+        if !layout.styles[cluster.style_index as usize].optical_alignment {
+            return None;
+        }
+        let glyph = if cluster.glyph_len == 0xFF {
+            Glyph {
+                id: cluster.glyph_offset,
+                advance: cluster.advance,
+                ..Default::default()
+            }
+        } else {
+            let start = run.glyph_start + cluster.glyph_offset as usize;
+            let glyphs = &layout.glyphs[start..start + cluster.glyph_len as usize];
+            *if last { glyphs.last() } else { glyphs.first() }?
+        };
+        let font = layout.fonts[run.font_index].as_ref();
+        let mut scaler = scx
+            .get_or_insert_with(ScaleContext::new)
+            .builder(font)
+            .size(run.font_size)
+            .normalized_coords(&layout.coords[run.coords_range.clone()])
+            .build();
+        let bounds = scaler.scale_outline(glyph.id)?.bounds();
+        if bounds.min.x >= bounds.max.x {
+            return None;
+        }
+        Some((glyph, bounds))
+    };
+    if let Some(line_run) = runs.first() {
+        let clusters = &layout.clusters[line_run.cluster_range.clone()];
+        let cluster = if line_run.bidi_level & 1 != 0 {
+            clusters.last()
+        } else {
+            clusters.first()
+        };
+        if let Some((glyph, bounds)) = cluster.and_then(|c| glyph_bounds(line_run, c, false)) {
+            bearings.0 = glyph.x + bounds.min.x;
+        }
+    }
+    if let Some(line_run) = runs.last() {
+        let clusters = &layout.clusters[line_run.cluster_range.clone()];
+        let cluster = if line_run.bidi_level & 1 != 0 {
+            clusters.iter().find(|c| !c.info.is_whitespace())
+        } else {
+            clusters.iter().rev().find(|c| !c.info.is_whitespace())
+        };
+        if let Some((glyph, bounds)) = cluster.and_then(|c| glyph_bounds(line_run, c, true)) {
+            bearings.1 = glyph.advance - (glyph.x + bounds.max.x);
+        }
+    }
+    bearings
 }
 
 #[derive(Clone, Default)]
@@ -481,6 +774,27 @@ struct PrevBoundaryState {
     state: LineState,
 }
 
+/// Progress of line finalization.
+struct FinishState {
+    lines: usize,
+    y: f32,
+    prev_break_reason: BreakReason,
+    scx: Option<ScaleContext>,
+    overflow_line: Option<usize>,
+}
+
+impl Default for FinishState {
+    fn default() -> Self {
+        Self {
+            lines: 0,
+            y: 0.,
+            prev_break_reason: BreakReason::Explicit,
+            scx: None,
+            overflow_line: None,
+        }
+    }
+}
+
 #[derive(Clone, Default)]
 struct BreakerState {
     runs: usize,
@@ -1,4 +1,7 @@
 use super::*;
+use peniko::kurbo::Point;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 
 pub mod greedy;
 
@@ -8,11 +11,105 @@ impl<'a, B: Brush> Line<'a, B> {
         &self.data.metrics
     }
 
-    /// Returns the range of text for the line.
+    /// Returns the range of source text covered by the line, including
+    /// trailing whitespace and the line break, if any.
+    ///
+    /// The ranges of the lines of a layout are contiguous and do not
+    /// overlap, so a line ending at a soft wrap ends where the following line
+    /// begins.
+    #[doc(alias = "source_range")]
     pub fn text_range(&self) -> Range<usize> {
         self.data.text_range.clone()
     }
 
+    /// Returns true if the line is a first line, which is the first line of
+    /// the layout and, unless disabled with
+    /// [`LayoutContext::set_forced_breaks_start_first_line`], each line
+    /// following a forced line break. This can be used to apply first line
+    /// styling or indentation.
+    ///
+    /// [`LayoutContext::set_forced_breaks_start_first_line`]: crate::LayoutContext::set_forced_breaks_start_first_line
+    pub fn is_first_line(&self) -> bool {
+        self.data.is_first_line
+    }
+
+    /// Returns an identifier for the line derived from its source range and
+    /// the identifiers of its runs. See [`Run::id`] for details on stability.
+    pub fn id(&self) -> LineId {
+        let mut hasher = DefaultHasher::new();
+        self.data.text_range.hash(&mut hasher);
+        for run in self.runs() {
+            run.id().hash(&mut hasher);
+        }
+        LineId(hasher.finish())
+    }
+
+    /// Returns the source offset nearest to the specified horizontal position,
+    /// in layout coordinates, along with the affinity that associates the
+    /// offset with the cluster at that position.
+    ///
+    /// The offset is the logical edge of the cluster under the position on
+    /// the side of the position, so the rightmost position of a right-to-left
+    /// line maps to the logical start of the line. Positions before or beyond
+    /// the line map to the nearest visual edge of the line.
+    pub fn offset_at_x(&self, x: f32) -> (usize, Affinity) {
+        let mut result = (self.data.text_range.start, Affinity::Downstream);
+        let mut last_edge = self.data.metrics.offset;
+        for run in self.runs() {
+            let is_rtl = run.is_rtl();
+            for cluster in run.visual_clusters() {
+                let range = cluster.text_range();
+                // The left edge of a cluster in a right-to-left run is its
+                // trailing edge.
+                let (left, right) = if is_rtl {
+                    (
+                        (range.end, Affinity::Upstream),
+                        (range.start, Affinity::Downstream),
+                    )
+                } else {
+                    (
+                        (range.start, Affinity::Downstream),
+                        (range.end, Affinity::Upstream),
+                    )
+                };
+                if x < last_edge {
+                    return left;
+                }
+                let next_edge = last_edge + cluster.advance();
+                if x < next_edge {
+                    return if x <= (last_edge + next_edge) * 0.5 {
+                        left
+                    } else {
+                        right
+                    };
+                }
+                result = right;
+                last_edge = next_edge;
+            }
+        }
+        result
+    }
+
+    /// Returns the text of the line in visual order, from left to right,
+    /// given the source text of the layout.
+    ///
+    /// The clusters of the line are concatenated in the order in which they
+    /// are displayed, with the characters of each cluster kept in logical
+    /// order. This is generally not the text to copy to the clipboard,
+    /// which should be in logical order, but is useful for inspecting and
+    /// testing bidi reordering.
+    pub fn visual_text(&self, source: &str) -> String {
+        let mut text = String::new();
+        for run in self.runs() {
+            for cluster in run.visual_clusters() {
+                if let Some(s) = source.get(cluster.text_range()) {
+                    text.push_str(s);
+                }
+            }
+        }
+        text
+    }
+
     /// Returns the number of runs in the line.
     pub fn len(&self) -> usize {
         self.data.run_range.len()
@@ -57,6 +154,16 @@ impl<'a, B: Brush> Line<'a, B> {
             offset: 0.,
         }
     }
+
+    /// Returns an iterator over the glyph runs for the line in visual order,
+    /// each paired with the pen position at which its first glyph should be
+    /// drawn: the offset along the line and the baseline.
+    pub fn runs_with_origin(&self) -> impl Iterator<Item = (GlyphRun<'a, B>, Point)> + 'a + Clone {
+        self.glyph_runs().map(|run| {
+            let origin = Point::new(run.offset() as f64, run.baseline() as f64);
+            (run, origin)
+        })
+    }
 }
 
 /// Metrics information for a line.
@@ -83,6 +190,16 @@ impl LineMetrics {
     pub fn size(&self) -> f32 {
         self.ascent + self.descent + self.leading
     }
+
+    /// Returns the top and bottom of the line box, which includes half of
+    /// the leading on either side.
+    pub(crate) fn extent(&self) -> (f32, f32) {
+        let half_leading = self.leading * 0.5;
+        (
+            self.baseline - self.ascent - half_leading,
+            self.baseline + self.descent + half_leading,
+        )
+    }
 }
 
 /// Sequence of fully positioned glyphs with the same style.
@@ -104,6 +221,10 @@ impl<'a, B: Brush> GlyphRun<'a, B> {
     }
 
     /// Returns the associated style.
+    ///
+    /// Glyph runs are split at run and line boundaries, but every glyph run
+    /// produced from a styled range carries the same brush value, so data
+    /// such as a gradient spanning the range is preserved across splits.
     pub fn style(&self) -> &Style<B> {
         self.style
     }
@@ -113,6 +234,12 @@ impl<'a, B: Brush> GlyphRun<'a, B> {
         self.baseline
     }
 
+    /// Returns the shadows that should be drawn beneath the glyphs in the
+    /// run, in the order specified by the style.
+    pub fn shadows(&self) -> &[Shadow<B>] {
+        &self.style.text_shadow
+    }
+
     /// Returns the offset to the first glyph along the baseline.
     pub fn offset(&self) -> f32 {
         self.offset
@@ -1,41 +1,126 @@
 //! Layout types.
 
+mod baseline;
 mod cluster;
 mod line;
+mod path;
 mod run;
 
 pub(crate) mod data;
 
 pub mod cursor;
 
-use super::font::Font;
-use super::style::Brush;
+use super::font::{Font, FontId};
+use super::style::{
+    Brush, Direction, FontFeature, FontPalette, FontStretch, FontStyle, FontWeight,
+    HangingPunctuation, OverflowWrap, PaletteOverride, Shadow, Wrap,
+};
 use core::ops::Range;
 use data::*;
+use peniko::kurbo::{Rect, Size, Vec2};
+use std::collections::BTreeSet;
+use swash::scale::ScaleContext;
+use swash::shape::ShapeContext;
 use swash::text::cluster::{Boundary, ClusterInfo};
-use swash::{GlyphId, NormalizedCoord, Synthesis};
+use swash::{FontRef, GlyphId, NormalizedCoord, Synthesis};
 
-pub use cursor::Cursor;
+pub use baseline::Baseline;
+pub use cursor::{CaretShape, Cursor, GlyphHit, Selection};
 pub use line::greedy::BreakLines;
 pub use line::{GlyphRun, LineMetrics};
+pub use path::{PathSide, PlacedGlyph};
 pub use run::RunMetrics;
 
 /// Alignment of a layout.
-#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+///
+/// The start and end edges are relative to the base direction of each
+/// paragraph, so `Start` aligns right-to-left paragraphs to the right.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Default, Debug)]
 #[repr(u8)]
 pub enum Alignment {
+    #[default]
     Start,
     Middle,
     End,
     Justified,
 }
 
-impl Default for Alignment {
-    fn default() -> Self {
-        Self::Start
+/// Handling of lines beyond the widths given to
+/// [`Layout::break_all_lines_with_widths`].
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub enum LineWidthsOverflow {
+    /// The remaining lines use the last width, or are unbounded if no widths
+    /// are given.
+    #[default]
+    RepeatLast,
+    /// The remaining lines are discarded and the offset of the text that
+    /// did not fit is reported by [`Layout::overflow_offset`].
+    Truncate,
+}
+
+/// Position of the box that lines are aligned within relative to the
+/// origin of a layout.
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub enum AlignmentAnchor {
+    /// The box starts at the origin.
+    #[default]
+    Start,
+    /// The box is centered on the origin.
+    Center,
+    /// The box ends at the origin.
+    End,
+}
+
+/// Marker drawn in place of a whitespace cluster when whitespace is
+/// revealed.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum WhitespaceMarker {
+    /// A space (U+0020).
+    Space,
+    /// A no-break space (U+00A0).
+    NoBreakSpace,
+    /// A horizontal tab.
+    Tab,
+    /// A line break.
+    Newline,
+}
+
+impl WhitespaceMarker {
+    /// Returns a character that conventionally represents the marker: a
+    /// middle dot for a space, a degree sign for a no-break space, an arrow
+    /// for a tab and a pilcrow for a line break.
+    pub fn symbol(self) -> char {
+        match self {
+            Self::Space => '\u{B7}',
+            Self::NoBreakSpace => '\u{B0}',
+            Self::Tab => '\u{2192}',
+            Self::Newline => '\u{B6}',
+        }
     }
 }
 
+/// Side of a text position that is associated with it when the position
+/// lies on a boundary, such as between two differently styled ranges.
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub enum Affinity {
+    /// The position is associated with the preceding text.
+    Upstream,
+    /// The position is associated with the following text.
+    #[default]
+    Downstream,
+}
+
+/// Alignment of the last line of a paragraph.
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub struct LastLineAlignment {
+    /// Alignment of the last line. If `None`, the last line uses the
+    /// alignment of the other lines, except that it is not justified.
+    pub alignment: Option<Alignment>,
+    /// True if lines that end in a forced line break are also treated as
+    /// last lines.
+    pub forced_breaks: bool,
+}
+
 /// Text layout.
 #[derive(Clone)]
 pub struct Layout<B: Brush> {
@@ -58,6 +143,19 @@ impl<B: Brush> Layout<B> {
         &self.data.styles
     }
 
+    /// Returns the base direction of the text, as set with
+    /// [`LayoutContext::set_base_direction`] or determined from its first
+    /// strong character.
+    ///
+    /// [`LayoutContext::set_base_direction`]: crate::LayoutContext::set_base_direction
+    pub fn direction(&self) -> Direction {
+        if self.data.base_level & 1 != 0 {
+            Direction::RightToLeft
+        } else {
+            Direction::LeftToRight
+        }
+    }
+
     /// Returns the width of the layout.
     pub fn width(&self) -> f32 {
         self.data.width
@@ -74,6 +172,192 @@ impl<B: Brush> Layout<B> {
         self.data.height
     }
 
+    /// Returns the distance from the top of the layout to the baseline of
+    /// the first line, for aligning the layout with the baselines of other
+    /// content.
+    ///
+    /// Layouts of empty text have a single line with the metrics of the
+    /// default style. Returns zero if line breaking has not been performed.
+    pub fn first_baseline(&self) -> f32 {
+        self.data
+            .lines
+            .first()
+            .map(|line| line.metrics.baseline)
+            .unwrap_or(0.)
+    }
+
+    /// Returns the distance from the top of the layout to the baseline of
+    /// the last line. See [`first_baseline`](Self::first_baseline).
+    pub fn last_baseline(&self) -> f32 {
+        self.data
+            .lines
+            .last()
+            .map(|line| line.metrics.baseline)
+            .unwrap_or(0.)
+    }
+
+    /// Returns the offset in the source text at which content was cut when
+    /// lines were broken with a maximum height, or `None` if all lines fit.
+    ///
+    /// Laying out the remaining text from this offset continues the content
+    /// in another region.
+    pub fn overflow_offset(&self) -> Option<usize> {
+        self.data.overflow_offset
+    }
+
+    /// Returns the smallest maximum advance at which no unbreakable segment
+    /// of the text overflows. This is the advance of the widest sequence of
+    /// clusters between line break opportunities, ignoring trailing
+    /// whitespace unless it is in a [`Wrap::BreakSpaces`] range. Where [`OverflowWrap`] allows overflowing words to be
+    /// broken, each cluster, or ligature, is a segment of its own.
+    ///
+    /// This does not require line breaking to have been performed.
+    pub fn min_width_without_overflow(&self) -> f32 {
+        let mut min_width = 0f32;
+        let mut width = 0.;
+        let mut trailing_whitespace = 0.;
+        for run in &self.data.runs {
+            for (index, cluster) in self.data.clusters[run.cluster_range.clone()]
+                .iter()
+                .enumerate()
+            {
+                let index = run.cluster_range.start + index;
+                let is_break = index > 0 && self.data.can_break_after(index - 1)
+                    || self.data.allows_emergency_break(index);
+                if is_break {
+                    min_width = min_width.max(width - trailing_whitespace);
+                    width = 0.;
+                    trailing_whitespace = 0.;
+                }
+                width += cluster.advance;
+                if cluster.info.is_whitespace() && !self.data.is_break_spaces(index) {
+                    trailing_whitespace += cluster.advance;
+                } else {
+                    trailing_whitespace = 0.;
+                }
+            }
+        }
+        min_width.max(width - trailing_whitespace)
+    }
+
+    /// Returns an iterator over the segments of text between line break
+    /// opportunities, such as words and their trailing whitespace, in
+    /// logical order. Each segment is paired with the rectangles covering
+    /// it, as returned by [`rects_for_source_range`](Self::rects_for_source_range),
+    /// so a segment that is broken across lines has a rectangle per line.
+    ///
+    /// Line breaking must have been performed.
+    pub fn word_segments(&self) -> impl Iterator<Item = (Range<usize>, Vec<Rect>)> + '_ {
+        let mut bounds = vec![];
+        let mut end = 0;
+        for run in &self.data.runs {
+            for (index, cluster) in self.data.clusters[run.cluster_range.clone()]
+                .iter()
+                .enumerate()
+            {
+                let range = cluster.text_range(run);
+                let index = run.cluster_range.start + index;
+                if index > 0 && self.data.can_break_after(index - 1) && range.start > 0 {
+                    bounds.push(range.start);
+                }
+                end = end.max(range.end);
+            }
+        }
+        bounds.push(end);
+        let mut start = 0;
+        bounds.into_iter().filter_map(move |bound| {
+            let range = start..bound;
+            start = bound;
+            if range.is_empty() {
+                return None;
+            }
+            let rects = self.rects_for_source_range(range.clone());
+            Some((range, rects))
+        })
+    }
+
+    /// Returns the source offset of the last line break opportunity after
+    /// the specified offset at which the preceding content, ignoring
+    /// trailing whitespace outside of [`Wrap::BreakSpaces`] ranges, fits
+    /// within the specified width. A mandatory
+    /// break that fits is returned immediately, and the length of the text
+    /// is returned if all of the remaining content fits.
+    ///
+    /// Returns `None` if the content before the first break opportunity
+    /// does not fit.
+    ///
+    /// This does not require line breaking to have been performed.
+    pub fn break_before_width(&self, start_offset: usize, width: f32) -> Option<usize> {
+        let mut result = None;
+        let mut x = 0.;
+        let mut trailing_whitespace = 0.;
+        let mut is_first = true;
+        for run in &self.data.runs {
+            for (index, cluster) in self.data.clusters[run.cluster_range.clone()]
+                .iter()
+                .enumerate()
+            {
+                let text_start = run.text_range.start + cluster.text_offset as usize;
+                if text_start < start_offset {
+                    continue;
+                }
+                let index = run.cluster_range.start + index;
+                if !is_first && self.data.can_break_after(index - 1) {
+                    let fits = x - trailing_whitespace <= width;
+                    if cluster.info.boundary() == Boundary::Mandatory {
+                        return if fits { Some(text_start) } else { result };
+                    }
+                    if !fits {
+                        return result;
+                    }
+                    result = Some(text_start);
+                }
+                is_first = false;
+                x += cluster.advance;
+                if cluster.info.is_whitespace() && !self.data.is_break_spaces(index) {
+                    trailing_whitespace += cluster.advance;
+                } else {
+                    trailing_whitespace = 0.;
+                }
+            }
+        }
+        if x - trailing_whitespace <= width {
+            Some(self.data.text_len)
+        } else {
+            result
+        }
+    }
+
+    /// Returns the shaping issues recorded while building the layout. This
+    /// is empty unless diagnostics were enabled on the layout context with
+    /// [`LayoutContext::set_diagnostics_enabled`](crate::LayoutContext::set_diagnostics_enabled).
+    pub fn shaping_diagnostics(&self) -> &[ShapingIssue] {
+        &self.data.diagnostics
+    }
+
+    /// Returns the time spent in each phase of building the layout and of
+    /// the last line breaking pass, along with the sizes of the results.
+    #[cfg(feature = "profiling")]
+    pub fn profile(&self) -> &LayoutProfile {
+        &self.data.profile
+    }
+
+    /// Returns the identifiers of the fonts used by the layout along with
+    /// the identifiers of the glyphs that are referenced from each font.
+    /// This is useful for subsetting fonts when embedding them in
+    /// documents. The data of each font is available from
+    /// [`FontContext::font`](crate::FontContext::font).
+    pub fn used_fonts(&self) -> Vec<(FontId, GlyphSet)> {
+        let mut sets = vec![GlyphSet::new(); self.data.fonts.len()];
+        for run in self.runs() {
+            let set = &mut sets[run.data.font_index];
+            for cluster in run.clusters() {
+                set.extend(cluster.glyphs().map(|glyph| glyph.id));
+            }
+        }
+        self.data.fonts.iter().map(Font::id).zip(sets).collect()
+    }
+
     /// Returns the number of lines in the layout.
     pub fn len(&self) -> usize {
         self.data.lines.len()
@@ -100,6 +384,294 @@ impl<B: Brush> Layout<B> {
         })
     }
 
+    /// Returns the bounds of the ink of the glyphs in the layout, including
+    /// the shadows of their styles.
+    ///
+    /// Each shadow covers the bounds of the glyph outline displaced by the
+    /// shadow offset and expanded by its blur radius. Synthesized oblique and
+    /// stretched styles are applied to the outlines. Glyphs without outlines,
+    /// such as spaces, are ignored, and an empty layout has empty bounds.
+    /// Line breaking must have been performed. The outlines are scaled with
+    /// the specified context, which can be reused across calls.
+    pub fn ink_bounds(&self, scx: &mut ScaleContext) -> Rect {
+        let mut bounds: Option<Rect> = None;
+        for line in self.lines() {
+            for glyph_run in line.glyph_runs() {
+                let run = glyph_run.run();
+                let (shear, scale) = (run.oblique_shear(), run.stretch_scale());
+                let mut scaler = scx
+                    .builder(run.font().as_ref())
+                    .size(run.font_size())
+                    .normalized_coords(run.normalized_coords())
+                    .build();
+                for glyph in glyph_run.positioned_glyphs() {
+                    let outline = match scaler.scale_outline(glyph.id) {
+                        Some(outline) => outline.bounds(),
+                        None => continue,
+                    };
+                    if outline.min.x >= outline.max.x {
+                        continue;
+                    }
+                    // Outlines are y-up with the origin on the baseline.
+                    let (mut x0, mut x1) = (f32::MAX, f32::MIN);
+                    for x in [outline.min.x, outline.max.x] {
+                        for y in [outline.min.y, outline.max.y] {
+                            let x = x * scale + shear * y;
+                            x0 = x0.min(x);
+                            x1 = x1.max(x);
+                        }
+                    }
+                    let ink = Rect::new(
+                        (glyph.x + x0) as f64,
+                        (glyph.y - outline.max.y) as f64,
+                        (glyph.x + x1) as f64,
+                        (glyph.y - outline.min.y) as f64,
+                    );
+                    let mut rect = ink;
+                    for shadow in glyph_run.shadows() {
+                        let blur = shadow.blur_radius as f64;
+                        let offset = Vec2::new(shadow.x as f64, shadow.y as f64);
+                        rect = rect.union((ink + offset).inflate(blur, blur));
+                    }
+                    bounds = Some(bounds.map_or(rect, |bounds| bounds.union(rect)));
+                }
+            }
+        }
+        bounds.unwrap_or_default()
+    }
+
+    /// Returns an iterator over the clusters, in logical order, that
+    /// overlap the specified range of the source text.
+    ///
+    /// If line breaking has been performed, the clusters are yielded per
+    /// line so a range that spans a line break produces clusters from both
+    /// lines. Otherwise, clusters are yielded from the unbroken runs.
+    pub fn clusters_for_source_range(
+        &self,
+        range: Range<usize>,
+    ) -> impl Iterator<Item = Cluster<'_, B>> + '_ + Clone {
+        let layout = &self.data;
+        let run_range = range.clone();
+        let line_runs = layout
+            .line_runs
+            .iter()
+            .map(move |line_run| (&layout.runs[line_run.run_index], Some(line_run)));
+        let runs = layout
+            .runs
+            .iter()
+            .filter(move |_| layout.lines.is_empty())
+            .map(|run| (run, None));
+        line_runs
+            .chain(runs)
+            .filter(move |(run, line_run)| {
+                let text_range = line_run.map_or(&run.text_range, |d| &d.text_range);
+                text_range.start < run_range.end && run_range.start < text_range.end
+            })
+            .flat_map(move |(data, line_data)| {
+                let run = Run::new(layout, data, line_data);
+                let cluster_range = line_data.map_or(&data.cluster_range, |d| &d.cluster_range);
+                cluster_range.clone().map(move |index| Cluster {
+                    run: run.clone(),
+                    index,
+                    data: &layout.clusters[index],
+                })
+            })
+            .filter(move |cluster| {
+                let text_range = cluster.text_range();
+                text_range.start < range.end && range.start < text_range.end
+            })
+    }
+
+    /// Returns the source offset following the first `count` clusters of
+    /// the layout in logical order, or the length of the text if the layout
+    /// has fewer clusters.
+    ///
+    /// Clusters correspond to grapheme clusters, so this maps the number of
+    /// revealed characters in a typewriter effect to a source offset.
+    pub fn cluster_count_to_offset(&self, count: usize) -> usize {
+        self.runs()
+            .flat_map(|run| {
+                let data = run.data;
+                self.data.clusters[data.cluster_range.clone()]
+                    .iter()
+                    .map(move |cluster| cluster.text_range(data).start)
+            })
+            .nth(count)
+            .unwrap_or(self.data.text_len)
+    }
+
+    /// Returns the bounds of the first `count` clusters of the layout in
+    /// logical order, spanning each line that contains any of them.
+    ///
+    /// Together with [`cluster_count_to_offset`](Self::cluster_count_to_offset)
+    /// and [`Cursor::from_position`], this provides the visible geometry
+    /// of a partially revealed layout. Line breaking must have been
+    /// performed.
+    pub fn visible_prefix_bounds(&self, count: usize) -> Rect {
+        let end = self.cluster_count_to_offset(count);
+        let mut bounds: Option<Rect> = None;
+        for line in self.lines() {
+            if line.text_range().start >= end {
+                break;
+            }
+            let metrics = line.metrics();
+            let (top, bottom) = metrics.extent();
+            let mut x = metrics.offset;
+            let (mut min_x, mut max_x) = (f32::MAX, f32::MIN);
+            for run in line.runs() {
+                for cluster in run.visual_clusters() {
+                    let advance = cluster.advance();
+                    if cluster.text_range().end <= end {
+                        min_x = min_x.min(x);
+                        max_x = max_x.max(x + advance);
+                    }
+                    x += advance;
+                }
+            }
+            if min_x > max_x {
+                min_x = metrics.offset;
+                max_x = metrics.offset;
+            }
+            let rect = Rect::new(min_x as f64, top as f64, max_x as f64, bottom as f64);
+            bounds = Some(bounds.map_or(rect, |bounds| bounds.union(rect)));
+        }
+        bounds.unwrap_or_default()
+    }
+
+    /// Returns the rectangles covering the clusters of the specified source
+    /// range, such as for the hover region of a link.
+    ///
+    /// Visually adjacent clusters on a line are merged into a single
+    /// rectangle, so a range that wraps produces one rectangle per line and
+    /// a range that is split by bidi reordering produces disjoint
+    /// rectangles. Each rectangle covers the full height of its line.
+    /// Line breaking must have been performed.
+    pub fn rects_for_source_range(&self, range: Range<usize>) -> Vec<Rect> {
+        let mut rects = vec![];
+        if range.is_empty() {
+            return rects;
+        }
+        for line in self.lines() {
+            let line_range = line.text_range();
+            if line_range.end <= range.start || line_range.start >= range.end {
+                continue;
+            }
+            let metrics = line.metrics();
+            let (top, bottom) = metrics.extent();
+            let (top, bottom) = (top as f64, bottom as f64);
+            let mut x = metrics.offset;
+            let mut span: Option<(f32, f32)> = None;
+            for run in line.runs() {
+                for cluster in run.visual_clusters() {
+                    let advance = cluster.advance();
+                    let cluster_range = cluster.text_range();
+                    if cluster_range.start < range.end && range.start < cluster_range.end {
+                        span =
+                            Some(span.map_or((x, x + advance), |(start, _)| (start, x + advance)));
+                    } else if let Some((start, end)) = span.take() {
+                        rects.push(Rect::new(start as f64, top, end as f64, bottom));
+                    }
+                    x += advance;
+                }
+            }
+            if let Some((start, end)) = span {
+                rects.push(Rect::new(start as f64, top, end as f64, bottom));
+            }
+        }
+        rects
+    }
+
+    /// Returns the ruby annotations attached with
+    /// [`push_ruby`](Self::push_ruby), positioned above their bases.
+    ///
+    /// Line breaking must have been performed.
+    pub fn ruby_annotations(&self) -> impl Iterator<Item = RubyAnnotation<'_, B>> + Clone {
+        self.data.rubies.iter().filter_map(move |ruby| {
+            let line = self
+                .lines()
+                .find(|line| line.text_range().contains(&ruby.text_range.start))?;
+            let base = *self
+                .rects_for_source_range(ruby.text_range.clone())
+                .first()?;
+            let annotation = &ruby.annotation;
+            let width = annotation.width();
+            Some(RubyAnnotation {
+                text_range: ruby.text_range.clone(),
+                layout: annotation,
+                x: base.x0 as f32 + (base.width() as f32 - width) * 0.5,
+                y: line.metrics().baseline - self.data.ruby_base_ascent(ruby) - annotation.height(),
+            })
+        })
+    }
+
+    /// Returns the individual glyph at the specified point, such as for a
+    /// glyph inspector. See [`GlyphHit::from_point`] for details.
+    pub fn hit_test_glyph(&self, scx: &mut ScaleContext, x: f32, y: f32) -> Option<GlyphHit> {
+        GlyphHit::from_point(self, scx, x, y)
+    }
+
+    /// Returns the caret rectangle for the specified source offset. This is
+    /// the caret of the cursor returned by [`Cursor::from_offset`]; see
+    /// [`Cursor::caret_rect`] for the geometry.
+    pub fn caret_for_offset(&self, offset: usize, affinity: Affinity) -> Rect {
+        Cursor::from_offset(self, offset, affinity).caret_rect(self)
+    }
+
+    /// Returns the rectangle of a caret of the specified width and shape for
+    /// the specified source offset. This is the caret of the cursor returned
+    /// by [`Cursor::from_offset`]; see [`Cursor::caret_rect_with_shape`] for
+    /// the geometry.
+    pub fn caret_for_offset_with_shape(
+        &self,
+        offset: usize,
+        affinity: Affinity,
+        width: f32,
+        shape: CaretShape,
+    ) -> Rect {
+        Cursor::from_offset(self, offset, affinity).caret_rect_with_shape(self, width, shape)
+    }
+
+    /// Returns the style of the text at the specified source offset, such
+    /// as for indicating the formatting at the caret.
+    ///
+    /// At the boundary between two styled ranges, the affinity selects the
+    /// style of the preceding or following text. The style of the last
+    /// range is returned for offsets at or beyond the end of the text.
+    pub fn style_at(&self, offset: usize, affinity: Affinity) -> Option<&Style<B>> {
+        let offset = if affinity == Affinity::Upstream && offset > 0 {
+            offset - 1
+        } else {
+            offset
+        };
+        self.data
+            .styles
+            .iter()
+            .find(|style| style.text_range.contains(&offset))
+            .or_else(|| self.data.styles.last())
+    }
+
+    /// Returns a summary of the styles of the text in the specified source
+    /// range, reporting for each property whether it is uniform or mixed.
+    ///
+    /// An empty range is summarized by the style that
+    /// [`style_at`](Self::style_at) returns for its start with downstream
+    /// affinity.
+    pub fn style_summary(&self, range: Range<usize>) -> Option<StyleSummary<B>> {
+        if range.is_empty() {
+            return self
+                .style_at(range.start, Affinity::Downstream)
+                .map(StyleSummary::new);
+        }
+        let mut styles = self.data.styles.iter().filter(|style| {
+            style.text_range.start < range.end && range.start < style.text_range.end
+        });
+        let mut summary = StyleSummary::new(styles.next()?);
+        for style in styles {
+            summary.add(style);
+        }
+        Some(summary)
+    }
+
     /// Returns line breaker to compute lines for the layout.
     pub fn break_lines(&mut self) -> BreakLines<B> {
         BreakLines::new(&mut self.data)
@@ -111,6 +683,282 @@ impl<B: Brush> Layout<B> {
             .break_remaining(max_advance.unwrap_or(f32::MAX), alignment)
     }
 
+    /// Breaks all lines with the specified maximum advance and alignment,
+    /// using a separate alignment for the last line.
+    pub fn break_all_lines_with_last_line(
+        &mut self,
+        max_advance: Option<f32>,
+        alignment: Alignment,
+        last_line_alignment: LastLineAlignment,
+    ) {
+        let mut breaker = self.break_lines();
+        breaker.set_last_line_alignment(last_line_alignment);
+        breaker.break_remaining(max_advance.unwrap_or(f32::MAX), alignment)
+    }
+
+    /// Breaks all lines with the specified maximum advance and alignment,
+    /// keeping only the lines that fit within the maximum height.
+    pub fn break_all_lines_within(
+        &mut self,
+        max_advance: Option<f32>,
+        max_height: f32,
+        alignment: Alignment,
+    ) {
+        self.break_lines().break_remaining_within(
+            max_advance.unwrap_or(f32::MAX),
+            max_height,
+            alignment,
+        )
+    }
+
+    /// Breaks all lines with the specified alignment, using the widths in
+    /// order as the maximum advances of successive lines, such as to fill a
+    /// shape with known ragged edges.
+    pub fn break_all_lines_with_widths(
+        &mut self,
+        widths: &[f32],
+        overflow: LineWidthsOverflow,
+        alignment: Alignment,
+    ) {
+        self.break_lines()
+            .break_remaining_with_widths(widths, overflow, alignment)
+    }
+
+    /// Breaks all lines with a hanging indent, treating the first
+    /// `marker_len` bytes of the text as a marker, such as the bullet or
+    /// number of a list item, followed by the body.
+    ///
+    /// The marker occupies a region at the start of the first line that is
+    /// at least `marker_width` wide, with the marker placed at the end of
+    /// the region so that the body begins where the region ends. Subsequent
+    /// lines are indented by the width of the region so that the body
+    /// aligns under its first line. Using the same marker width for the
+    /// items of a list aligns their bodies even if the markers differ in
+    /// width. The marker should include any space that separates it from
+    /// the body.
+    ///
+    /// The indent is applied at the left edge, so this is intended for
+    /// left-to-right paragraphs. To build a layout from separate marker and
+    /// body texts, use
+    /// [`LayoutContext::layout_with_marker`](crate::LayoutContext::layout_with_marker).
+    pub fn break_all_lines_with_marker(
+        &mut self,
+        marker_len: usize,
+        marker_width: f32,
+        max_advance: Option<f32>,
+        alignment: Alignment,
+    ) {
+        let mut marker_advance = 0.;
+        for run in self.runs() {
+            for cluster in run.clusters() {
+                if cluster.text_range().end <= marker_len {
+                    marker_advance += cluster.advance();
+                }
+            }
+        }
+        let indent = marker_width.max(marker_advance);
+        let marker_offset = indent - marker_advance;
+        let max_advance = max_advance.unwrap_or(f32::MAX);
+        let widths = [max_advance - marker_offset, max_advance - indent];
+        self.break_all_lines_with_widths(&widths, LineWidthsOverflow::RepeatLast, alignment);
+        let mut width = 0f32;
+        let mut full_width = 0f32;
+        for (index, line) in self.data.lines.iter_mut().enumerate() {
+            let shift = if index == 0 { marker_offset } else { indent };
+            line.metrics.offset += shift;
+            width = width.max(
+                shift + line.metrics.advance - line.metrics.trailing_whitespace - line.hanging,
+            );
+            full_width = full_width.max(shift + line.metrics.advance);
+        }
+        self.data.width = width;
+        self.data.full_width = full_width;
+    }
+
+    /// Breaks all lines with the specified maximum advance and alignment,
+    /// invoking the callback with each line as soon as it has been computed.
+    ///
+    /// Lines are aligned before they are passed to the callback, so their
+    /// metrics and glyph runs are final. This allows rendering to begin
+    /// before the remainder of a large layout has been broken.
+    pub fn break_all_lines_streaming(
+        &mut self,
+        max_advance: Option<f32>,
+        alignment: Alignment,
+        f: impl FnMut(Line<B>),
+    ) {
+        self.break_lines()
+            .break_remaining_streaming(max_advance.unwrap_or(f32::MAX), alignment, f)
+    }
+
+    /// Breaks all lines with a new maximum advance, retaining the alignment
+    /// from the previous line breaking pass.
+    ///
+    /// Shaping results are reused, so this is much cheaper than rebuilding
+    /// the layout when only the available width has changed.
+    pub fn reflow(&mut self, max_advance: Option<f32>) {
+        let (alignment, last_line_alignment) = self
+            .data
+            .lines
+            .first()
+            .map(|line| (line.alignment, line.last_line_alignment))
+            .unwrap_or_default();
+        self.break_all_lines_with_last_line(max_advance, alignment, last_line_alignment);
+    }
+
+    /// Returns the size of the layout when broken at each of the specified
+    /// maximum advances, retaining the alignment from the previous line
+    /// breaking pass as in [`reflow`](Self::reflow).
+    ///
+    /// Shaping results are reused for every width, so this is much cheaper
+    /// than building a layout for each candidate width. The lines are left
+    /// broken at the last width.
+    pub fn measure_at_widths(&mut self, widths: &[f32]) -> Vec<Size> {
+        widths
+            .iter()
+            .map(|width| {
+                self.reflow(Some(*width));
+                Size::new(self.width() as f64, self.height() as f64)
+            })
+            .collect()
+    }
+
+    /// Realigns the lines within a box of the specified width, positioned
+    /// relative to the origin by the anchor, rather than within the maximum
+    /// advance used for line breaking.
+    ///
+    /// Each line keeps the alignment it was broken with, so middle aligned
+    /// lines in a box centered on the origin are centered around `x = 0`
+    /// and extend to negative positions. Unlike alignment during line
+    /// breaking, lines wider than the box are aligned as well and overflow
+    /// it on the side opposite to the alignment. Justified lines keep their
+    /// justification and are placed at the start of the box.
+    ///
+    /// This must be called again after line breaking.
+    pub fn align_to(&mut self, width: f32, anchor: AlignmentAnchor) {
+        let start = match anchor {
+            AlignmentAnchor::Start => 0.,
+            AlignmentAnchor::Center => width * -0.5,
+            AlignmentAnchor::End => -width,
+        };
+        for line in &mut self.data.lines {
+            let extra = width - line.aligned_advance;
+            let offset = match line.effective_alignment().0 {
+                Alignment::Middle => start + extra * 0.5,
+                Alignment::End => start + extra,
+                _ => start,
+            };
+            line.metrics.offset += offset - line.alignment_offset;
+            line.alignment_offset = offset;
+        }
+    }
+
+    /// Moves the baseline of each line down to the nearest position of the
+    /// form `offset + pitch * n`, so that lines align to a baseline grid.
+    ///
+    /// Baselines are only ever moved down, and each line is shifted by at
+    /// least as much as the line above it, so lines never overlap and the
+    /// spacing between lines is a whole multiple of the pitch. The height
+    /// of the layout is updated accordingly.
+    ///
+    /// This must be called again after line breaking.
+    pub fn snap_to_baseline_grid(&mut self, pitch: f32, offset: f32) {
+        if pitch <= 0. || !pitch.is_finite() {
+            return;
+        }
+        let mut shift = 0.;
+        let mut height = 0.;
+        for line in &mut self.data.lines {
+            let baseline = line.metrics.baseline + shift;
+            let snapped = offset + ((baseline - offset) / pitch).ceil() * pitch;
+            shift = snapped - line.metrics.baseline;
+            line.metrics.baseline = snapped;
+            let below = (line.metrics.descent + line.metrics.leading * 0.5).round();
+            height = snapped + below;
+        }
+        self.data.height = height;
+    }
+
+    /// Shapes the words containing ligatures that span any of the specified
+    /// source offsets again with ligatures disabled, so that the text on
+    /// either side of each offset, such as the bounds of a search match
+    /// within a word, can be drawn in a different style.
+    ///
+    /// Each word is shaped as a whole with the font, features and
+    /// variations of its run, so kerning and cursive joining within the
+    /// word are kept. Required ligatures (`rlig`) are not affected.
+    ///
+    /// Line breaking must be performed again after splitting ligatures.
+    pub fn split_ligatures_at(&mut self, offsets: &[usize]) {
+        self.data
+            .split_ligatures_at(&mut ShapeContext::new(), offsets);
+    }
+
+    /// Attaches a ruby annotation, such as furigana, to the clusters
+    /// covering the specified base range of the source text.
+    ///
+    /// The annotation is a separately built layout, typically of the same
+    /// text style at a smaller font size. It is broken into a single line
+    /// if line breaking has not been performed on it. If the annotation is
+    /// wider than its base, space is added on either side of the base so
+    /// that the base is centered under the annotation; otherwise the
+    /// annotation is centered over the base. Lines are never broken within
+    /// the base, and the ascent of a line containing a base grows to make
+    /// room for the annotation above it. An annotation replaces any
+    /// previously attached annotations with bases that overlap its base.
+    /// The positioned annotations are available from
+    /// [`ruby_annotations`](Self::ruby_annotations).
+    ///
+    /// Line breaking must be performed again after pushing annotations.
+    pub fn push_ruby(&mut self, base: Range<usize>, mut annotation: Layout<B>) {
+        let data = &mut self.data;
+        let mut cluster_range: Option<Range<usize>> = None;
+        let mut text_range = base.clone();
+        for run in &data.runs {
+            for (index, cluster) in data.clusters[run.cluster_range.clone()].iter().enumerate() {
+                let range = cluster.text_range(run);
+                if range.start < base.end && base.start < range.end {
+                    let index = run.cluster_range.start + index;
+                    cluster_range = Some(match cluster_range {
+                        Some(clusters) => clusters.start.min(index)..clusters.end.max(index + 1),
+                        None => index..index + 1,
+                    });
+                    text_range.start = text_range.start.min(range.start);
+                    text_range.end = text_range.end.max(range.end);
+                }
+            }
+        }
+        let cluster_range = match cluster_range {
+            Some(cluster_range) => cluster_range,
+            None => return,
+        };
+        if annotation.data.lines.is_empty() {
+            annotation.break_all_lines(None, Alignment::Start);
+        }
+        data.insert_ruby(RubyData {
+            text_range,
+            cluster_range,
+            padding: 0.,
+            annotation,
+        });
+    }
+
+    /// Appends the lines of another layout, such as a separately built
+    /// paragraph, below the existing lines of this layout.
+    ///
+    /// The existing lines are left untouched and the appended lines are
+    /// positioned below them. Source ranges of the appended content are
+    /// offset by `text_offset`, which is typically the running length of
+    /// the text that has already been laid out.
+    ///
+    /// Breaking lines again treats the combined runs as a single
+    /// paragraph, so the other layout should already have been broken.
+    ///
+    /// Panics if the combined layouts have more than 65536 distinct styles.
+    pub fn append(&mut self, other: Layout<B>, text_offset: usize) {
+        self.data.append(other.data, text_offset);
+    }
+
     /// Returns an iterator over the runs in the layout.
     pub fn runs(&self) -> impl Iterator<Item = Run<B>> + '_ + Clone {
         self.data.runs.iter().map(move |data| Run {
@@ -121,6 +969,56 @@ impl<B: Brush> Layout<B> {
     }
 }
 
+impl<B: Brush> Layout<B> {
+    /// Creates a layout by stacking the specified paragraphs vertically.
+    ///
+    /// Source offsets of each paragraph are offset by the total text length
+    /// of the preceding paragraphs, so the text of each paragraph should
+    /// include its trailing separator, if any.
+    pub fn from_paragraphs(paragraphs: impl IntoIterator<Item = ParagraphLayout<B>>) -> Self {
+        let mut layout = Self::default();
+        let mut text_offset = 0;
+        for paragraph in paragraphs {
+            let text_len = paragraph.text_len();
+            layout.append(paragraph.layout, text_offset);
+            text_offset += text_len;
+        }
+        layout
+    }
+}
+
+/// Shaped and broken layout of a single paragraph that can be cached and
+/// later combined with other paragraphs using [`Layout::from_paragraphs`].
+///
+/// Source offsets are relative to the start of the paragraph.
+#[derive(Clone)]
+pub struct ParagraphLayout<B: Brush> {
+    layout: Layout<B>,
+}
+
+impl<B: Brush> ParagraphLayout<B> {
+    /// Creates a paragraph from a layout on which line breaking has been
+    /// performed.
+    pub fn new(layout: Layout<B>) -> Self {
+        Self { layout }
+    }
+
+    /// Returns the layout of the paragraph.
+    pub fn layout(&self) -> &Layout<B> {
+        &self.layout
+    }
+
+    /// Returns the length of the text of the paragraph.
+    pub fn text_len(&self) -> usize {
+        self.layout.data.text_len
+    }
+
+    /// Returns the height of the paragraph.
+    pub fn height(&self) -> f32 {
+        self.layout.height()
+    }
+}
+
 impl<B: Brush> Default for Layout<B> {
     fn default() -> Self {
         Self {
@@ -129,6 +1027,44 @@ impl<B: Brush> Default for Layout<B> {
     }
 }
 
+/// Returns the distance by which to scroll a viewport, given in layout
+/// coordinates, so that the target rectangle, such as a caret from
+/// [`Layout::caret_for_offset`] or a rectangle of a selection, is visible
+/// with the specified margin around it.
+///
+/// The distance is zero along an axis on which the target is already
+/// visible. A target that is taller or wider than the viewport is aligned
+/// to its top or left edge.
+pub fn scroll_to_make_visible(target: Rect, viewport: Rect, margin: f64) -> Vec2 {
+    let target = target.inflate(margin, margin);
+    let axis = |start: f64, end: f64, view_start: f64, view_end: f64| {
+        if end - start > view_end - view_start || start < view_start {
+            start - view_start
+        } else if end > view_end {
+            end - view_end
+        } else {
+            0.
+        }
+    };
+    Vec2::new(
+        axis(target.x0, target.x1, viewport.x0, viewport.x1),
+        axis(target.y0, target.y1, viewport.y0, viewport.y1),
+    )
+}
+
+/// Ruby annotation positioned above its base.
+#[derive(Clone)]
+pub struct RubyAnnotation<'a, B: Brush> {
+    /// Range of source text covered by the base.
+    pub text_range: Range<usize>,
+    /// Layout of the annotation.
+    pub layout: &'a Layout<B>,
+    /// Horizontal position of the origin of the annotation layout.
+    pub x: f32,
+    /// Vertical position of the origin of the annotation layout.
+    pub y: f32,
+}
+
 /// Sequence of clusters with a single font and style.
 #[derive(Copy, Clone)]
 pub struct Run<'a, B: Brush> {
@@ -141,14 +1077,27 @@ pub struct Run<'a, B: Brush> {
 #[derive(Copy, Clone)]
 pub struct Cluster<'a, B: Brush> {
     run: Run<'a, B>,
+    index: usize,
     data: &'a ClusterData,
 }
 
+/// Identifier for a line, stable across layouts with the same content.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct LineId(u64);
+
+/// Identifier for a run, stable across layouts with the same content.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct RunId(u64);
+
+/// Set of glyph identifiers used from a font.
+pub type GlyphSet = BTreeSet<GlyphId>;
+
 /// Glyph with an offset and advance.
 #[derive(Copy, Clone, Default, Debug)]
 pub struct Glyph {
     pub id: GlyphId,
     pub style_index: u16,
+    pub(crate) source_offset: u8,
     pub x: f32,
     pub y: f32,
     pub advance: f32,
@@ -159,6 +1108,19 @@ impl Glyph {
     pub fn style_index(&self) -> usize {
         self.style_index as usize
     }
+
+    /// Returns the byte offset of the source character that produced the
+    /// glyph, relative to the start of the text range of its cluster.
+    ///
+    /// This distinguishes the glyphs of a cluster built from several
+    /// characters, such as a base with combining marks. Glyphs produced by
+    /// decomposing a single character share its offset, and a ligature
+    /// glyph has the offset of its first component. Glyphs of runs shaped
+    /// with [`LayoutContext::shape_run`](crate::LayoutContext::shape_run)
+    /// always have an offset of zero.
+    pub fn source_offset(&self) -> usize {
+        self.source_offset as usize
+    }
 }
 
 /// Line in a text layout.
@@ -173,12 +1135,204 @@ pub struct Line<'a, B: Brush> {
 pub struct Style<B: Brush> {
     /// Brush for drawing glyphs.
     pub brush: B,
+    /// Color palette for drawing color glyphs. Use
+    /// [`palette_color`](Self::palette_color) to obtain the colors of glyph
+    /// layers, including overrides.
+    pub font_palette: FontPalette,
+    /// Replacements for entries of the color palette.
+    pub font_palette_overrides: Vec<PaletteOverride>,
     /// Underline decoration.
     pub underline: Option<Decoration<B>>,
     /// Strikethrough decoration.
     pub strikethrough: Option<Decoration<B>>,
+    /// Shadows drawn beneath the glyphs.
+    pub text_shadow: Vec<Shadow<B>>,
+    /// Opaque user data specified by [`StyleProperty::UserData`](crate::style::StyleProperty::UserData).
+    pub user_data: u64,
+    /// Resolved font size.
+    pub font_size: f32,
+    /// Font weight.
+    pub font_weight: FontWeight,
+    /// Font style.
+    pub font_style: FontStyle,
+    /// Font width.
+    pub font_stretch: FontStretch,
+    /// Range of the source text with this style.
+    pub(crate) text_range: Range<usize>,
     /// Multiplicative line height factor.
     pub(crate) line_height: f32,
+    /// Line wrapping behavior.
+    pub(crate) wrap: Wrap,
+    /// Breaking of words that do not fit on a line.
+    pub(crate) overflow_wrap: OverflowWrap,
+    /// Hanging punctuation.
+    pub(crate) hanging_punctuation: HangingPunctuation,
+    /// Optical alignment of line edges.
+    pub(crate) optical_alignment: bool,
+}
+
+impl<B: Brush> Style<B> {
+    /// Returns the color of the specified entry of the palette selected from
+    /// the font, with overrides applied.
+    ///
+    /// Returns `None` if the entry is neither overridden nor present in the
+    /// palette, such as for the index that refers to the text color, in which
+    /// case the brush should be used.
+    pub fn palette_color(&self, font: &FontRef, index: u16) -> Option<[u8; 4]> {
+        if let Some(entry) = self
+            .font_palette_overrides
+            .iter()
+            .rev()
+            .find(|entry| entry.index == index)
+        {
+            return Some(entry.color);
+        }
+        let palette = self.font_palette.select(font)?;
+        if index < palette.len() {
+            Some(palette.get(index))
+        } else {
+            None
+        }
+    }
+}
+
+/// Value of a style property over a range of text.
+#[derive(Clone, PartialEq, Debug)]
+pub enum PropertyValue<T> {
+    /// The property has the same value for all of the text.
+    Uniform(T),
+    /// The property has different values within the text.
+    Mixed,
+}
+
+impl<T: PartialEq> PropertyValue<T> {
+    /// Returns the value if it is uniform.
+    pub fn uniform(&self) -> Option<&T> {
+        match self {
+            Self::Uniform(value) => Some(value),
+            Self::Mixed => None,
+        }
+    }
+
+    /// Returns true if the property has different values.
+    pub fn is_mixed(&self) -> bool {
+        matches!(self, Self::Mixed)
+    }
+
+    fn add(&mut self, value: T) {
+        if self.uniform() != Some(&value) {
+            *self = Self::Mixed;
+        }
+    }
+}
+
+/// Summary of the styles over a range of text, such as for the state of a
+/// formatting toolbar.
+#[derive(Clone, PartialEq, Debug)]
+pub struct StyleSummary<B: Brush> {
+    /// Brush for drawing glyphs.
+    pub brush: PropertyValue<B>,
+    /// Resolved font size.
+    pub font_size: PropertyValue<f32>,
+    /// Font weight.
+    pub font_weight: PropertyValue<FontWeight>,
+    /// Font style.
+    pub font_style: PropertyValue<FontStyle>,
+    /// Font width.
+    pub font_stretch: PropertyValue<FontStretch>,
+    /// True if the text is underlined.
+    pub underline: PropertyValue<bool>,
+    /// True if the text is struck through.
+    pub strikethrough: PropertyValue<bool>,
+    /// Opaque user data.
+    pub user_data: PropertyValue<u64>,
+}
+
+impl<B: Brush> StyleSummary<B> {
+    fn new(style: &Style<B>) -> Self {
+        Self {
+            brush: PropertyValue::Uniform(style.brush.clone()),
+            font_size: PropertyValue::Uniform(style.font_size),
+            font_weight: PropertyValue::Uniform(style.font_weight),
+            font_style: PropertyValue::Uniform(style.font_style),
+            font_stretch: PropertyValue::Uniform(style.font_stretch),
+            underline: PropertyValue::Uniform(style.underline.is_some()),
+            strikethrough: PropertyValue::Uniform(style.strikethrough.is_some()),
+            user_data: PropertyValue::Uniform(style.user_data),
+        }
+    }
+
+    fn add(&mut self, style: &Style<B>) {
+        self.brush.add(style.brush.clone());
+        self.font_size.add(style.font_size);
+        self.font_weight.add(style.font_weight);
+        self.font_style.add(style.font_style);
+        self.font_stretch.add(style.font_stretch);
+        self.underline.add(style.underline.is_some());
+        self.strikethrough.add(style.strikethrough.is_some());
+        self.user_data.add(style.user_data);
+    }
+}
+
+/// Time spent in the phases of computing a layout, reported by
+/// [`Layout::profile`] when the `profiling` feature is enabled.
+#[cfg(feature = "profiling")]
+#[derive(Copy, Clone, Default, Debug)]
+pub struct LayoutProfile {
+    /// Analysis of the characters of the text, bidi resolution and
+    /// resolution of the styles, including the analysis performed when the
+    /// builder is created.
+    pub analysis: std::time::Duration,
+    /// Itemization of the text into runs of uniform style and script,
+    /// font selection and shaping.
+    pub shaping: std::time::Duration,
+    /// Line breaking.
+    pub line_breaking: std::time::Duration,
+    /// Computation of line metrics and alignment.
+    pub alignment: std::time::Duration,
+    /// Number of runs.
+    pub runs: usize,
+    /// Number of glyphs.
+    pub glyphs: usize,
+    /// Number of lines.
+    pub lines: usize,
+}
+
+#[cfg(feature = "profiling")]
+impl LayoutProfile {
+    pub(crate) fn add(&mut self, other: &Self) {
+        self.analysis += other.analysis;
+        self.shaping += other.shaping;
+        self.line_breaking += other.line_breaking;
+        self.alignment += other.alignment;
+        self.runs += other.runs;
+        self.glyphs += other.glyphs;
+        self.lines += other.lines;
+    }
+}
+
+/// Problem encountered while shaping a cluster of text.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ShapingIssue {
+    /// Range of the source text for the cluster.
+    pub text_range: Range<usize>,
+    /// First character of the cluster.
+    pub ch: char,
+    /// Names of the font families that were searched, including fallbacks.
+    pub families: Vec<String>,
+    /// Kind of the issue.
+    pub kind: ShapingIssueKind,
+}
+
+/// Kind of a shaping issue.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ShapingIssueKind {
+    /// None of the fonts in the font stack or the fallback fonts for the
+    /// script contain a glyph for the cluster.
+    FallbackExhausted,
+    /// No fallback fonts are available for the script of the cluster, and
+    /// the fonts in the font stack do not contain a glyph for it.
+    UnsupportedScript,
 }
 
 /// Underline or strikethrough decoration.
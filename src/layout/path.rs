@@ -0,0 +1,115 @@
+//! Placement of glyphs along a path.
+
+use super::*;
+use peniko::kurbo::{
+    Affine, BezPath, ParamCurve, ParamCurveArclen, ParamCurveDeriv, PathSeg, Point, Vec2,
+};
+
+/// Accuracy of arc length computations, in layout units.
+const ACCURACY: f64 = 1e-3;
+
+/// Side of a path on which text placed along it is drawn.
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub enum PathSide {
+    /// The text follows the direction of the path, so it is drawn on the
+    /// left side of the path, which is above a path running from left to
+    /// right.
+    #[default]
+    Left,
+    /// The text follows the reversed path, so it is drawn on the right side
+    /// of the path.
+    Right,
+}
+
+/// Glyph positioned along a path.
+#[derive(Copy, Clone, Debug)]
+pub struct PlacedGlyph {
+    /// The glyph, with offsets and advance as in the layout.
+    pub glyph: Glyph,
+    /// Transform from the coordinate space of the outline of the glyph to
+    /// the coordinate space of the path, including the offsets of the
+    /// glyph.
+    pub transform: Affine,
+}
+
+impl<B: Brush> Layout<B> {
+    /// Places the glyphs of the layout along the specified path, with the
+    /// baseline of the first line following the path.
+    ///
+    /// The glyphs are placed in visual order, starting at the specified
+    /// distance along the path. Each glyph is positioned at the distance of
+    /// the center of its advance and rotated to the tangent of the path at
+    /// that point, as for an SVG `textPath`. Offsets between lines and the
+    /// vertical offsets of glyphs are applied perpendicular to the path.
+    /// Glyphs whose center lies before the start or beyond the end of the
+    /// path are dropped.
+    ///
+    /// Line breaking must have been performed.
+    pub fn place_on_path(
+        &self,
+        path: &BezPath,
+        start_offset: f64,
+        side: PathSide,
+    ) -> Vec<PlacedGlyph> {
+        let mut segments: Vec<(PathSeg, f64)> = path
+            .segments()
+            .map(|segment| (segment, segment.arclen(ACCURACY)))
+            .collect();
+        if side == PathSide::Right {
+            segments.reverse();
+            for (segment, _) in &mut segments {
+                *segment = segment.reverse();
+            }
+        }
+        let mut placed = vec![];
+        let first_baseline = match self.lines().next() {
+            Some(line) => line.metrics().baseline,
+            None => return placed,
+        };
+        for line in self.lines() {
+            let line_offset = (line.metrics().baseline - first_baseline) as f64;
+            for run in line.glyph_runs() {
+                let mut pen = run.offset() as f64;
+                for glyph in run.glyphs() {
+                    let advance = glyph.advance as f64;
+                    let distance = start_offset + pen + advance * 0.5;
+                    pen += advance;
+                    let (point, tangent) = match point_at(&segments, distance) {
+                        Some(point) => point,
+                        None => continue,
+                    };
+                    let transform = Affine::translate(point.to_vec2())
+                        * Affine::rotate(tangent.atan2())
+                        * Affine::translate((
+                            glyph.x as f64 - advance * 0.5,
+                            glyph.y as f64 + line_offset,
+                        ));
+                    placed.push(PlacedGlyph { glyph, transform });
+                }
+            }
+        }
+        placed
+    }
+}
+
+/// Returns the point and tangent at the specified distance along the
+/// segments, or `None` if the distance is outside the path.
+fn point_at(segments: &[(PathSeg, f64)], mut distance: f64) -> Option<(Point, Vec2)> {
+    if distance < 0. {
+        return None;
+    }
+    for (segment, len) in segments {
+        if distance > *len {
+            distance -= len;
+            continue;
+        }
+        let t = segment.inv_arclen(distance, ACCURACY);
+        let tangent = match segment {
+            PathSeg::Line(line) => line.deriv().eval(t),
+            PathSeg::Quad(quad) => quad.deriv().eval(t),
+            PathSeg::Cubic(cubic) => cubic.deriv().eval(t),
+        };
+        return Some((segment.eval(t), tangent.to_vec2()));
+    }
+    None
+}
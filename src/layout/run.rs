@@ -1,4 +1,6 @@
 use super::*;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 
 impl<'a, B: Brush> Run<'a, B> {
     pub(crate) fn new(
@@ -28,6 +30,31 @@ impl<'a, B: Brush> Run<'a, B> {
         self.data.synthesis
     }
 
+    /// Returns the horizontal shear factor for a synthesized oblique style,
+    /// or zero if the run is not obliqued. A renderer applies this as the
+    /// transform `x' = x + shear * y` in a y-up glyph coordinate space.
+    pub fn oblique_shear(&self) -> f32 {
+        self.data
+            .synthesis
+            .skew()
+            .map(|angle| angle.to_radians().tan())
+            .unwrap_or(0.)
+    }
+
+    /// Returns the horizontal scale factor for a synthesized condensed or
+    /// expanded style, or one if the run is not scaled. Glyph advances and
+    /// offsets are already scaled; a renderer applies this as the transform
+    /// `x' = x * scale` to the glyph outlines.
+    pub fn stretch_scale(&self) -> f32 {
+        self.data.stretch
+    }
+
+    /// Returns true if the renderer should apply a synthesized bold by
+    /// widening the glyph outlines.
+    pub fn is_emboldened(&self) -> bool {
+        self.data.synthesis.embolden()
+    }
+
     /// Returns the normalized variation coordinates for the font associated
     /// with the run.
     pub fn normalized_coords(&self) -> &[NormalizedCoord] {
@@ -37,6 +64,19 @@ impl<'a, B: Brush> Run<'a, B> {
             .unwrap_or(&[])
     }
 
+    /// Returns the requested font features that were applied when shaping
+    /// the run: those set with
+    /// [`StyleProperty::FontFeatures`](crate::style::StyleProperty::FontFeatures),
+    /// and the disabling of kerning, that the font provides. Features that
+    /// the font does not provide are omitted, as are the features that the
+    /// shaper enables by default.
+    pub fn applied_features(&self) -> &[FontFeature] {
+        self.layout
+            .features
+            .get(self.data.features_range.clone())
+            .unwrap_or(&[])
+    }
+
     /// Returns metrics for the run.
     pub fn metrics(&self) -> &RunMetrics {
         &self.data.metrics
@@ -57,6 +97,54 @@ impl<'a, B: Brush> Run<'a, B> {
             .clone()
     }
 
+    /// Returns an identifier for the run derived from its source range, its
+    /// font and the source range of its line. The identifier is computed on
+    /// demand and remains the same across layouts built with the same font
+    /// context as long as the run covers the same text with the same font on
+    /// the same line, so it can be used to track runs across line breaking
+    /// passes. Styles that do not affect the font, such as brushes and
+    /// decorations, do not change the identifier.
+    pub fn id(&self) -> RunId {
+        let mut hasher = DefaultHasher::new();
+        let text_range = self.text_range();
+        if self.line_data.is_some() {
+            let lines = &self.layout.lines;
+            let index = lines.partition_point(|line| line.text_range.end <= text_range.start);
+            lines
+                .get(index)
+                .map(|line| line.text_range.clone())
+                .hash(&mut hasher);
+        }
+        text_range.hash(&mut hasher);
+        self.font().as_ref().key.value().hash(&mut hasher);
+        self.data.font_size.to_bits().hash(&mut hasher);
+        self.data.bidi_level.hash(&mut hasher);
+        self.normalized_coords().hash(&mut hasher);
+        let synthesis = self.data.synthesis;
+        synthesis.embolden().hash(&mut hasher);
+        synthesis.skew().map(f32::to_bits).hash(&mut hasher);
+        RunId(hasher.finish())
+    }
+
+    /// Returns the user data specified with
+    /// [`StyleProperty::UserData`](crate::style::StyleProperty::UserData) for
+    /// the text of the run.
+    ///
+    /// User data does not split runs, so the value is mixed if the run covers
+    /// text with different user data, and a range with the same user data
+    /// reports it on every run that the range is split into.
+    pub fn user_data(&self) -> PropertyValue<u64> {
+        let range = self.text_range();
+        let mut styles = self.layout.styles.iter().filter(|style| {
+            style.text_range.start < range.end && range.start < style.text_range.end
+        });
+        let mut value = PropertyValue::Uniform(styles.next().map_or(0, |style| style.user_data));
+        for style in styles {
+            value.add(style.user_data);
+        }
+        value
+    }
+
     /// Returns true if the run has right-to-left directionality.
     pub fn is_rtl(&self) -> bool {
         self.data.bidi_level & 1 != 0
@@ -84,6 +172,7 @@ impl<'a, B: Brush> Run<'a, B> {
         let index = range.start + index;
         Some(Cluster {
             run: self.clone(),
+            index,
             data: self.layout.clusters.get(index)?,
         })
     }
@@ -148,6 +237,7 @@ impl<'a, B: Brush> Iterator for Clusters<'a, B> {
         };
         Some(Cluster {
             run: self.run.clone(),
+            index,
             data: self.run.layout.clusters.get(index)?,
         })
     }
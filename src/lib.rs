@@ -7,10 +7,12 @@ mod shape;
 mod util;
 
 pub mod context;
+pub mod debug;
 pub mod font;
 pub mod layout;
 pub mod style;
+pub mod text;
 
 pub use context::LayoutContext;
-pub use font::{Font, FontContext};
+pub use font::{Font, FontContext, FontId, FontMetrics, FontRequest};
 pub use layout::Layout;
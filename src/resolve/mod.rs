@@ -4,8 +4,10 @@ pub mod range;
 pub mod tree;
 
 use super::style::{
-    Brush, FontFamily, FontFeature, FontSettings, FontStack, FontStretch, FontStyle, FontVariation,
-    FontWeight, StyleProperty,
+    Brush, Direction, FontFamily, FontFeature, FontPalette, FontSettings, FontStack, FontStretch,
+    FontStyle, FontSynthesis, FontVariantLigatures, FontVariation, FontWeight, HangingPunctuation,
+    LineBreakStrictness, NumberShaping, OpticalSizing, OverflowWrap, PaletteOverride, Shadow,
+    SpacingValue, StyleProperty, UnicodeBidi, Wrap,
 };
 use crate::font::*;
 use crate::fount::FamilyId;
@@ -117,7 +119,14 @@ impl ResolveContext {
             StyleProperty::FontWeight(value) => FontWeight(*value),
             StyleProperty::FontVariations(value) => FontVariations(self.resolve_variations(*value)),
             StyleProperty::FontFeatures(value) => FontFeatures(self.resolve_features(*value)),
+            StyleProperty::Kerning(value) => Kerning(*value),
+            StyleProperty::OpticalSizing(value) => OpticalSizing(*value),
+            StyleProperty::FontVariantLigatures(value) => FontVariantLigatures(*value),
+            StyleProperty::FontPalette(value) => FontPalette(value.clone()),
+            StyleProperty::FontPaletteOverrides(value) => FontPaletteOverrides(value.to_vec()),
+            StyleProperty::FontSynthesis(value) => FontSynthesis(*value),
             StyleProperty::Locale(value) => Locale(value.map(Language::parse).flatten()),
+            StyleProperty::NumberShaping(value) => NumberShaping(*value),
             StyleProperty::Brush(value) => Brush(value.clone()),
             StyleProperty::Underline(value) => Underline(*value),
             StyleProperty::UnderlineOffset(value) => UnderlineOffset(value.map(|x| x * scale)),
@@ -129,9 +138,29 @@ impl ResolveContext {
             }
             StyleProperty::StrikethroughSize(value) => StrikethroughSize(value.map(|x| x * scale)),
             StyleProperty::StrikethroughBrush(value) => StrikethroughBrush(value.clone()),
+            StyleProperty::TextShadow(value) => TextShadow(
+                value
+                    .iter()
+                    .map(|shadow| Shadow {
+                        x: shadow.x * scale,
+                        y: shadow.y * scale,
+                        blur_radius: shadow.blur_radius * scale,
+                        brush: shadow.brush.clone(),
+                    })
+                    .collect(),
+            ),
             StyleProperty::LineHeight(value) => LineHeight(*value),
-            StyleProperty::WordSpacing(value) => WordSpacing(*value * scale),
-            StyleProperty::LetterSpacing(value) => LetterSpacing(*value * scale),
+            StyleProperty::WordSpacing(value) => WordSpacing(scale_spacing(*value, scale)),
+            StyleProperty::LetterSpacing(value) => LetterSpacing(scale_spacing(*value, scale)),
+            StyleProperty::UnicodeBidi(value) => UnicodeBidi(*value),
+            StyleProperty::DirectionOverride(value) => DirectionOverride(*value),
+            StyleProperty::Wrap(value) => Wrap(*value),
+            StyleProperty::OverflowWrap(value) => OverflowWrap(*value),
+            StyleProperty::LineBreak(value) => LineBreak(*value),
+            StyleProperty::HangingPunctuation(value) => HangingPunctuation(*value),
+            StyleProperty::OpticalAlignment(value) => OpticalAlignment(*value),
+            StyleProperty::UserData(value) => UserData(*value),
+            StyleProperty::Reset(value) => Reset(Box::new(self.resolve(fcx, value, scale))),
         }
     }
 
@@ -275,8 +304,22 @@ pub enum ResolvedProperty<B: Brush> {
     FontVariations(Resolved<Setting<f32>>),
     /// Font feature settings.
     FontFeatures(Resolved<Setting<u16>>),
+    /// Kerning.
+    Kerning(bool),
+    /// Optical sizing of variable fonts.
+    OpticalSizing(OpticalSizing),
+    /// Ligatures and contextual alternates.
+    FontVariantLigatures(FontVariantLigatures),
+    /// Color palette for color fonts.
+    FontPalette(FontPalette),
+    /// Replacements for entries of the color palette.
+    FontPaletteOverrides(Vec<PaletteOverride>),
+    /// Font synthesis.
+    FontSynthesis(FontSynthesis),
     /// Locale.
     Locale(Option<Language>),
+    /// Number shaping.
+    NumberShaping(NumberShaping),
     /// Brush for rendering text.
     Brush(B),
     /// Underline decoration.
@@ -295,12 +338,32 @@ pub enum ResolvedProperty<B: Brush> {
     StrikethroughSize(Option<f32>),
     /// Brush for rendering the strikethrough decoration.
     StrikethroughBrush(Option<B>),
+    /// Shadows drawn beneath the text.
+    TextShadow(Vec<Shadow<B>>),
     /// Line height multiplier.
     LineHeight(f32),
     /// Extra spacing between words.
-    WordSpacing(f32),
+    WordSpacing(SpacingValue),
     /// Extra spacing between letters.
-    LetterSpacing(f32),
+    LetterSpacing(SpacingValue),
+    /// Bidirectional behavior of the text.
+    UnicodeBidi(UnicodeBidi),
+    /// Direction override for shaping and layout.
+    DirectionOverride(Option<Direction>),
+    /// Line wrapping behavior.
+    Wrap(Wrap),
+    /// Breaking of words that do not fit on a line.
+    OverflowWrap(OverflowWrap),
+    /// Strictness of line breaking for CJK text.
+    LineBreak(LineBreakStrictness),
+    /// Hanging punctuation.
+    HangingPunctuation(HangingPunctuation),
+    /// Optical alignment of line edges.
+    OpticalAlignment(bool),
+    /// Opaque user data.
+    UserData(u64),
+    /// Reset of a property to its value in the default style.
+    Reset(Box<ResolvedProperty<B>>),
 }
 
 /// Flattened group of style properties.
@@ -320,20 +383,52 @@ pub struct ResolvedStyle<B: Brush> {
     pub font_variations: Resolved<Setting<f32>>,
     /// Font feature settings.
     pub font_features: Resolved<Setting<u16>>,
+    /// Kerning.
+    pub kerning: bool,
+    /// Optical sizing of variable fonts.
+    pub optical_sizing: OpticalSizing,
+    /// Ligatures and contextual alternates.
+    pub font_variant_ligatures: FontVariantLigatures,
+    /// Color palette for color fonts.
+    pub font_palette: FontPalette,
+    /// Replacements for entries of the color palette.
+    pub font_palette_overrides: Vec<PaletteOverride>,
+    /// Font synthesis.
+    pub font_synthesis: FontSynthesis,
     /// Locale.
     pub locale: Option<Language>,
+    /// Number shaping.
+    pub number_shaping: NumberShaping,
     /// Brush for rendering text.
     pub brush: B,
     /// Underline decoration.
     pub underline: ResolvedDecoration<B>,
     /// Strikethrough decoration.
     pub strikethrough: ResolvedDecoration<B>,
+    /// Shadows drawn beneath the text.
+    pub text_shadow: Vec<Shadow<B>>,
     /// Line height multiplier.
     pub line_height: f32,
     /// Extra spacing between words.
-    pub word_spacing: f32,
+    pub word_spacing: SpacingValue,
     /// Extra spacing between letters.
-    pub letter_spacing: f32,
+    pub letter_spacing: SpacingValue,
+    /// Bidirectional behavior of the text.
+    pub unicode_bidi: UnicodeBidi,
+    /// Line wrapping behavior.
+    pub wrap: Wrap,
+    /// Breaking of words that do not fit on a line.
+    pub overflow_wrap: OverflowWrap,
+    /// Strictness of line breaking for CJK text.
+    pub line_break: LineBreakStrictness,
+    /// Hanging punctuation.
+    pub hanging_punctuation: HangingPunctuation,
+    /// Optical alignment of line edges.
+    pub optical_alignment: bool,
+    /// Opaque user data.
+    pub user_data: u64,
+    /// Direction override for shaping and layout.
+    pub direction_override: Option<Direction>,
 }
 
 impl<B: Brush> Default for ResolvedStyle<B> {
@@ -346,20 +441,37 @@ impl<B: Brush> Default for ResolvedStyle<B> {
             font_weight: Default::default(),
             font_variations: Default::default(),
             font_features: Default::default(),
+            kerning: true,
+            optical_sizing: OpticalSizing::Auto,
+            font_variant_ligatures: FontVariantLigatures::default(),
+            font_palette: Default::default(),
+            font_palette_overrides: vec![],
+            font_synthesis: Default::default(),
             locale: None,
+            number_shaping: NumberShaping::Auto,
             brush: Default::default(),
             underline: Default::default(),
             strikethrough: Default::default(),
+            text_shadow: vec![],
             line_height: 1.,
-            word_spacing: 0.,
-            letter_spacing: 0.,
+            word_spacing: Default::default(),
+            letter_spacing: Default::default(),
+            unicode_bidi: Default::default(),
+            wrap: Default::default(),
+            overflow_wrap: Default::default(),
+            line_break: LineBreakStrictness::default(),
+            hanging_punctuation: Default::default(),
+            optical_alignment: false,
+            user_data: 0,
+            direction_override: None,
         }
     }
 }
 
 impl<B: Brush> ResolvedStyle<B> {
-    /// Applies the specified property to this style.
-    pub fn apply(&mut self, property: ResolvedProperty<B>) {
+    /// Applies the specified property to this style. Resets take the value
+    /// of the specified default style.
+    pub fn apply(&mut self, property: ResolvedProperty<B>, default: &Self) {
         use ResolvedProperty::*;
         match property {
             FontStack(value) => self.font_stack = value,
@@ -369,7 +481,14 @@ impl<B: Brush> ResolvedStyle<B> {
             FontWeight(value) => self.font_weight = value,
             FontVariations(value) => self.font_variations = value,
             FontFeatures(value) => self.font_features = value,
+            Kerning(value) => self.kerning = value,
+            OpticalSizing(value) => self.optical_sizing = value,
+            FontVariantLigatures(value) => self.font_variant_ligatures = value,
+            FontPalette(value) => self.font_palette = value,
+            FontPaletteOverrides(value) => self.font_palette_overrides = value,
+            FontSynthesis(value) => self.font_synthesis = value,
             Locale(value) => self.locale = value,
+            NumberShaping(value) => self.number_shaping = value,
             Brush(value) => self.brush = value,
             Underline(value) => self.underline.enabled = value,
             UnderlineOffset(value) => self.underline.offset = value,
@@ -379,13 +498,71 @@ impl<B: Brush> ResolvedStyle<B> {
             StrikethroughOffset(value) => self.strikethrough.offset = value,
             StrikethroughSize(value) => self.strikethrough.size = value,
             StrikethroughBrush(value) => self.strikethrough.brush = value,
+            TextShadow(value) => self.text_shadow = value,
             LineHeight(value) => self.line_height = value,
             WordSpacing(value) => self.word_spacing = value,
             LetterSpacing(value) => self.letter_spacing = value,
+            UnicodeBidi(value) => self.unicode_bidi = value,
+            Wrap(value) => self.wrap = value,
+            OverflowWrap(value) => self.overflow_wrap = value,
+            LineBreak(value) => self.line_break = value,
+            HangingPunctuation(value) => self.hanging_punctuation = value,
+            OpticalAlignment(value) => self.optical_alignment = value,
+            UserData(value) => self.user_data = value,
+            DirectionOverride(value) => self.direction_override = value,
+            Reset(value) => self.apply(default.value_of(&value), default),
         }
     }
 
-    pub fn check(&self, property: &ResolvedProperty<B>) -> bool {
+    /// Returns the property of the same kind as the specified property with
+    /// the value from this style.
+    pub fn value_of(&self, property: &ResolvedProperty<B>) -> ResolvedProperty<B> {
+        use ResolvedProperty::*;
+        match property {
+            FontStack(_) => FontStack(self.font_stack),
+            FontSize(_) => FontSize(self.font_size),
+            FontStretch(_) => FontStretch(self.font_stretch),
+            FontStyle(_) => FontStyle(self.font_style),
+            FontWeight(_) => FontWeight(self.font_weight),
+            FontVariations(_) => FontVariations(self.font_variations),
+            FontFeatures(_) => FontFeatures(self.font_features),
+            Kerning(_) => Kerning(self.kerning),
+            OpticalSizing(_) => OpticalSizing(self.optical_sizing),
+            FontVariantLigatures(_) => FontVariantLigatures(self.font_variant_ligatures),
+            FontPalette(_) => FontPalette(self.font_palette.clone()),
+            FontPaletteOverrides(_) => FontPaletteOverrides(self.font_palette_overrides.clone()),
+            FontSynthesis(_) => FontSynthesis(self.font_synthesis),
+            Locale(_) => Locale(self.locale),
+            NumberShaping(_) => NumberShaping(self.number_shaping),
+            Brush(_) => Brush(self.brush.clone()),
+            Underline(_) => Underline(self.underline.enabled),
+            UnderlineOffset(_) => UnderlineOffset(self.underline.offset),
+            UnderlineSize(_) => UnderlineSize(self.underline.size),
+            UnderlineBrush(_) => UnderlineBrush(self.underline.brush.clone()),
+            Strikethrough(_) => Strikethrough(self.strikethrough.enabled),
+            StrikethroughOffset(_) => StrikethroughOffset(self.strikethrough.offset),
+            StrikethroughSize(_) => StrikethroughSize(self.strikethrough.size),
+            StrikethroughBrush(_) => StrikethroughBrush(self.strikethrough.brush.clone()),
+            TextShadow(_) => TextShadow(self.text_shadow.clone()),
+            LineHeight(_) => LineHeight(self.line_height),
+            WordSpacing(_) => WordSpacing(self.word_spacing),
+            LetterSpacing(_) => LetterSpacing(self.letter_spacing),
+            UnicodeBidi(_) => UnicodeBidi(self.unicode_bidi),
+            Wrap(_) => Wrap(self.wrap),
+            OverflowWrap(_) => OverflowWrap(self.overflow_wrap),
+            LineBreak(_) => LineBreak(self.line_break),
+            HangingPunctuation(_) => HangingPunctuation(self.hanging_punctuation),
+            OpticalAlignment(_) => OpticalAlignment(self.optical_alignment),
+            UserData(_) => UserData(self.user_data),
+            DirectionOverride(_) => DirectionOverride(self.direction_override),
+            Reset(value) => self.value_of(value),
+        }
+    }
+
+    /// Returns true if the specified property has the same value in this
+    /// style. Resets compare against the value of the specified default
+    /// style.
+    pub fn check(&self, property: &ResolvedProperty<B>, default: &Self) -> bool {
         use ResolvedProperty::*;
         match property {
             FontStack(value) => self.font_stack == *value,
@@ -395,7 +572,14 @@ impl<B: Brush> ResolvedStyle<B> {
             FontWeight(value) => self.font_weight == *value,
             FontVariations(value) => self.font_variations == *value,
             FontFeatures(value) => self.font_features == *value,
+            Kerning(value) => self.kerning == *value,
+            OpticalSizing(value) => self.optical_sizing == *value,
+            FontVariantLigatures(value) => self.font_variant_ligatures == *value,
+            FontPalette(value) => self.font_palette == *value,
+            FontPaletteOverrides(value) => self.font_palette_overrides == *value,
+            FontSynthesis(value) => self.font_synthesis == *value,
             Locale(value) => self.locale == *value,
+            NumberShaping(value) => self.number_shaping == *value,
             Brush(value) => self.brush == *value,
             Underline(value) => self.underline.enabled == *value,
             UnderlineOffset(value) => self.underline.offset == *value,
@@ -405,9 +589,19 @@ impl<B: Brush> ResolvedStyle<B> {
             StrikethroughOffset(value) => self.strikethrough.offset == *value,
             StrikethroughSize(value) => self.strikethrough.size == *value,
             StrikethroughBrush(value) => self.strikethrough.brush == *value,
+            TextShadow(value) => self.text_shadow == *value,
             LineHeight(value) => nearly_eq(self.line_height, *value),
-            WordSpacing(value) => nearly_eq(self.word_spacing, *value),
-            LetterSpacing(value) => nearly_eq(self.letter_spacing, *value),
+            WordSpacing(value) => self.word_spacing == *value,
+            LetterSpacing(value) => self.letter_spacing == *value,
+            UnicodeBidi(value) => self.unicode_bidi == *value,
+            Wrap(value) => self.wrap == *value,
+            OverflowWrap(value) => self.overflow_wrap == *value,
+            LineBreak(value) => self.line_break == *value,
+            HangingPunctuation(value) => self.hanging_punctuation == *value,
+            OpticalAlignment(value) => self.optical_alignment == *value,
+            UserData(value) => self.user_data == *value,
+            DirectionOverride(value) => self.direction_override == *value,
+            Reset(value) => self.check(&default.value_of(value), default),
         }
     }
 }
@@ -424,3 +618,12 @@ pub struct ResolvedDecoration<B: Brush> {
     /// Brush for the decoration.
     pub brush: Option<B>,
 }
+
+/// Applies the layout scale factor to absolute spacing values. Relative
+/// values are resolved against the already scaled font size during shaping.
+fn scale_spacing(value: SpacingValue, scale: f32) -> SpacingValue {
+    match value {
+        SpacingValue::Absolute(value) => SpacingValue::Absolute(value * scale),
+        _ => value,
+    }
+}
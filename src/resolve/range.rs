@@ -34,7 +34,12 @@ impl<B: Brush> RangedStyleBuilder<B> {
     /// Pushes a property that covers the full range of text.
     pub fn push_default(&mut self, property: ResolvedProperty<B>) {
         assert!(self.len != !0);
-        self.default_style.apply(property)
+        // Resetting the default style to itself has no effect.
+        if let ResolvedProperty::Reset(_) = property {
+            return;
+        }
+        let default = ResolvedStyle::default();
+        self.default_style.apply(property, &default)
     }
 
     /// Pushes a property that covers the specified range of text.
@@ -45,6 +50,11 @@ impl<B: Brush> RangedStyleBuilder<B> {
     }
 
     /// Computes the sequence of ranged styles.
+    ///
+    /// The styles are emitted in ascending order of their ranges, which are
+    /// contiguous, non-overlapping and cover the full length of the text.
+    /// Properties pushed with empty ranges have no effect, so every style
+    /// has a non-empty range unless the text itself is empty.
     pub fn finish(&mut self, styles: &mut Vec<RangedStyle<B>>) {
         if self.len == !0 {
             self.properties.clear();
@@ -56,19 +66,21 @@ impl<B: Brush> RangedStyleBuilder<B> {
             range: 0..self.len,
         });
         for prop in &self.properties {
-            if prop.range.start > prop.range.end {
+            if prop.range.start >= prop.range.end {
                 continue;
             }
+            // Resets take the value of the default style.
+            let default = &self.default_style;
             let split_range = split_range(prop, &styles);
             let mut inserted = 0;
             if let Some(first) = split_range.first {
                 let original_span = &mut styles[first];
-                if !original_span.style.check(&prop.property) {
+                if !original_span.style.check(&prop.property, default) {
                     let mut new_span = original_span.clone();
                     let original_end = original_span.range.end;
                     original_span.range.end = prop.range.start;
                     new_span.range.start = prop.range.start;
-                    new_span.style.apply(prop.property.clone());
+                    new_span.style.apply(prop.property.clone(), default);
                     if split_range.replace_len == 0 && split_range.last == Some(first) {
                         let mut new_end_span = original_span.clone();
                         new_end_span.range.start = prop.range.end;
@@ -88,16 +100,16 @@ impl<B: Brush> RangedStyleBuilder<B> {
             let replace_start = split_range.replace_start + inserted;
             let replace_end = replace_start + split_range.replace_len;
             for style in &mut styles[replace_start..replace_end] {
-                style.style.apply(prop.property.clone());
+                style.style.apply(prop.property.clone(), default);
             }
             if let Some(mut last) = split_range.last {
                 last += inserted;
                 let original_span = &mut styles[last];
-                if !original_span.style.check(&prop.property) {
+                if !original_span.style.check(&prop.property, default) {
                     let mut new_span = original_span.clone();
                     original_span.range.start = prop.range.end;
                     new_span.range.end = prop.range.end;
-                    new_span.style.apply(prop.property.clone());
+                    new_span.style.apply(prop.property.clone(), default);
                     styles.insert(last, new_span);
                 }
             }
@@ -118,6 +130,11 @@ impl<B: Brush> RangedStyleBuilder<B> {
             }
         }
         styles.truncate(styles.len() - merged_count);
+        debug_assert!(styles.first().map(|s| s.range.start) == Some(0));
+        debug_assert!(styles.last().map(|s| s.range.end) == Some(self.len));
+        debug_assert!(styles
+            .windows(2)
+            .all(|pair| { pair[0].range.end == pair[1].range.start && !pair[0].range.is_empty() }));
         self.properties.clear();
         self.default_style = ResolvedStyle::default();
         self.len = !0;
@@ -190,3 +207,84 @@ fn resolve_range(range: impl RangeBounds<usize>, len: usize) -> Range<usize> {
     };
     start.min(len)..end.min(len)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ResolvedProperty::*;
+
+    fn sizes(builder: &mut RangedStyleBuilder<()>) -> Vec<(Range<usize>, f32)> {
+        let mut styles = vec![];
+        builder.finish(&mut styles);
+        styles
+            .iter()
+            .map(|s| (s.range.clone(), s.style.font_size))
+            .collect()
+    }
+
+    #[test]
+    fn reset_takes_default_style_value() {
+        let mut builder = RangedStyleBuilder::default();
+        builder.begin(10);
+        builder.push_default(FontSize(20.));
+        builder.push(FontSize(30.), 0..6);
+        builder.push(Reset(Box::new(FontSize(0.))), 2..4);
+        assert_eq!(
+            sizes(&mut builder),
+            vec![(0..2, 30.), (2..4, 20.), (4..6, 30.), (6..10, 20.)]
+        );
+    }
+
+    #[test]
+    fn reset_of_default_is_ignored() {
+        let mut builder = RangedStyleBuilder::default();
+        builder.begin(4);
+        builder.push_default(FontSize(20.));
+        builder.push_default(Reset(Box::new(FontSize(0.))));
+        assert_eq!(sizes(&mut builder), vec![(0..4, 20.)]);
+    }
+
+    #[test]
+    fn nested_ranges_are_contiguous() {
+        let mut builder = RangedStyleBuilder::default();
+        builder.begin(12);
+        builder.push_default(FontSize(10.));
+        builder.push(FontSize(20.), 2..10);
+        builder.push(FontSize(30.), 4..6);
+        // Empty ranges, including one at the end of the text, do not split
+        // the styles around them.
+        builder.push(FontSize(40.), 5..5);
+        builder.push(FontSize(50.), 12..12);
+        let styles = sizes(&mut builder);
+        assert_eq!(
+            styles,
+            vec![
+                (0..2, 10.),
+                (2..4, 20.),
+                (4..6, 30.),
+                (6..10, 20.),
+                (10..12, 10.)
+            ]
+        );
+        assert_eq!(styles[0].0.start, 0);
+        assert_eq!(styles.last().unwrap().0.end, 12);
+        for pair in styles.windows(2) {
+            assert!(!pair[0].0.is_empty());
+            assert_eq!(pair[0].0.end, pair[1].0.start);
+        }
+    }
+
+    #[test]
+    fn check_reset_uses_default_style() {
+        let mut default = ResolvedStyle::<()>::default();
+        let other = ResolvedStyle::default();
+        default.apply(FontSize(20.), &other);
+        let mut style = default.clone();
+        style.apply(FontSize(30.), &default);
+        let reset = Reset(Box::new(FontSize(0.)));
+        assert!(!style.check(&reset, &default));
+        style.apply(reset.clone(), &default);
+        assert!(style.check(&reset, &default));
+        assert_eq!(style.font_size, 20.);
+    }
+}
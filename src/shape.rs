@@ -1,13 +1,22 @@
-use super::font::{Font, FontContext};
-use super::layout::Layout;
+use super::context::{ControlCharRendering, MarkHandling};
+use super::font::{Font, FontContext, FontRequest};
+use super::layout::data::token_data;
+use super::layout::{Layout, ShapingIssue, ShapingIssueKind};
 use super::resolve::range::RangedStyle;
 use super::resolve::{ResolveContext, Resolved};
-use super::style::{Brush, FontFeature, FontVariation};
+use super::style::{
+    Brush, FontFeature, FontSynthesis, FontVariantLigatures, FontVariation, NumberShaping,
+    OpticalSizing,
+};
 use crate::util::nearly_eq;
+use core::ops::Range;
 use swash::shape::*;
-use swash::text::cluster::{CharCluster, CharInfo, Token};
-use swash::text::{Language, Script};
-use swash::{Attributes, FontRef, Synthesis};
+use swash::text::cluster::{CharCluster, CharInfo, Parser, Token};
+use swash::text::{BidiClass, Language, Script};
+use swash::{tag_from_bytes, Attributes, FontRef, Stretch, Synthesis, Tag};
+
+const KERN: Tag = tag_from_bytes(b"kern");
+const OPSZ: Tag = tag_from_bytes(b"opsz");
 
 struct Item {
     style_index: u16,
@@ -17,10 +26,23 @@ struct Item {
     locale: Option<Language>,
     variations: Resolved<FontVariation>,
     features: Resolved<FontFeature>,
+    kerning: bool,
+    optical_sizing: OpticalSizing,
+    ligatures: FontVariantLigatures,
     word_spacing: f32,
     letter_spacing: f32,
 }
 
+/// Returns the value of the `opsz` axis for a font size in layout units.
+fn optical_size(size: f32, scale: f32) -> f32 {
+    if scale > 0. {
+        size / scale
+    } else {
+        size
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn shape_text<B: Brush>(
     rcx: &ResolveContext,
     fcx: &mut FontContext,
@@ -28,6 +50,9 @@ pub fn shape_text<B: Brush>(
     infos: &[(CharInfo, u16)],
     levels: &[u8],
     scx: &mut ShapeContext,
+    diagnostics: bool,
+    mark_handling: MarkHandling,
+    control_chars: ControlCharRendering,
     text: &str,
     layout: &mut Layout<B>,
 ) {
@@ -47,13 +72,45 @@ pub fn shape_text<B: Brush>(
         locale: style.locale,
         variations: style.font_variations,
         features: style.font_features,
-        word_spacing: style.word_spacing,
-        letter_spacing: style.letter_spacing,
+        kerning: style.kerning,
+        optical_sizing: style.optical_sizing,
+        ligatures: style.font_variant_ligatures,
+        word_spacing: style.word_spacing.resolve(style.font_size),
+        letter_spacing: style.letter_spacing.resolve(style.font_size),
     };
+    let substitutes = substitute_chars(text, styles, infos, control_chars);
     let mut char_range = 0..0;
     let mut text_range = 0..0;
+    let mut features = vec![];
+    let mut variations = vec![];
     macro_rules! shape_item {
         () => {
+            variations.clear();
+            variations.extend_from_slice(rcx.variations(item.variations).unwrap_or(&[]));
+            // Fonts without an `opsz` axis ignore the setting. The optical
+            // size is in points, so it excludes the scale of the layout.
+            if item.optical_sizing == OpticalSizing::Auto
+                && !variations.iter().any(|v| v.tag == OPSZ)
+            {
+                variations.push(FontVariation {
+                    tag: OPSZ,
+                    value: optical_size(item.size, layout.data.scale),
+                });
+            }
+            features.clear();
+            features.extend_from_slice(rcx.features(item.features).unwrap_or(&[]));
+            if !item.kerning && !features.iter().any(|f| f.tag == KERN) {
+                features.push(FontFeature {
+                    tag: KERN,
+                    value: 0,
+                });
+            }
+            for feature in item.ligatures.features() {
+                if !features.iter().any(|f| f.tag == feature.tag) {
+                    features.push(feature);
+                }
+            }
+            let first_run = layout.data.runs.len();
             let item_text = &text[text_range.clone()];
             let item_infos = &infos[char_range.start..];
             let first_style_index = item_infos[0].1;
@@ -69,40 +126,61 @@ pub fn shape_text<B: Brush>(
                 size: item.size,
                 script: item.script,
                 language: item.locale,
+                // For right-to-left runs, the partitioner substitutes the bidi
+                // mirror of paired characters such as brackets before shaping.
                 direction: if item.level & 1 != 0 {
                     Direction::RightToLeft
                 } else {
                     Direction::LeftToRight
                 },
-                variations: rcx.variations(item.variations).unwrap_or(&[]),
-                features: rcx.features(item.features).unwrap_or(&[]),
-                insert_dotted_circles: false,
+                variations: &variations,
+                features: &features,
+                insert_dotted_circles: mark_handling == MarkHandling::InsertDottedCircle,
             };
-            partition::shape(
-                scx,
-                &mut fs,
-                &options,
-                item_text.char_indices().zip(item_infos).map(
-                    |((offset, ch), (info, style_index))| Token {
-                        ch,
+            let tokens = item_text.char_indices().zip(item_infos).enumerate().map(
+                |(i, ((offset, ch), (info, style_index)))| {
+                    let substitute = substitutes
+                        .as_ref()
+                        .map_or(ch, |chars| chars[char_range.start + i]);
+                    Token {
+                        ch: substitute,
                         offset: (text_range.start + offset) as u32,
                         len: ch.len_utf8() as u8,
-                        info: *info,
-                        data: *style_index as _,
-                    },
-                ),
-                |font, shaper| {
-                    layout.data.push_run(
-                        font.font.clone(),
-                        item.size,
-                        font.synthesis,
-                        shaper,
-                        item.level,
-                        item.word_spacing,
-                        item.letter_spacing,
-                    );
+                        // Substitutes are shaped with their own properties,
+                        // but keep the boundaries of the source text.
+                        info: if substitute != ch {
+                            CharInfo::new(substitute.into(), info.boundary())
+                        } else {
+                            *info
+                        },
+                        data: token_data(*style_index, text_range.start + offset),
+                    }
                 },
             );
+            partition::shape(scx, &mut fs, &options, tokens.clone(), |font, shaper| {
+                layout.data.push_run(
+                    font.font.clone(),
+                    item.size,
+                    font.synthesis,
+                    font.stretch,
+                    shaper,
+                    &features,
+                    item.level,
+                    item.script,
+                    item.word_spacing,
+                    item.letter_spacing,
+                    mark_handling == MarkHandling::Drop,
+                );
+            });
+            let run_range = first_run..layout.data.runs.len();
+            layout
+                .data
+                .push_ligature_words(run_range.clone(), tokens, item.locale);
+            if diagnostics {
+                let issues =
+                    shaping_issues(fcx, rcx, styles, infos, &item, text, layout, run_range);
+                layout.data.diagnostics.extend(issues);
+            }
         };
     }
     for ((char_index, ch), (info, style_index)) in text.chars().enumerate().zip(infos) {
@@ -119,8 +197,17 @@ pub fn shape_text<B: Brush>(
                 || style.locale != item.locale
                 || style.font_variations != item.variations
                 || style.font_features != item.features
-                || !nearly_eq(style.letter_spacing, item.letter_spacing)
-                || !nearly_eq(style.word_spacing, item.word_spacing)
+                || style.kerning != item.kerning
+                || style.optical_sizing != item.optical_sizing
+                || style.font_variant_ligatures != item.ligatures
+                || !nearly_eq(
+                    style.letter_spacing.resolve(style.font_size),
+                    item.letter_spacing,
+                )
+                || !nearly_eq(
+                    style.word_spacing.resolve(style.font_size),
+                    item.word_spacing,
+                )
             {
                 break_run = true;
             }
@@ -133,6 +220,11 @@ pub fn shape_text<B: Brush>(
             item.locale = style.locale;
             item.variations = style.font_variations;
             item.features = style.font_features;
+            item.kerning = style.kerning;
+            item.optical_sizing = style.optical_sizing;
+            item.ligatures = style.font_variant_ligatures;
+            item.word_spacing = style.word_spacing.resolve(style.font_size);
+            item.letter_spacing = style.letter_spacing.resolve(style.font_size);
             text_range.start = text_range.end;
             char_range.start = char_range.end;
         }
@@ -144,10 +236,286 @@ pub fn shape_text<B: Brush>(
     }
 }
 
+/// Returns the fonts selected for the text, itemized and mapped to fonts
+/// as in [`shape_text`], without shaping. Adjacent clusters mapped to the
+/// same font are combined into a single request.
+pub fn required_fonts<B: Brush>(
+    rcx: &ResolveContext,
+    fcx: &mut FontContext,
+    styles: &[RangedStyle<B>],
+    infos: &[(CharInfo, u16)],
+    control_chars: ControlCharRendering,
+    text: &str,
+) -> Vec<FontRequest> {
+    let mut requests: Vec<FontRequest> = vec![];
+    if text.is_empty() || styles.is_empty() {
+        return requests;
+    }
+    let substitutes = substitute_chars(text, styles, infos, control_chars);
+    let mut item_script = infos
+        .iter()
+        .map(|x| x.0.script())
+        .find(|&script| real_script(script))
+        .unwrap_or(Script::Latin);
+    let mut item_locale = styles[0].style.locale;
+    let mut char_range = 0..0;
+    let mut text_range = 0..0;
+    let mut cluster = CharCluster::new();
+    let mut map_item = |char_range: Range<usize>,
+                        text_range: Range<usize>,
+                        script: Script,
+                        locale: Option<Language>| {
+        let item_infos = &infos[char_range.clone()];
+        let mut fs = FontSelector::new(fcx, rcx, styles, item_infos[0].1, script, locale);
+        let tokens = text[text_range.clone()]
+            .char_indices()
+            .zip(item_infos)
+            .enumerate()
+            .map(|(i, ((offset, ch), (info, style_index)))| {
+                let substitute = substitutes
+                    .as_ref()
+                    .map_or(ch, |chars| chars[char_range.start + i]);
+                Token {
+                    ch: substitute,
+                    offset: (text_range.start + offset) as u32,
+                    len: ch.len_utf8() as u8,
+                    info: if substitute != ch {
+                        CharInfo::new(substitute.into(), info.boundary())
+                    } else {
+                        *info
+                    },
+                    data: *style_index as u32,
+                }
+            });
+        let mut parser = Parser::new(script, tokens);
+        while parser.next(&mut cluster) {
+            let range = cluster.range().to_range();
+            let font = match partition::Selector::select_font(&mut fs, &mut cluster) {
+                Some(selected) => selected.font,
+                None => continue,
+            };
+            match requests.last_mut() {
+                Some(last) if last.font == font && last.text_range.end == range.start => {
+                    last.text_range.end = range.end;
+                }
+                _ => requests.push(fs.fcx.cache.request(&font, range)),
+            }
+        }
+    };
+    for (ch, (info, style_index)) in text.chars().zip(infos) {
+        let mut script = info.script();
+        if !real_script(script) {
+            script = item_script;
+        }
+        let locale = styles[*style_index as usize].style.locale;
+        if !text_range.is_empty() && (script != item_script || locale != item_locale) {
+            map_item(
+                char_range.clone(),
+                text_range.clone(),
+                item_script,
+                item_locale,
+            );
+            item_script = script;
+            item_locale = locale;
+            text_range.start = text_range.end;
+            char_range.start = char_range.end;
+        }
+        text_range.end += ch.len_utf8();
+        char_range.end += 1;
+    }
+    if !text_range.is_empty() {
+        map_item(char_range, text_range, item_script, item_locale);
+    }
+    requests
+}
+
+/// Builds shaping issues for the clusters in the specified runs that were
+/// mapped to the `.notdef` glyph.
+#[allow(clippy::too_many_arguments)]
+fn shaping_issues<B: Brush>(
+    fcx: &FontContext,
+    rcx: &ResolveContext,
+    styles: &[RangedStyle<B>],
+    infos: &[(CharInfo, u16)],
+    item: &Item,
+    text: &str,
+    layout: &Layout<B>,
+    run_range: Range<usize>,
+) -> Vec<ShapingIssue> {
+    let context = &fcx.cache.context;
+    let fallbacks = context.fallback_families(item.script, item.locale);
+    let family_name = |id| context.family(id).map(|family| family.name().to_owned());
+    let mut issues = vec![];
+    for text_range in layout.data.notdef_clusters(run_range) {
+        let ch = text[text_range.clone()].chars().next().unwrap_or_default();
+        let char_index = text[..text_range.start].chars().count();
+        let style_index = infos.get(char_index).map(|info| info.1).unwrap_or(0);
+        let style = &styles[style_index as usize].style;
+        let stack = rcx.stack(style.font_stack).unwrap_or(&[]);
+        let mut families: Vec<String> = vec![];
+        for name in stack
+            .iter()
+            .chain(fallbacks)
+            .filter_map(|id| family_name(*id))
+        {
+            if !families.contains(&name) {
+                families.push(name);
+            }
+        }
+        let kind = if fallbacks.is_empty() {
+            ShapingIssueKind::UnsupportedScript
+        } else {
+            ShapingIssueKind::FallbackExhausted
+        };
+        issues.push(ShapingIssue {
+            text_range,
+            ch,
+            families,
+            kind,
+        });
+    }
+    issues
+}
+
 fn real_script(script: Script) -> bool {
     script != Script::Common && script != Script::Unknown && script != Script::Inherited
 }
 
+/// Returns the characters of the text with control characters substituted
+/// according to the rendering mode and digits substituted according to the
+/// number shaping of each style, or `None` if no substitution is requested.
+fn substitute_chars<B: Brush>(
+    text: &str,
+    styles: &[RangedStyle<B>],
+    infos: &[(CharInfo, u16)],
+    control_chars: ControlCharRendering,
+) -> Option<Vec<char>> {
+    if control_chars == ControlCharRendering::Shaped
+        && styles
+            .iter()
+            .all(|style| style.style.number_shaping == NumberShaping::Auto)
+    {
+        return None;
+    }
+    // Zero digit of the script of the preceding text in the paragraph.
+    let mut context = None;
+    let chars = text
+        .chars()
+        .zip(infos)
+        .map(|(ch, (info, style_index))| {
+            let style = &styles[*style_index as usize].style;
+            let script = info.script();
+            if real_script(script) {
+                context = script_zero_digit(script, style.locale);
+            } else if info.bidi_class() == BidiClass::B {
+                context = None;
+            }
+            if let Some(substitute) = control_char_substitute(ch, control_chars) {
+                return substitute;
+            }
+            let zero = match style.number_shaping {
+                NumberShaping::Auto => return ch,
+                NumberShaping::Nominal => {
+                    return match DIGIT_ZEROS
+                        .iter()
+                        .find(|&&zero| (zero..=digit(zero, 9)).contains(&ch))
+                    {
+                        Some(&zero) => digit('0', ch as u32 - zero as u32),
+                        None => ch,
+                    };
+                }
+                NumberShaping::National => style.locale.and_then(national_zero_digit),
+                NumberShaping::Contextual => context,
+            };
+            match (zero, ch.to_digit(10)) {
+                (Some(zero), Some(value)) if ch.is_ascii_digit() => digit(zero, value),
+                _ => ch,
+            }
+        })
+        .collect();
+    Some(chars)
+}
+
+/// Returns the visible substitute for a control character that is not a
+/// line break or tab.
+fn control_char_substitute(ch: char, rendering: ControlCharRendering) -> Option<char> {
+    if !ch.is_control() || matches!(ch, '\t' | '\n' | '\u{B}' | '\u{C}' | '\r' | '\u{85}') {
+        return None;
+    }
+    match rendering {
+        ControlCharRendering::Shaped => None,
+        ControlCharRendering::Pictures => Some(match ch as u32 {
+            c @ 0..=0x1F => core::char::from_u32(0x2400 + c).unwrap_or('\u{25A1}'),
+            0x7F => '\u{2421}',
+            _ => '\u{25A1}',
+        }),
+        ControlCharRendering::Box => Some('\u{25A1}'),
+    }
+}
+
+/// Zero digits of the scripts with decimal digits that are supported by
+/// number shaping.
+const DIGIT_ZEROS: &[char] = &[
+    '\u{660}', '\u{6F0}', '\u{966}', '\u{9E6}', '\u{A66}', '\u{AE6}', '\u{B66}', '\u{BE6}',
+    '\u{C66}', '\u{CE6}', '\u{D66}', '\u{E50}', '\u{ED0}', '\u{F20}', '\u{1040}', '\u{17E0}',
+];
+
+fn digit(zero: char, value: u32) -> char {
+    core::char::from_u32(zero as u32 + value).unwrap_or(zero)
+}
+
+/// Returns the zero digit conventionally used with the specified script.
+fn script_zero_digit(script: Script, locale: Option<Language>) -> Option<char> {
+    Some(match script {
+        Script::Arabic => match locale.as_ref().map(|locale| locale.language()) {
+            Some("fa") | Some("ur") | Some("ps") | Some("sd") | Some("ks") => '\u{6F0}',
+            _ => '\u{660}',
+        },
+        Script::Devanagari => '\u{966}',
+        Script::Bengali => '\u{9E6}',
+        Script::Gurmukhi => '\u{A66}',
+        Script::Gujarati => '\u{AE6}',
+        Script::Oriya => '\u{B66}',
+        Script::Tamil => '\u{BE6}',
+        Script::Telugu => '\u{C66}',
+        Script::Kannada => '\u{CE6}',
+        Script::Malayalam => '\u{D66}',
+        Script::Thai => '\u{E50}',
+        Script::Lao => '\u{ED0}',
+        Script::Tibetan => '\u{F20}',
+        Script::Myanmar => '\u{1040}',
+        Script::Khmer => '\u{17E0}',
+        _ => return None,
+    })
+}
+
+/// Returns the zero digit of the national digits of the specified locale.
+fn national_zero_digit(locale: Language) -> Option<char> {
+    Some(match locale.language() {
+        // Arabic in the Maghreb uses European digits.
+        "ar" => match locale.region() {
+            Some("MA") | Some("DZ") | Some("TN") | Some("LY") | Some("EH") => return None,
+            _ => '\u{660}',
+        },
+        "fa" | "ur" | "ps" | "sd" | "ks" => '\u{6F0}',
+        "hi" | "mr" | "ne" | "sa" => '\u{966}',
+        "bn" | "as" => '\u{9E6}',
+        "pa" => '\u{A66}',
+        "gu" => '\u{AE6}',
+        "or" => '\u{B66}',
+        "ta" => '\u{BE6}',
+        "te" => '\u{C66}',
+        "kn" => '\u{CE6}',
+        "ml" => '\u{D66}',
+        "th" => '\u{E50}',
+        "lo" => '\u{ED0}',
+        "bo" | "dz" => '\u{F20}',
+        "my" => '\u{1040}',
+        "km" => '\u{17E0}',
+        _ => return None,
+    })
+}
+
 struct FontSelector<'a, B: Brush> {
     fcx: &'a mut FontContext,
     rcx: &'a ResolveContext,
@@ -156,6 +524,7 @@ struct FontSelector<'a, B: Brush> {
     script: Script,
     locale: Option<Language>,
     attrs: Attributes,
+    font_synthesis: FontSynthesis,
     variations: &'a [FontVariation],
     features: &'a [FontFeature],
 }
@@ -185,6 +554,7 @@ impl<'a, B: Brush> FontSelector<'a, B> {
             script,
             locale,
             attrs,
+            font_synthesis: style.font_synthesis,
             variations,
             features,
         }
@@ -211,11 +581,48 @@ impl<'a, B: Brush> partition::Selector for FontSelector<'a, B> {
                     .select_fallbacks(self.script, self.locale, attrs);
             }
             self.attrs = attrs;
+            self.font_synthesis = style.font_synthesis;
             self.variations = variations;
             self.features = features;
         }
         let (font, synthesis) = self.fcx.cache.map_cluster(cluster)?;
-        Some(SelectedFont { font, synthesis })
+        let mut synthesis = self.font_synthesis.apply(synthesis);
+        let defaults = self.fcx.cache.default_variations(&font);
+        if !defaults.is_empty() {
+            // Synthesized variations take precedence over the defaults.
+            let synthesized = synthesis.variations();
+            let variations = synthesized
+                .iter()
+                .chain(
+                    defaults
+                        .iter()
+                        .filter(|v| !synthesized.iter().any(|s| s.tag == v.tag)),
+                )
+                .copied();
+            synthesis = Synthesis::new(
+                variations,
+                synthesis.embolden(),
+                synthesis.skew().unwrap_or(0.),
+            );
+        }
+        let mut stretch = 1.;
+        if self.font_synthesis.stretch {
+            // Only scale when no face of the requested width was available,
+            // that is, when the selected face is not condensed or expanded
+            // in the same direction as requested.
+            let font_attrs = font.as_ref().attributes();
+            let (actual, requested) = (font_attrs.stretch(), self.attrs.stretch());
+            let same_direction = actual != Stretch::NORMAL
+                && (actual < Stretch::NORMAL) == (requested < Stretch::NORMAL);
+            if actual != requested && !same_direction && !font_attrs.has_stretch_variation() {
+                stretch = requested.to_percentage() / actual.to_percentage();
+            }
+        }
+        Some(SelectedFont {
+            font,
+            synthesis,
+            stretch,
+        })
     }
 }
 
@@ -223,6 +630,8 @@ impl<'a, B: Brush> partition::Selector for FontSelector<'a, B> {
 struct SelectedFont {
     font: Font,
     synthesis: Synthesis,
+    /// Horizontal scale factor for synthesized stretch.
+    stretch: f32,
 }
 
 impl partition::SelectedFont for SelectedFont {
@@ -234,3 +643,15 @@ impl partition::SelectedFont for SelectedFont {
         Some(self.synthesis)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn optical_size_excludes_scale() {
+        assert_eq!(optical_size(32., 2.), 16.);
+        assert_eq!(optical_size(12., 1.), 12.);
+        assert_eq!(optical_size(12., 0.), 12.);
+    }
+}
@@ -1,6 +1,7 @@
 use core::fmt;
 
 pub use crate::fount::GenericFamily;
+use swash::{tag_from_bytes, ColorPalette, FontRef, Synthesis, Tag, Usability};
 pub use swash::{ObliqueAngle, Stretch as FontStretch, Style as FontStyle, Weight as FontWeight};
 
 /// Setting for a font variation.
@@ -177,3 +178,294 @@ impl<'a, T> From<&'a [T]> for FontSettings<'a, T> {
         Self::List(value)
     }
 }
+
+/// OpenType feature for the typesetting of numerals, such as in fractions
+/// and chemical formulas.
+///
+/// The corresponding settings can be supplied with
+/// [`StyleProperty::FontFeatures`](super::StyleProperty::FontFeatures) to
+/// apply the feature to a range of text. Fonts that do not support a
+/// feature render the text unchanged.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum NumericFeature {
+    /// Fractions (`frac`), which form a fraction from numerals separated by
+    /// a slash, such as "1/2".
+    Fractions,
+    /// Ordinals (`ordn`), which form superior letters that follow numerals,
+    /// such as in "2nd".
+    Ordinals,
+    /// Scientific inferiors (`sinf`), which form subscripts such as in
+    /// chemical formulas.
+    ScientificInferiors,
+    /// Numerators (`numr`), which form the numerals above the bar of a
+    /// fraction.
+    Numerators,
+    /// Denominators (`dnom`), which form the numerals below the bar of a
+    /// fraction.
+    Denominators,
+}
+
+impl NumericFeature {
+    /// Returns the tag of the feature.
+    pub fn tag(self) -> Tag {
+        tag_from_bytes(match self {
+            Self::Fractions => b"frac",
+            Self::Ordinals => b"ordn",
+            Self::ScientificInferiors => b"sinf",
+            Self::Numerators => b"numr",
+            Self::Denominators => b"dnom",
+        })
+    }
+
+    /// Returns the setting that enables the feature.
+    pub fn enabled(self) -> FontFeature {
+        FontFeature {
+            tag: self.tag(),
+            value: 1,
+        }
+    }
+
+    /// Returns the setting that disables the feature.
+    pub fn disabled(self) -> FontFeature {
+        FontFeature {
+            tag: self.tag(),
+            value: 0,
+        }
+    }
+}
+
+/// Ligatures and contextual alternates that are applied by shaping.
+///
+/// Required ligatures (`rlig`), such as those needed to render Arabic
+/// correctly, are not affected by these settings. Features supplied with
+/// [`StyleProperty::FontFeatures`](super::StyleProperty::FontFeatures)
+/// take precedence over the settings.
+///
+/// <https://developer.mozilla.org/en-US/docs/Web/CSS/font-variant-ligatures>
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct FontVariantLigatures {
+    /// Common ligatures (`liga` and `clig`), such as "fi". Enabled by
+    /// default.
+    pub common: bool,
+    /// Discretionary ligatures (`dlig`). Disabled by default.
+    pub discretionary: bool,
+    /// Historical ligatures (`hlig`). Disabled by default.
+    pub historical: bool,
+    /// Contextual alternates (`calt`), which substitute glyphs depending on
+    /// the surrounding text. Enabled by default.
+    pub contextual: bool,
+}
+
+impl FontVariantLigatures {
+    /// Settings with all optional ligatures and contextual alternates
+    /// disabled, as for `font-variant-ligatures: none`.
+    pub fn none() -> Self {
+        Self {
+            common: false,
+            discretionary: false,
+            historical: false,
+            contextual: false,
+        }
+    }
+
+    /// Returns the feature settings that differ from the defaults of
+    /// shaping.
+    pub fn features(&self) -> impl Iterator<Item = FontFeature> + Clone {
+        let settings = [
+            (b"liga", !self.common, 0),
+            (b"clig", !self.common, 0),
+            (b"dlig", self.discretionary, 1),
+            (b"hlig", self.historical, 1),
+            (b"calt", !self.contextual, 0),
+        ];
+        IntoIterator::into_iter(settings)
+            .filter(|setting| setting.1)
+            .map(|(tag, _, value)| FontFeature {
+                tag: tag_from_bytes(tag),
+                value,
+            })
+    }
+}
+
+impl Default for FontVariantLigatures {
+    fn default() -> Self {
+        Self {
+            common: true,
+            discretionary: false,
+            historical: false,
+            contextual: true,
+        }
+    }
+}
+
+/// Selection of a color palette for rendering color fonts.
+///
+/// <https://developer.mozilla.org/en-US/docs/Web/CSS/font-palette>
+#[derive(Clone, PartialEq, Eq, Default, Debug)]
+pub enum FontPalette {
+    /// Default palette of the font.
+    #[default]
+    Normal,
+    /// First palette marked as usable on a light background.
+    Light,
+    /// First palette marked as usable on a dark background.
+    Dark,
+    /// Palette at the specified index.
+    Index(u16),
+    /// Palette with the specified label in any language of the font's naming
+    /// table.
+    Named(String),
+}
+
+impl FontPalette {
+    /// Selects the corresponding color palette from the specified font. Falls
+    /// back to the default palette if the requested palette is not available.
+    pub fn select<'a>(&self, font: &FontRef<'a>) -> Option<ColorPalette<'a>> {
+        let usability = match self {
+            Self::Normal => None,
+            Self::Light => Some(Usability::Light),
+            Self::Dark => Some(Usability::Dark),
+            Self::Index(index) => {
+                if let Some(palette) = font.color_palettes().nth(*index as usize) {
+                    return Some(palette);
+                }
+                None
+            }
+            Self::Named(name) => {
+                let palette = font.color_palettes().find(|palette| {
+                    palette
+                        .name_id()
+                        .map(|id| {
+                            font.localized_strings()
+                                .any(|label| label.id() == id && label.chars().eq(name.chars()))
+                        })
+                        .unwrap_or(false)
+                });
+                if palette.is_some() {
+                    return palette;
+                }
+                None
+            }
+        };
+        if let Some(usability) = usability {
+            let palette = font.color_palettes().find(|palette| {
+                matches!(palette.usability(), Some(value) if value == usability || value == Usability::Both)
+            });
+            if palette.is_some() {
+                return palette;
+            }
+        }
+        font.color_palettes().next()
+    }
+}
+
+/// Replacement for an entry of the selected color palette.
+///
+/// <https://developer.mozilla.org/en-US/docs/Web/CSS/@font-palette-values/override-colors>
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct PaletteOverride {
+    /// Index of the palette entry.
+    pub index: u16,
+    /// Replacement color in RGBA order.
+    pub color: [u8; 4],
+}
+
+/// Controls which font styles may be synthesized when the selected font
+/// does not provide a face that matches the requested attributes.
+///
+/// <https://developer.mozilla.org/en-US/docs/Web/CSS/font-synthesis>
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct FontSynthesis {
+    /// Allow faux bold. Weight variations of variable fonts are applied
+    /// regardless, as they select a real instance of the font.
+    pub weight: bool,
+    /// Allow faux italic or oblique. Slant and italic variations of
+    /// variable fonts are applied regardless.
+    pub style: bool,
+    /// Allow synthesized small capitals. Small caps are not currently
+    /// synthesized, so this has no effect.
+    pub small_caps: bool,
+    /// Allow synthesized condensing or expansion by horizontally scaling
+    /// glyphs when the selected face does not match the requested stretch
+    /// and has no width variation.
+    pub stretch: bool,
+}
+
+impl FontSynthesis {
+    /// Filters the synthesis suggestions for a font according to these
+    /// settings.
+    pub fn apply(&self, synthesis: Synthesis) -> Synthesis {
+        if self.weight && self.style {
+            return synthesis;
+        }
+        let variations = synthesis.variations().iter().copied();
+        let embolden = self.weight && synthesis.embolden();
+        let skew = if self.style {
+            synthesis.skew().unwrap_or(0.)
+        } else {
+            0.
+        };
+        Synthesis::new(variations, embolden, skew)
+    }
+}
+
+impl Default for FontSynthesis {
+    fn default() -> Self {
+        Self {
+            weight: true,
+            style: true,
+            small_caps: true,
+            stretch: false,
+        }
+    }
+}
+
+/// Selection of the optical size of variable fonts.
+///
+/// <https://developer.mozilla.org/en-US/docs/Web/CSS/font-optical-sizing>
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub enum OpticalSizing {
+    /// The `opsz` axis of fonts that provide one is set to the font size,
+    /// unless it is specified in the font variation settings.
+    #[default]
+    Auto,
+    /// The optical size is left at the default of the font.
+    None,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use swash::Setting;
+
+    fn wght(value: f32) -> Setting<f32> {
+        Setting {
+            tag: tag_from_bytes(b"wght"),
+            value,
+        }
+    }
+
+    #[test]
+    fn synthesis_disabled_keeps_variations() {
+        let synthesis = Synthesis::new([wght(700.)].iter().copied(), true, 14.);
+        let none = FontSynthesis {
+            weight: false,
+            style: false,
+            small_caps: false,
+            stretch: false,
+        };
+        let applied = none.apply(synthesis);
+        assert_eq!(applied.variations(), &[wght(700.)]);
+        assert!(!applied.embolden());
+        assert_eq!(applied.skew(), None);
+    }
+
+    #[test]
+    fn synthesis_enabled_is_unchanged() {
+        let synthesis = Synthesis::new([wght(700.)].iter().copied(), true, 14.);
+        let applied = FontSynthesis::default().apply(synthesis);
+        assert_eq!(applied.variations(), &[wght(700.)]);
+        assert!(applied.embolden());
+        assert_eq!(applied.skew(), Some(14.));
+    }
+}
@@ -2,12 +2,15 @@
 
 mod brush;
 mod font;
+mod shadow;
 
 pub use brush::*;
 pub use font::{
-    FontFamily, FontFeature, FontSettings, FontStack, FontStretch, FontStyle, FontVariation,
-    FontWeight, GenericFamily, ObliqueAngle,
+    FontFamily, FontFeature, FontPalette, FontSettings, FontStack, FontStretch, FontStyle,
+    FontSynthesis, FontVariantLigatures, FontVariation, FontWeight, GenericFamily, NumericFeature,
+    ObliqueAngle, OpticalSizing, PaletteOverride,
 };
+pub use shadow::Shadow;
 
 /// Properties that define a style.
 #[derive(Clone, PartialEq, Debug)]
@@ -26,8 +29,25 @@ pub enum StyleProperty<'a, B: Brush> {
     FontVariations(FontSettings<'a, FontVariation>),
     /// Font feature settings.
     FontFeatures(FontSettings<'a, FontFeature>),
+    /// Kerning. When disabled, the `kern` feature is turned off unless it is
+    /// explicitly specified in the font feature settings.
+    Kerning(bool),
+    /// Optical sizing of variable fonts.
+    OpticalSizing(OpticalSizing),
+    /// Ligatures and contextual alternates.
+    FontVariantLigatures(FontVariantLigatures),
+    /// Color palette for color fonts.
+    FontPalette(FontPalette),
+    /// Replacements for entries of the selected color palette. Later entries
+    /// take precedence over earlier ones with the same index.
+    FontPaletteOverrides(&'a [PaletteOverride]),
+    /// Controls which font styles may be synthesized when a matching face
+    /// is not available.
+    FontSynthesis(FontSynthesis),
     /// Locale.
     Locale(Option<&'a str>),
+    /// Substitution of digits according to the locale or surrounding text.
+    NumberShaping(NumberShaping),
     /// Brush for rendering text.
     Brush(B),
     /// Underline decoration.
@@ -46,10 +66,205 @@ pub enum StyleProperty<'a, B: Brush> {
     StrikethroughSize(Option<f32>),
     /// Brush for rendering the strikethrough decoration.
     StrikethroughBrush(Option<B>),
+    /// Shadows drawn beneath the text.
+    TextShadow(&'a [Shadow<B>]),
     /// Line height multiplier.
     LineHeight(f32),
-    /// Extra spacing between words.
-    WordSpacing(f32),
-    /// Extra spacing between letters.
-    LetterSpacing(f32),
+    /// Extra spacing between words. Following CSS, the spacing is added to
+    /// the advance of spaces (U+0020) and no-break spaces (U+00A0) only;
+    /// tabs and other spaces such as the ideographic space are unaffected.
+    WordSpacing(SpacingValue),
+    /// Extra spacing between letters. Spacing is applied after kerning.
+    LetterSpacing(SpacingValue),
+    /// Bidirectional behavior of the text.
+    UnicodeBidi(UnicodeBidi),
+    /// Forces the text to be shaped and laid out in the specified direction,
+    /// regardless of the resolved bidi levels. The text is treated as an
+    /// embedding within the surrounding text so it does not affect the
+    /// ordering of neighboring runs.
+    DirectionOverride(Option<Direction>),
+    /// Line wrapping behavior.
+    Wrap(Wrap),
+    /// Whether words that do not fit on a line may be broken at arbitrary
+    /// points.
+    OverflowWrap(OverflowWrap),
+    /// Strictness of line breaking for CJK text.
+    LineBreak(LineBreakStrictness),
+    /// Punctuation that may hang outside the line box at the start or end
+    /// of a line.
+    HangingPunctuation(HangingPunctuation),
+    /// Shifts the first and last glyphs of each line by their side bearings
+    /// so that the ink, rather than the advance, is flush with the margins.
+    OpticalAlignment(bool),
+    /// Opaque value that is not interpreted by the layout and is exposed on
+    /// the resulting [`Style`](crate::layout::Style), such as a key for
+    /// custom effects.
+    UserData(u64),
+    /// Resets the kind of property given by the inner property to its value
+    /// in the default style, removing values applied by enclosing ranges.
+    /// The value of the inner property is ignored.
+    Reset(&'a StyleProperty<'a, B>),
+}
+
+/// Length of extra spacing between letters or words.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum SpacingValue {
+    /// Absolute length, multiplied by the scale factor of the layout.
+    Absolute(f32),
+    /// Multiple of the font size.
+    Em(f32),
+    /// Percentage of the font size.
+    Percent(f32),
+}
+
+impl SpacingValue {
+    /// Computes the absolute spacing for the specified font size.
+    pub fn resolve(self, font_size: f32) -> f32 {
+        match self {
+            Self::Absolute(value) => value,
+            Self::Em(value) => value * font_size,
+            Self::Percent(value) => value * font_size * 0.01,
+        }
+    }
+}
+
+impl Default for SpacingValue {
+    fn default() -> Self {
+        Self::Absolute(0.)
+    }
+}
+
+impl From<f32> for SpacingValue {
+    fn from(value: f32) -> Self {
+        Self::Absolute(value)
+    }
+}
+
+/// Bidirectional behavior of a range of text.
+///
+/// <https://developer.mozilla.org/en-US/docs/Web/CSS/unicode-bidi>
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub enum UnicodeBidi {
+    /// Text participates in the bidirectional algorithm of the surrounding
+    /// paragraph.
+    #[default]
+    Normal,
+    /// Each paragraph in the range is resolved in isolation with the
+    /// direction determined by its first strong character.
+    Plaintext,
+}
+
+/// Text direction.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Direction {
+    /// Left-to-right.
+    LeftToRight,
+    /// Right-to-left.
+    RightToLeft,
+}
+
+/// Line wrapping behavior of a range of text.
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub enum Wrap {
+    /// Lines may be broken at any break opportunity within the range.
+    #[default]
+    Wrap,
+    /// Soft line breaks are suppressed within the range. Content that does
+    /// not fit overflows the line.
+    NoWrap,
+    /// Lines may be broken at any break opportunity within the range and
+    /// after every space. Spaces at the end of a line do not hang past it,
+    /// so they occupy width and wrap to the next line if they do not fit.
+    ///
+    /// <https://developer.mozilla.org/en-US/docs/Web/CSS/white-space#break-spaces>
+    BreakSpaces,
+}
+
+/// Breaking behavior of words that are too long to fit on a line.
+///
+/// <https://developer.mozilla.org/en-US/docs/Web/CSS/overflow-wrap>
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub enum OverflowWrap {
+    /// Lines are only broken at break opportunities, so a word that does
+    /// not fit overflows the line.
+    #[default]
+    Normal,
+    /// A word that does not fit on a line by itself may be broken between
+    /// any two grapheme clusters.
+    BreakWord,
+    /// Same as [`BreakWord`](Self::BreakWord). CSS distinguishes the two
+    /// only when computing intrinsic sizes, which are not computed here.
+    Anywhere,
+}
+
+/// Strictness of the line breaking rules applied to Chinese and Japanese
+/// text.
+///
+/// The default rules of UAX #14 are strict. The relaxed modes tailor the
+/// rules to allow additional break opportunities, following CSS.
+///
+/// <https://drafts.csswg.org/css-text-3/#line-break-property>
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub enum LineBreakStrictness {
+    /// Same as [`Strict`](Self::Strict), so text that does not specify a
+    /// strictness breaks according to UAX #14.
+    #[default]
+    Auto,
+    /// Allows breaks before small kana and the prolonged sound mark, and
+    /// before hyphens following ideographic text.
+    Normal,
+    /// Additionally allows breaks before iteration marks, between
+    /// inseparable characters such as ellipses, before centered punctuation
+    /// and postfixes, and after prefixes in ideographic text.
+    Loose,
+    /// Applies the rules of UAX #14 without tailoring.
+    Strict,
+}
+
+/// Punctuation that may be placed outside the line box at the edges of a
+/// line. Hanging punctuation is not included when fitting the line to the
+/// maximum advance or when aligning it.
+///
+/// <https://developer.mozilla.org/en-US/docs/Web/CSS/hanging-punctuation>
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub struct HangingPunctuation {
+    /// An opening bracket or quote at the start of the first line of a
+    /// paragraph hangs.
+    pub first: bool,
+    /// A closing bracket or quote at the end of the last line of a
+    /// paragraph hangs.
+    pub last: bool,
+    /// Behavior of a stop or comma at the end of a line.
+    pub end: HangingEnd,
+}
+
+/// Hanging behavior of a stop or comma at the end of a line.
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub enum HangingEnd {
+    /// Stops and commas do not hang.
+    #[default]
+    None,
+    /// Stops and commas always hang.
+    Force,
+    /// Stops and commas hang only if they would not otherwise fit on the
+    /// line.
+    Allow,
+}
+
+/// Digit substitution applied to a range of text before shaping. The
+/// source text, and offsets into it, are unaffected.
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub enum NumberShaping {
+    /// Digits are shaped as they appear in the text.
+    #[default]
+    Auto,
+    /// Decimal digits of any script are shaped as European digits.
+    Nominal,
+    /// European digits are shaped as the national digits of the locale,
+    /// such as Arabic-Indic digits for Arabic or Devanagari digits for
+    /// Hindi.
+    National,
+    /// European digits are shaped as the digits of the script of the
+    /// preceding text in the same paragraph.
+    Contextual,
 }
@@ -0,0 +1,16 @@
+use super::Brush;
+
+/// Shadow drawn beneath the glyphs of a range of text.
+///
+/// <https://developer.mozilla.org/en-US/docs/Web/CSS/text-shadow>
+#[derive(Clone, PartialEq, Default, Debug)]
+pub struct Shadow<B: Brush> {
+    /// Horizontal offset of the shadow.
+    pub x: f32,
+    /// Vertical offset of the shadow.
+    pub y: f32,
+    /// Radius of the blur applied to the shadow.
+    pub blur_radius: f32,
+    /// Brush for rendering the shadow. If `None`, use the brush of the text.
+    pub brush: Option<B>,
+}
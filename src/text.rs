@@ -0,0 +1,139 @@
+//! Utilities for working with source text.
+
+use swash::text::{ClusterBreak, Codepoint as _};
+
+/// Returns the offset of the nearest grapheme cluster boundary after the
+/// specified byte offset, or the length of the text if there is none.
+///
+/// Offsets that are not on a character boundary are treated as lying within
+/// the grapheme cluster containing them.
+pub fn next_grapheme_boundary(s: &str, offset: usize) -> usize {
+    grapheme_boundaries(s)
+        .find(|boundary| *boundary > offset)
+        .unwrap_or(s.len())
+}
+
+/// Returns the offset of the nearest grapheme cluster boundary before the
+/// specified byte offset, or zero if there is none.
+///
+/// Offsets that are not on a character boundary are treated as lying within
+/// the grapheme cluster containing them.
+pub fn prev_grapheme_boundary(s: &str, offset: usize) -> usize {
+    grapheme_boundaries(s)
+        .take_while(|boundary| *boundary < offset)
+        .last()
+        .unwrap_or(0)
+}
+
+/// Returns an iterator over the offsets of the starts of the extended
+/// grapheme clusters in the text, as defined by UAX #29.
+///
+/// Layout clusters of complex scripts may combine several grapheme clusters,
+/// so the cluster boundaries of a layout are a subset of these boundaries.
+fn grapheme_boundaries(s: &str) -> impl Iterator<Item = usize> + '_ {
+    let mut prev = None;
+    // The preceding characters match `Extended_Pictographic Extend*`.
+    let mut pictographic = false;
+    // The preceding characters match `Extended_Pictographic Extend* ZWJ`.
+    let mut pictographic_zwj = false;
+    // An odd number of regional indicators precede the character.
+    let mut odd_regional_indicators = false;
+    s.char_indices().filter_map(move |(offset, ch)| {
+        let kind = ch.cluster_break();
+        let is_pictographic = ch.is_extended_pictographic();
+        let is_boundary = prev
+            .map(|prev| {
+                is_grapheme_boundary(
+                    prev,
+                    kind,
+                    pictographic_zwj && is_pictographic,
+                    odd_regional_indicators,
+                )
+            })
+            .unwrap_or(true);
+        pictographic_zwj = pictographic && kind == ClusterBreak::ZWJ;
+        pictographic = is_pictographic || (pictographic && kind == ClusterBreak::EX);
+        odd_regional_indicators = kind == ClusterBreak::RI && !odd_regional_indicators;
+        prev = Some(kind);
+        if is_boundary {
+            Some(offset)
+        } else {
+            None
+        }
+    })
+}
+
+/// Returns true if there is a grapheme cluster boundary between characters
+/// with the specified cluster break properties.
+fn is_grapheme_boundary(
+    prev: ClusterBreak,
+    next: ClusterBreak,
+    joins_pictographic: bool,
+    odd_regional_indicators: bool,
+) -> bool {
+    use ClusterBreak::*;
+    match (prev, next) {
+        // GB3
+        (CR, LF) => false,
+        // GB4, GB5
+        (CN, _) | (CR, _) | (LF, _) | (_, CN) | (_, CR) | (_, LF) => true,
+        // GB6, GB7, GB8
+        (L, L) | (L, V) | (L, LV) | (L, LVT) | (LV, V) | (LV, T) | (V, V) | (V, T) => false,
+        (LVT, T) | (T, T) => false,
+        // GB9, GB9a, GB9b
+        (_, EX) | (_, ZWJ) | (_, SM) | (PP, _) => false,
+        // GB11
+        (ZWJ, _) if joins_pictographic => false,
+        // GB12, GB13
+        (RI, RI) => !odd_regional_indicators,
+        // GB999
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn boundaries(s: &str) -> Vec<usize> {
+        grapheme_boundaries(s).collect()
+    }
+
+    #[test]
+    fn combining_marks() {
+        assert_eq!(boundaries("e\u{301}x"), vec![0, 3]);
+        assert_eq!(boundaries("\r\nx"), vec![0, 2]);
+    }
+
+    #[test]
+    fn hangul_syllables() {
+        // Conjoining jamo L V T followed by a precomposed LV syllable and T.
+        let s = "\u{1100}\u{1161}\u{11A8}\u{AC00}\u{11A8}x";
+        assert_eq!(boundaries(s), vec![0, 9, 15]);
+        assert_eq!(next_grapheme_boundary(s, 0), 9);
+        assert_eq!(next_grapheme_boundary(s, 3), 9);
+        assert_eq!(prev_grapheme_boundary(s, 15), 9);
+        assert_eq!(prev_grapheme_boundary(s, 6), 0);
+    }
+
+    #[test]
+    fn emoji_zwj_sequences() {
+        // Man, woman and girl joined into a family.
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+        let s = format!("a{}b", family);
+        assert_eq!(boundaries(&s), vec![0, 1, 1 + family.len()]);
+        // A joiner after a letter does not join the following emoji.
+        assert_eq!(boundaries("a\u{200D}\u{1F468}"), vec![0, 4]);
+        // Skin tone modifiers extend the emoji.
+        assert_eq!(boundaries("\u{1F44D}\u{1F3FD}x"), vec![0, 8]);
+    }
+
+    #[test]
+    fn regional_indicator_pairs() {
+        // US and FR flags followed by a lone regional indicator.
+        let s = "\u{1F1FA}\u{1F1F8}\u{1F1EB}\u{1F1F7}\u{1F1E6}";
+        assert_eq!(boundaries(s), vec![0, 8, 16]);
+        assert_eq!(next_grapheme_boundary(s, 4), 8);
+        assert_eq!(prev_grapheme_boundary(s, 20), 16);
+    }
+}
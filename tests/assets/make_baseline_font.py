@@ -0,0 +1,64 @@
+#!/usr/bin/env python3
+"""Generates ParleyBaselineTest.ttf, a minimal font with a BASE table.
+
+The font maps "a" and the Devanagari letter "ka" to squares. Its BASE table
+defines the hanging, ideographic and alphabetic baselines on the horizontal
+axis, with a higher hanging baseline for Devanagari than for the default
+script.
+
+Run from this directory: python3 make_baseline_font.py
+"""
+
+import struct
+
+from make_color_font import assemble, base_tables, family_names, rect_glyph
+
+FAMILY = "Parley Baseline Test"
+
+# .notdef, "a" and "ka".
+GLYPHS = [
+    b"",
+    rect_glyph(50, 0, 550, 500),
+    rect_glyph(50, 0, 550, 700),
+]
+CHARS = [(ord("a"), 1), (0x915, 2)]
+# Baseline tags in alphabetical order and the coordinates of each script.
+TAGS = [b"hang", b"ideo", b"romn"]
+SCRIPTS = [
+    (b"DFLT", [600, -120, 0]),
+    (b"deva", [700, -120, 0]),
+]
+
+
+def base_script(coords):
+    values = struct.pack(">HH", len(TAGS) - 1, len(coords))
+    offset = 4 + 2 * len(coords)
+    for i in range(len(coords)):
+        values += struct.pack(">H", offset + 4 * i)
+    for coord in coords:
+        values += struct.pack(">Hh", 1, coord)
+    return struct.pack(">HHH", 6, 0, 0) + values
+
+
+def base():
+    tag_list = struct.pack(">H", len(TAGS)) + b"".join(TAGS)
+    scripts = [base_script(coords) for _, coords in SCRIPTS]
+    script_list = struct.pack(">H", len(SCRIPTS))
+    offset = 2 + 6 * len(SCRIPTS)
+    for (tag, _), script in zip(SCRIPTS, scripts):
+        script_list += struct.pack(">4sH", tag, offset)
+        offset += len(script)
+    script_list += b"".join(scripts)
+    axis = struct.pack(">HH", 4, 4 + len(tag_list)) + tag_list + script_list
+    return struct.pack(">HHHH", 1, 0, 8, 0) + axis
+
+
+def build():
+    tables = base_tables(family_names(FAMILY), GLYPHS, CHARS)
+    tables[b"BASE"] = base()
+    return assemble(tables)
+
+
+if __name__ == "__main__":
+    with open("ParleyBaselineTest.ttf", "wb") as f:
+        f.write(build())
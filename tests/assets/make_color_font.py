@@ -0,0 +1,217 @@
+#!/usr/bin/env python3
+"""Generates ParleyColorTest.ttf, a minimal color font for the tests.
+
+The font maps "A" to a square drawn as two COLR layers: the left half uses
+palette entry 0 and the right half uses palette entry 1. The CPAL table has
+two palettes, "Sunrise" for light backgrounds and "Midnight" for dark
+backgrounds.
+
+Run from this directory: python3 make_color_font.py
+"""
+
+import struct
+
+UPEM = 1000
+ADVANCE = 600
+FAMILY = "Parley Color Test"
+
+# Palettes of RGBA colors.
+PALETTES = [
+    ("Sunrise", 1, [(255, 0, 0, 255), (255, 200, 0, 255)]),
+    ("Midnight", 2, [(0, 0, 128, 255), (128, 0, 255, 255)]),
+]
+
+
+def rect_glyph(x0, y0, x1, y1):
+    points = [(x0, y0), (x0, y1), (x1, y1), (x1, y0)]
+    data = struct.pack(">hhhhh", 1, x0, y0, x1, y1)
+    data += struct.pack(">HH", len(points) - 1, 0)
+    data += bytes([0x01] * len(points))
+    prev = 0
+    for x, _ in points:
+        data += struct.pack(">h", x - prev)
+        prev = x
+    prev = 0
+    for _, y in points:
+        data += struct.pack(">h", y - prev)
+        prev = y
+    return data
+
+
+# .notdef, the base glyph and its two layers.
+GLYPHS = [
+    b"",
+    rect_glyph(50, 0, 550, 500),
+    rect_glyph(50, 0, 300, 500),
+    rect_glyph(300, 0, 550, 500),
+]
+
+
+def glyf_loca(glyphs):
+    glyf = b""
+    offsets = []
+    for glyph in glyphs:
+        offsets.append(len(glyf))
+        glyf += glyph + b"\0" * (-len(glyph) % 4)
+    offsets.append(len(glyf))
+    return glyf, b"".join(struct.pack(">I", offset) for offset in offsets)
+
+
+def head():
+    return struct.pack(
+        ">IIIIHHqqhhhhHHhhh",
+        0x00010000, 0x00010000, 0, 0x5F0F3CF5, 0, UPEM, 0, 0,
+        0, 0, ADVANCE, 800, 0, 8, 2, 1, 0,
+    )
+
+
+def hhea(num_glyphs):
+    return struct.pack(
+        ">IhhhHhhhhhh8xhH",
+        0x00010000, 800, -200, 0, ADVANCE, 0, 0, 550, 1, 0, 0, 0,
+        num_glyphs,
+    )
+
+
+def maxp(num_glyphs):
+    return struct.pack(">IHHHHHHHHHHHHHH", 0x00010000, num_glyphs, 4, 1,
+                       0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0)
+
+
+def os2(first_char, last_char):
+    return struct.pack(
+        ">HhHHH" + "h" * 11 + "10s" + "IIII" + "4s" + "HHH" + "hhh" + "HH" + "II" + "hhHHH",
+        4, ADVANCE, 400, 5, 0,
+        650, 700, 0, 140, 650, 700, 0, 480, 50, 250, 0,
+        b"\0" * 10, 1, 0, 0, 0, b"NONE",
+        0x40, first_char, last_char,
+        800, -200, 0, 800, 200, 1, 0,
+        500, 500, 0, 0x20, 1,
+    )
+
+
+def hmtx(num_glyphs):
+    return b"".join(struct.pack(">Hh", ADVANCE, 0) for _ in range(num_glyphs))
+
+
+def cmap(chars):
+    # One segment per character, followed by the terminating segment.
+    ends = starts = [ch for ch, _ in chars] + [0xFFFF]
+    deltas = [(glyph - ch) % 0x10000 for ch, glyph in chars] + [1]
+    seg_count = len(ends)
+    power = 1
+    while power * 2 <= seg_count:
+        power *= 2
+    sub = struct.pack(">HHHHHHH", 4, 16 + seg_count * 8, 0, seg_count * 2, power * 2,
+                      power.bit_length() - 1, seg_count * 2 - power * 2)
+    sub += struct.pack(">%dH" % seg_count, *ends) + b"\0\0"
+    sub += struct.pack(">%dH" % seg_count, *starts)
+    sub += struct.pack(">%dH" % seg_count, *deltas)
+    sub += struct.pack(">%dH" % seg_count, *([0] * seg_count))
+    return struct.pack(">HHHHI", 0, 1, 3, 1, 12) + sub
+
+
+def family_names(family):
+    return [
+        (1, family),
+        (2, "Regular"),
+        (4, family + " Regular"),
+        (6, family.replace(" ", "") + "-Regular"),
+    ]
+
+
+def name(names):
+    records = b""
+    strings = b""
+    for name_id, value in names:
+        encoded = value.encode("utf-16-be")
+        records += struct.pack(">HHHHHH", 3, 1, 0x409, name_id, len(encoded), len(strings))
+        strings += encoded
+    header = struct.pack(">HHH", 0, len(names), 6 + len(records))
+    return header + records + strings
+
+
+def post():
+    return struct.pack(">IIhhIIIII", 0x00030000, 0, -100, 50, 0, 0, 0, 0, 0)
+
+
+def colr():
+    base = struct.pack(">HHH", 1, 0, 2)
+    layers = struct.pack(">HHHH", 2, 0, 3, 1)
+    return struct.pack(">HHIIH", 0, 1, 14, 14 + len(base), 2) + base + layers
+
+
+def cpal():
+    entries = len(PALETTES[0][2])
+    count = len(PALETTES)
+    header_len = 12 + count * 2 + 12
+    records_offset = header_len
+    types_offset = records_offset + count * entries * 4
+    labels_offset = types_offset + count * 4
+    data = struct.pack(">HHHHI", 1, entries, count, count * entries, records_offset)
+    data += struct.pack(">%dH" % count, *[i * entries for i in range(count)])
+    data += struct.pack(">III", types_offset, labels_offset, 0)
+    for _, _, colors in PALETTES:
+        for r, g, b, a in colors:
+            data += bytes([b, g, r, a])
+    for _, usability, _ in PALETTES:
+        data += struct.pack(">I", usability)
+    for i in range(count):
+        data += struct.pack(">H", 256 + i)
+    return data
+
+
+def checksum(data):
+    data += b"\0" * (-len(data) % 4)
+    return sum(struct.unpack(">%dI" % (len(data) // 4), data)) & 0xFFFFFFFF
+
+
+def base_tables(names, glyphs=GLYPHS, chars=((0x41, 1),)):
+    """Returns the tables common to the test fonts.
+
+    The characters are pairs of a code point and the glyph it maps to.
+    """
+    glyf, loca = glyf_loca(glyphs)
+    codes = [ch for ch, _ in chars]
+    return {
+        b"OS/2": os2(min(codes), max(codes)),
+        b"cmap": cmap(chars),
+        b"glyf": glyf,
+        b"head": head(),
+        b"hhea": hhea(len(glyphs)),
+        b"hmtx": hmtx(len(glyphs)),
+        b"loca": loca,
+        b"maxp": maxp(len(glyphs)),
+        b"name": name(names),
+        b"post": post(),
+    }
+
+
+def build():
+    names = [(256 + i, label) for i, (label, _, _) in enumerate(PALETTES)]
+    tables = base_tables(family_names(FAMILY) + names)
+    tables[b"COLR"] = colr()
+    tables[b"CPAL"] = cpal()
+    return assemble(tables)
+
+
+def assemble(tables):
+    tags = sorted(tables)
+    count = len(tags)
+    power = 1
+    while power * 2 <= count:
+        power *= 2
+    font = struct.pack(">IHHHH", 0x00010000, count, power * 16,
+                       power.bit_length() - 1, count * 16 - power * 16)
+    offset = 12 + count * 16
+    body = b""
+    for tag in tags:
+        data = tables[tag]
+        font += struct.pack(">4sIII", tag, checksum(data), offset + len(body), len(data))
+        body += data + b"\0" * (-len(data) % 4)
+    return font + body
+
+
+if __name__ == "__main__":
+    with open("ParleyColorTest.ttf", "wb") as f:
+        f.write(build())
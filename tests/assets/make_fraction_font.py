@@ -0,0 +1,67 @@
+#!/usr/bin/env python3
+"""Generates ParleyFractionTest.ttf, a minimal font with a fraction feature.
+
+The font maps "1", "/" and "2" to rectangles of different heights. Its
+`frac` feature substitutes the sequence "1/2" with a single square glyph
+for the fraction one half.
+
+Run from this directory: python3 make_fraction_font.py
+"""
+
+import struct
+
+from make_color_font import assemble, base_tables, family_names, rect_glyph
+
+FAMILY = "Parley Fraction Test"
+
+# .notdef, one, slash, two and the fraction one half.
+GLYPHS = [
+    b"",
+    rect_glyph(250, 0, 350, 700),
+    rect_glyph(250, -100, 350, 800),
+    rect_glyph(150, 0, 450, 700),
+    rect_glyph(50, 0, 550, 500),
+]
+CHARS = [(ord("/"), 2), (ord("1"), 1), (ord("2"), 3)]
+# Components of the ligature and the glyph that replaces them.
+LIGATURE = ([1, 2, 3], 4)
+
+
+def gsub():
+    components, ligature = LIGATURE
+    # Script list with the default and Latin scripts, both of which use the
+    # single feature.
+    lang_sys = struct.pack(">HHHH", 0, 0xFFFF, 1, 0)
+    script = struct.pack(">HH", 4, 0) + lang_sys
+    scripts = [b"DFLT", b"latn"]
+    script_list = struct.pack(">H", len(scripts))
+    offset = 2 + 6 * len(scripts)
+    for tag in scripts:
+        script_list += struct.pack(">4sH", tag, offset)
+    script_list += script
+    # Feature list with the fraction feature.
+    feature_list = struct.pack(">H4sH", 1, b"frac", 8) + struct.pack(">HHH", 0, 1, 0)
+    # Lookup list with a single ligature substitution.
+    lig = struct.pack(">HH", ligature, len(components))
+    lig += struct.pack(">%dH" % (len(components) - 1), *components[1:])
+    lig_set = struct.pack(">HH", 1, 4) + lig
+    coverage = struct.pack(">HHH", 1, 1, components[0])
+    subtable = struct.pack(">HHHH", 1, 8, 1, 8 + len(coverage)) + coverage + lig_set
+    lookup = struct.pack(">HHHH", 4, 0, 1, 8) + subtable
+    lookup_list = struct.pack(">HH", 1, 4) + lookup
+    header_len = 10
+    feature_offset = header_len + len(script_list)
+    lookup_offset = feature_offset + len(feature_list)
+    header = struct.pack(">HHHHH", 1, 0, header_len, feature_offset, lookup_offset)
+    return header + script_list + feature_list + lookup_list
+
+
+def build():
+    tables = base_tables(family_names(FAMILY), GLYPHS, CHARS)
+    tables[b"GSUB"] = gsub()
+    return assemble(tables)
+
+
+if __name__ == "__main__":
+    with open("ParleyFractionTest.ttf", "wb") as f:
+        f.write(build())
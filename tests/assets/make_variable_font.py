@@ -0,0 +1,47 @@
+#!/usr/bin/env python3
+"""Generates minimal variable fonts for the tests.
+
+The fonts map "A" to a square and have a single axis. ParleyVariableTest.ttf
+has a weight axis ranging from 100 to 900 with a default of 400, and
+ParleyOpticalSizeTest.ttf has an optical size axis ranging from 8 to 72 with
+a default of 8. They have no glyph variations, since the tests only inspect
+the normalized coordinates of the runs.
+
+Run from this directory: python3 make_variable_font.py
+"""
+
+import struct
+
+from make_color_font import assemble, base_tables, family_names
+
+FONTS = [
+    ("ParleyVariableTest.ttf", "Parley Variable Test",
+     [(b"wght", 100, 400, 900, 256, "Weight")]),
+    ("ParleyOpticalSizeTest.ttf", "Parley Optical Size Test",
+     [(b"opsz", 8, 8, 72, 256, "Optical Size")]),
+]
+
+
+def fixed(value):
+    return int(value * 65536)
+
+
+def fvar(axes):
+    data = struct.pack(">HHHHHHHH", 1, 0, 16, 2, len(axes), 20, 0, 4 + 4 * len(axes))
+    for tag, min_value, default, max_value, name_id, _ in axes:
+        data += struct.pack(">4siiiHH", tag, fixed(min_value), fixed(default),
+                            fixed(max_value), 0, name_id)
+    return data
+
+
+def build(family, axes):
+    names = [(name_id, label) for _, _, _, _, name_id, label in axes]
+    tables = base_tables(family_names(family) + names)
+    tables[b"fvar"] = fvar(axes)
+    return assemble(tables)
+
+
+if __name__ == "__main__":
+    for path, family, axes in FONTS:
+        with open(path, "wb") as f:
+            f.write(build(family, axes))
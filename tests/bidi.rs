@@ -0,0 +1,205 @@
+mod common;
+
+use common::*;
+use parley::layout::{Affinity, Alignment};
+use parley::style::{Direction, StyleProperty, UnicodeBidi};
+
+const ARABIC: &str = "\u{645}\u{631}\u{62D}\u{628}\u{627} \u{628}\u{643}";
+
+#[test]
+fn plaintext_paragraphs_align_to_their_direction() {
+    let mut cx = TestContext::new();
+    let text = format!("{}\nhello there", ARABIC);
+    let width = 300.;
+    let mut layout = cx.layout_with(&text, 16., None, |builder| {
+        builder.push_default(&StyleProperty::UnicodeBidi(UnicodeBidi::Plaintext));
+    });
+    layout.break_all_lines(Some(width), Alignment::Start);
+    let lines: Vec<_> = layout.lines().collect();
+    assert_eq!(lines.len(), 2);
+    // The Arabic message starts at the right edge and the English message
+    // at the left edge.
+    let arabic = lines[0].metrics();
+    assert!(lines[0].runs().all(|run| run.is_rtl()));
+    let end = arabic.offset + arabic.advance - arabic.trailing_whitespace;
+    assert!((end - width).abs() < 0.01, "{}", end);
+    assert_eq!(lines[1].metrics().offset, 0.);
+    assert!(lines[1].runs().all(|run| !run.is_rtl()));
+    // End alignment is mirrored as well.
+    layout.break_all_lines(Some(width), Alignment::End);
+    let lines: Vec<_> = layout.lines().collect();
+    assert_eq!(lines[0].metrics().offset, 0.);
+    assert!(lines[1].metrics().offset > 0.);
+    // Without plaintext, both follow the left-to-right base direction.
+    let layout = cx.layout(&text, 16., Some(width));
+    for line in layout.lines() {
+        assert_eq!(line.metrics().offset, 0.);
+    }
+}
+
+#[test]
+fn plaintext_range_is_isolated() {
+    let mut cx = TestContext::new();
+    // The range starts with a Hebrew letter, so it is resolved as a right to
+    // left paragraph, including the space at its end. The digits inside it
+    // keep their left-to-right order and the surrounding words are
+    // unaffected.
+    let text = "abc \u{5D0} 12 xyz";
+    let layout = cx.layout_with(text, 16., None, |builder| {
+        builder.push(&StyleProperty::UnicodeBidi(UnicodeBidi::Plaintext), 4..10);
+    });
+    let runs: Vec<_> = layout
+        .runs()
+        .map(|run| (&text[run.text_range()], run.is_rtl()))
+        .collect();
+    assert_eq!(
+        runs,
+        vec![
+            ("abc ", false),
+            ("\u{5D0} ", true),
+            ("12", false),
+            (" ", true),
+            ("xyz", false)
+        ]
+    );
+}
+
+#[test]
+fn direction_override_pins_range_to_ltr() {
+    let mut cx = TestContext::new();
+    // In a right-to-left paragraph, the space between the digits is resolved
+    // as right to left, so the digits are displayed in reverse order.
+    let text = "\u{5D0} 1 2 \u{5D1}";
+    let x = |layout: &parley::Layout<Color>, range| layout.rects_for_source_range(range)[0].x0;
+    let layout = cx.layout(text, 16., None);
+    assert!(x(&layout, 5..6) < x(&layout, 3..4));
+    let layout = cx.layout_with(text, 16., None, |builder| {
+        builder.push(
+            &StyleProperty::DirectionOverride(Some(Direction::LeftToRight)),
+            3..6,
+        );
+    });
+    assert!(x(&layout, 3..4) < x(&layout, 5..6));
+    let runs: Vec<_> = layout
+        .runs()
+        .map(|run| (&text[run.text_range()], run.is_rtl()))
+        .collect();
+    assert_eq!(
+        runs,
+        vec![("\u{5D0} ", true), ("1 2", false), (" \u{5D1}", true)]
+    );
+    // The surrounding Hebrew text keeps its right-to-left order, with the
+    // pinned range between its words.
+    let alef = x(&layout, 0..2);
+    let bet = x(&layout, 7..9);
+    assert!(bet < x(&layout, 3..4) && x(&layout, 5..6) < alef);
+    // An override applies to text that otherwise needs no bidi resolution.
+    let layout = cx.layout_with("abc", 16., None, |builder| {
+        builder.push_default(&StyleProperty::DirectionOverride(Some(
+            Direction::RightToLeft,
+        )));
+    });
+    assert!(layout.runs().all(|run| run.is_rtl()));
+    assert!(x(&layout, 2..3) < x(&layout, 0..1));
+}
+
+#[test]
+fn brackets_are_mirrored_in_rtl_runs() {
+    let mut cx = TestContext::new();
+    let ltr = glyph_ids(&cx.layout("()[]<>", 16., None));
+    // Each bracket is between Hebrew letters, so all of them are resolved
+    // as right to left.
+    let text = "\u{5D0}(\u{5D0})\u{5D0}[\u{5D0}]\u{5D0}<\u{5D0}>\u{5D0}";
+    let layout = cx.layout(text, 16., None);
+    let mut mirrored = vec![];
+    for run in layout.runs() {
+        assert!(run.is_rtl());
+        for cluster in run.clusters() {
+            if text[cluster.text_range()].is_ascii() {
+                let offset = cluster.text_range().start;
+                mirrored.push((offset, cluster.glyphs().next().unwrap().id));
+            }
+        }
+    }
+    mirrored.sort();
+    // Opening and closing brackets swap their glyphs.
+    let expected = vec![ltr[1], ltr[0], ltr[3], ltr[2], ltr[5], ltr[4]];
+    let ids: Vec<_> = mirrored.into_iter().map(|(_, id)| id).collect();
+    assert_eq!(ids, expected);
+}
+
+#[test]
+fn auto_direction_follows_first_strong_character() {
+    let mut cx = TestContext::new();
+    let arabic_first = "\u{645}\u{631}\u{62D}\u{628}\u{627} hello";
+    let latin_first = "hello \u{645}\u{631}\u{62D}\u{628}\u{627}";
+    // By default, the base direction is left-to-right.
+    assert_eq!(
+        cx.layout(arabic_first, 16., None).direction(),
+        Direction::LeftToRight
+    );
+    cx.lcx.set_base_direction(None);
+    let layout = cx.layout(arabic_first, 16., None);
+    assert_eq!(layout.direction(), Direction::RightToLeft);
+    assert!(layout.runs().next().unwrap().is_rtl());
+    assert_eq!(
+        cx.layout(latin_first, 16., None).direction(),
+        Direction::LeftToRight
+    );
+    // Text without right-to-left characters resolves to left-to-right,
+    // whatever the previous layout resolved to.
+    cx.layout(arabic_first, 16., None);
+    assert_eq!(
+        cx.layout("hello", 16., None).direction(),
+        Direction::LeftToRight
+    );
+    // An explicit direction applies regardless of the text.
+    cx.lcx.set_base_direction(Some(Direction::RightToLeft));
+    assert_eq!(
+        cx.layout("hello", 16., None).direction(),
+        Direction::RightToLeft
+    );
+}
+
+#[test]
+fn offset_at_x_on_rtl_line() {
+    let mut cx = TestContext::new();
+    let layout = cx.layout(ARABIC, 16., None);
+    let line = layout.get(0).unwrap();
+    let width = line.metrics().advance;
+    // The rightmost position is the logical start of the line and the
+    // leftmost position is its logical end.
+    assert_eq!(line.offset_at_x(width - 0.1), (0, Affinity::Downstream));
+    assert_eq!(line.offset_at_x(width + 10.), (0, Affinity::Downstream));
+    assert_eq!(line.offset_at_x(0.1), (ARABIC.len(), Affinity::Upstream));
+    assert_eq!(line.offset_at_x(-10.), (ARABIC.len(), Affinity::Upstream));
+    // The right half of the first character maps to its start, and the
+    // left half to its end.
+    let first = layout.rects_for_source_range(0..2)[0];
+    let x = first.x1 as f32 - 1.;
+    assert_eq!(line.offset_at_x(x), (0, Affinity::Downstream));
+    let x = first.x0 as f32 + 1.;
+    assert_eq!(line.offset_at_x(x), (2, Affinity::Upstream));
+}
+
+#[test]
+fn visual_text_reorders_rtl_runs() {
+    let mut cx = TestContext::new();
+    let text = "ab \u{5D0}\u{5D1}\u{5D2} cd";
+    let layout = cx.layout(text, 16., None);
+    let line = layout.get(0).unwrap();
+    // The Hebrew letters are displayed from right to left between the
+    // Latin words.
+    assert_eq!(line.visual_text(text), "ab \u{5D2}\u{5D1}\u{5D0} cd");
+    // In a left-to-right paragraph, only the Arabic run is reversed, while
+    // a right-to-left paragraph also reverses the order of the runs.
+    let text = format!("{} ab", ARABIC);
+    let reversed: String = ARABIC.chars().rev().collect();
+    let layout = cx.layout(&text, 16., None);
+    let visual = layout.get(0).unwrap().visual_text(&text);
+    assert_eq!(visual, format!("{} ab", reversed));
+    cx.lcx.set_base_direction(Some(Direction::RightToLeft));
+    let layout = cx.layout(&text, 16., None);
+    let visual = layout.get(0).unwrap().visual_text(&text);
+    assert_eq!(visual, format!("ab {}", reversed));
+}
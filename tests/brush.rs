@@ -0,0 +1,110 @@
+mod common;
+
+use common::*;
+use parley::layout::{Alignment, PropertyValue};
+use parley::style::{Brush, FontFamily, FontStack, StyleProperty};
+use parley::LayoutContext;
+
+/// Brush carrying a horizontal gradient spanning a range of text.
+#[derive(Clone, PartialEq, Default, Debug)]
+struct Gradient {
+    start: f32,
+    end: f32,
+    stops: Vec<[u8; 4]>,
+}
+
+impl Brush for Gradient {}
+
+#[test]
+fn brush_is_preserved_across_run_splits() {
+    let mut fcx = font_context();
+    let mut lcx = LayoutContext::<Gradient>::new();
+    // The gradient covers Latin and Hebrew text, which are shaped as
+    // separate runs, and the range is broken across lines.
+    let text = "one two \u{5D0}\u{5D1}\u{5D2} three";
+    let gradient = Gradient {
+        start: 10.,
+        end: 90.,
+        stops: vec![[255, 0, 0, 255], [0, 0, 255, 255]],
+    };
+    let mut builder = lcx.ranged_builder(&mut fcx, text, 1.);
+    builder.push_default(&StyleProperty::FontStack(FontStack::Single(
+        FontFamily::Named(FAMILY),
+    )));
+    builder.push_default(&StyleProperty::FontSize(16.));
+    builder.push(&StyleProperty::Brush(gradient.clone()), 4..text.len());
+    let mut layout = builder.build();
+    layout.break_all_lines(Some(100.), Alignment::Start);
+    assert!(layout.len() > 1);
+    let brushes: Vec<_> = layout
+        .lines()
+        .flat_map(|line| {
+            line.glyph_runs()
+                .map(|glyph_run| glyph_run.style().brush.clone())
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    // Only the first word is outside the range. The rest is split into at
+    // least the Latin, Hebrew and final Latin glyph runs.
+    assert_eq!(brushes[0], Gradient::default());
+    assert!(brushes.len() >= 4);
+    for brush in &brushes[1..] {
+        assert_eq!(*brush, gradient);
+    }
+}
+
+#[test]
+fn user_data_is_preserved_across_run_splits() {
+    let mut cx = TestContext::new();
+    // The tagged range spans a font size change and Hebrew text, which are
+    // shaped as separate runs, and is broken across lines.
+    let text = "one two \u{5D0}\u{5D1}\u{5D2} three four";
+    let tagged = 4..text.len() - 5;
+    let layout = cx.layout_with(text, 16., Some(100.), |builder| {
+        builder.push(&StyleProperty::UserData(7), tagged.clone());
+        builder.push(&StyleProperty::FontSize(20.), 5..6);
+    });
+    assert!(layout.len() > 1);
+    assert!(layout.runs().count() >= 5);
+    let styles = layout.styles();
+    for line in layout.lines() {
+        for run in line.runs() {
+            for cluster in run.clusters() {
+                let range = cluster.text_range();
+                let expected = if tagged.contains(&range.start) { 7 } else { 0 };
+                for glyph in cluster.glyphs() {
+                    assert_eq!(styles[glyph.style_index()].user_data, expected);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn run_user_data_covers_every_split_run() {
+    let mut cx = TestContext::new();
+    let text = "one two \u{5D0}\u{5D1}\u{5D2} three four";
+    let tagged = 4..text.len() - 5;
+    let layout = cx.layout_with(text, 16., Some(100.), |builder| {
+        builder.push(&StyleProperty::UserData(7), tagged.clone());
+        builder.push(&StyleProperty::FontSize(20.), 5..6);
+    });
+    let mut tagged_runs = 0;
+    for line in layout.lines() {
+        for run in line.runs() {
+            let range = run.text_range();
+            let value = run.user_data();
+            if range.start >= tagged.start && range.end <= tagged.end {
+                assert_eq!(value, PropertyValue::Uniform(7));
+                tagged_runs += 1;
+            } else if range.end <= tagged.start || range.start >= tagged.end {
+                assert_eq!(value, PropertyValue::Uniform(0));
+            } else {
+                assert!(value.is_mixed());
+            }
+        }
+    }
+    // The range is split by the font size change, the script change and
+    // the line breaks.
+    assert!(tagged_runs >= 3);
+}
@@ -0,0 +1,386 @@
+mod common;
+
+use common::*;
+use parley::layout::{Affinity, Alignment, PropertyValue};
+use parley::style::{FontStack, FontWeight, StyleProperty};
+use parley::Layout;
+
+#[test]
+fn required_fonts_keeps_pushed_styles() {
+    let mut cx = TestContext::new();
+    let text = "abc def";
+    let mut builder = cx.builder(text, 16.);
+    builder.push(&StyleProperty::FontSize(24.), 0..3);
+    let requests = builder.required_fonts();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].text_range, 0..text.len());
+    assert_eq!(requests[0].family, FAMILY);
+    // Styles pushed after the query still apply.
+    builder.push(&StyleProperty::FontSize(32.), 4..7);
+    let mut layout = builder.build();
+    layout.break_all_lines(None, Alignment::Start);
+    let sizes: Vec<_> = layout
+        .runs()
+        .map(|run| (run.text_range(), run.font_size()))
+        .collect();
+    assert_eq!(sizes, vec![(0..3, 24.), (3..4, 16.), (4..7, 32.)]);
+}
+
+#[test]
+fn layout_key_keeps_pushed_styles() {
+    let mut cx = TestContext::new();
+    let text = "abc def";
+    let mut builder = cx.builder(text, 16.);
+    let before = builder.layout_key(None, Alignment::Start);
+    assert_eq!(before, builder.layout_key(None, Alignment::Start));
+    builder.push(&StyleProperty::FontSize(32.), 4..7);
+    let after = builder.layout_key(None, Alignment::Start);
+    assert_ne!(before, after);
+    let mut layout = builder.build();
+    layout.break_all_lines(None, Alignment::Start);
+    assert_eq!(layout.runs().last().unwrap().font_size(), 32.);
+}
+
+#[test]
+fn layout_key_depends_on_inputs() {
+    let mut cx = TestContext::new();
+    let key = |cx: &mut TestContext, text: &str, size: f32, max_advance: Option<f32>| {
+        cx.builder(text, size)
+            .layout_key(max_advance, Alignment::Start)
+    };
+    let base = key(&mut cx, "abc", 16., None);
+    assert_eq!(base, key(&mut cx, "abc", 16., None));
+    assert_ne!(base, key(&mut cx, "abd", 16., None));
+    assert_ne!(base, key(&mut cx, "abc", 17., None));
+    assert_ne!(base, key(&mut cx, "abc", 16., Some(100.)));
+}
+
+#[test]
+fn append_paragraphs() {
+    let mut cx = TestContext::new();
+    let messages = ["hello there", "a longer second message", "bye"];
+    let width = cx.layout("a longer ", 16., None).width() + 1.;
+    let paragraphs: Vec<_> = messages
+        .iter()
+        .map(|message| cx.layout(message, 16., Some(width)))
+        .collect();
+    let heights: Vec<_> = paragraphs.iter().map(|layout| layout.height()).collect();
+    let expected: Vec<_> = messages
+        .iter()
+        .zip(&paragraphs)
+        .flat_map(|(message, layout)| line_texts(layout, message))
+        .collect();
+    let mut layouts = paragraphs.into_iter();
+    let mut layout = layouts.next().unwrap();
+    let first_line = layout.lines().next().unwrap().metrics().baseline;
+    let mut offset = messages[0].len();
+    for (message, paragraph) in messages[1..].iter().zip(layouts) {
+        layout.append(paragraph, offset);
+        offset += message.len();
+    }
+    assert_eq!(layout.height(), heights.iter().sum::<f32>());
+    // The existing lines are untouched, and the appended lines follow them
+    // with source ranges relative to the running offset.
+    let text = messages.concat();
+    assert_eq!(line_texts(&layout, &text), expected);
+    assert!(expected.len() > messages.len());
+    let baselines: Vec<_> = layout.lines().map(|line| line.metrics().baseline).collect();
+    assert_eq!(baselines[0], first_line);
+    assert!(baselines.windows(2).all(|pair| pair[0] < pair[1]));
+    let last = layout.rects_for_source_range(text.len() - 1..text.len())[0];
+    assert!(last.y0 as f32 >= heights[0] + heights[1]);
+}
+
+#[test]
+#[should_panic(expected = "maximum of 65536 styles")]
+fn append_rejects_too_many_styles() {
+    let mut cx = TestContext::new();
+    let text = "a".repeat(40_000);
+    let mut build = || {
+        cx.layout_with(&text, 16., None, |builder| {
+            for index in 0..text.len() {
+                builder.push(&StyleProperty::UserData(index as u64), index..index + 1);
+            }
+        })
+    };
+    let mut layout = build();
+    let other = build();
+    layout.append(other, text.len());
+}
+
+#[test]
+fn stack_cached_paragraphs() {
+    let mut cx = TestContext::new();
+    let width = cx.layout("second para ", 16., None).width() + 1.;
+    let layout_paragraph = |cx: &mut TestContext, text: &str| {
+        cx.lcx.layout_paragraph(
+            &mut cx.fcx,
+            text,
+            1.,
+            Some(width),
+            Alignment::Start,
+            |builder| {
+                builder.push_default(&StyleProperty::FontStack(FontStack::Source(FAMILY)));
+                builder.push_default(&StyleProperty::FontSize(16.));
+            },
+        )
+    };
+    let mut texts = vec!["first\n", "second\n", "third"];
+    let mut paragraphs: Vec<_> = texts
+        .iter()
+        .map(|text| layout_paragraph(&mut cx, text))
+        .collect();
+    let layout = Layout::from_paragraphs(paragraphs.clone());
+    let text = texts.concat();
+    assert_eq!(
+        line_texts(&layout, &text),
+        vec!["first\n", "second\n", "third"]
+    );
+    let baselines: Vec<_> = layout.lines().map(|line| line.metrics().baseline).collect();
+    // Only the edited middle paragraph is laid out again.
+    texts[1] = "second paragraph, edited\n";
+    paragraphs[1] = layout_paragraph(&mut cx, texts[1]);
+    let edited = Layout::from_paragraphs(paragraphs.clone());
+    let text = texts.concat();
+    assert_eq!(
+        line_texts(&edited, &text),
+        vec!["first\n", "second ", "paragraph, ", "edited\n", "third"]
+    );
+    let edited_baselines: Vec<_> = edited.lines().map(|line| line.metrics().baseline).collect();
+    assert_eq!(edited_baselines[0], baselines[0]);
+    // The last paragraph moves down by the growth of the middle one.
+    let growth = paragraphs[1].height() - layout.lines().nth(1).unwrap().metrics().size();
+    assert!(growth > 0.);
+    assert!((edited_baselines[4] - baselines[2] - growth).abs() < 0.01);
+    assert_eq!(
+        edited.height(),
+        paragraphs
+            .iter()
+            .map(|paragraph| paragraph.height())
+            .sum::<f32>()
+    );
+}
+
+#[test]
+fn fit_text_into_small_box() {
+    let mut cx = TestContext::new();
+    let text = "The quick brown fox jumps over the lazy dog and keeps on running";
+    let (max_width, max_height) = (200., 60.);
+    let styles = |builder: &mut parley::context::RangedBuilder<Color, &str>| {
+        builder.push_default(&StyleProperty::FontStack(FontStack::Source(FAMILY)));
+    };
+    let fit = |cx: &mut TestContext, max_width, max_height| {
+        cx.lcx.fit_text(
+            &mut cx.fcx,
+            text,
+            max_width,
+            max_height,
+            None,
+            4.0..=64.,
+            styles,
+        )
+    };
+    let size = fit(&mut cx, max_width, max_height);
+    assert!(size > 4. && size < 64.);
+    let layout = cx.layout(text, size, Some(max_width));
+    assert!(layout.len() > 1);
+    assert!(layout.width() <= max_width);
+    assert!(layout.height() <= max_height);
+    // A slightly larger size no longer fits.
+    let larger = cx.layout(text, size + 0.5, Some(max_width));
+    assert!(larger.height() > max_height || larger.min_width_without_overflow() > max_width);
+    // The bounds of the range are used when the text fits at any size or at
+    // none.
+    assert_eq!(fit(&mut cx, 10000., 10000.), 64.);
+    assert_eq!(fit(&mut cx, 1., 1.), 4.);
+}
+
+#[test]
+fn fit_text_within_max_lines() {
+    let mut cx = TestContext::new();
+    let text = "The quick brown fox jumps over the lazy dog";
+    let styles = |builder: &mut parley::context::RangedBuilder<Color, &str>| {
+        builder.push_default(&StyleProperty::FontStack(FontStack::Source(FAMILY)));
+    };
+    let max_width = 200.;
+    let fit = |cx: &mut TestContext, max_lines| {
+        cx.lcx.fit_text(
+            &mut cx.fcx,
+            text,
+            max_width,
+            10000.,
+            max_lines,
+            4.0..=64.,
+            styles,
+        )
+    };
+    // Without a line limit, the height allows the largest size.
+    assert_eq!(fit(&mut cx, None), 64.);
+    // Limiting the text to two lines selects a smaller size at which it
+    // wraps onto two lines, while a slightly larger size needs more.
+    let size = fit(&mut cx, Some(2));
+    assert!(size > 4. && size < 64.);
+    assert!(cx.layout(text, size, Some(max_width)).len() <= 2);
+    assert!(cx.layout(text, size + 0.5, Some(max_width)).len() > 2);
+    // A single line requires a size at which the whole text fits the width.
+    let single = fit(&mut cx, Some(1));
+    assert!(single < size);
+    assert_eq!(cx.layout(text, single, Some(max_width)).len(), 1);
+}
+
+#[test]
+fn style_at_caret_uses_affinity() {
+    let mut cx = TestContext::new();
+    let text = "bold plain";
+    let layout = cx.layout_with(text, 16., None, |builder| {
+        builder.push(&StyleProperty::FontWeight(FontWeight::BOLD), 0..5);
+        builder.push(&StyleProperty::FontSize(20.), 7..8);
+    });
+    let weight = |offset, affinity| layout.style_at(offset, affinity).unwrap().font_weight;
+    assert_eq!(weight(2, Affinity::Downstream), FontWeight::BOLD);
+    assert_eq!(weight(2, Affinity::Upstream), FontWeight::BOLD);
+    // The caret after the bold range takes the style of the side selected
+    // by the affinity.
+    assert_eq!(weight(5, Affinity::Upstream), FontWeight::BOLD);
+    assert_eq!(weight(5, Affinity::Downstream), FontWeight::NORMAL);
+    // Offsets at the start and end of the text resolve to the first and
+    // last ranges.
+    assert_eq!(weight(0, Affinity::Upstream), FontWeight::BOLD);
+    assert_eq!(weight(text.len(), Affinity::Downstream), FontWeight::NORMAL);
+    let size = |offset, affinity| layout.style_at(offset, affinity).unwrap().font_size;
+    assert_eq!(size(7, Affinity::Upstream), 16.);
+    assert_eq!(size(7, Affinity::Downstream), 20.);
+    assert_eq!(size(8, Affinity::Upstream), 20.);
+}
+
+#[test]
+fn style_summary_reports_mixed_weight() {
+    let mut cx = TestContext::new();
+    let text = "bold plain";
+    let layout = cx.layout_with(text, 16., None, |builder| {
+        builder.push(&StyleProperty::FontWeight(FontWeight::BOLD), 0..5);
+        builder.push(&StyleProperty::Underline(true), 2..8);
+    });
+    // The selection spans bold and regular text, all of it underlined.
+    let summary = layout.style_summary(3..7).unwrap();
+    assert!(summary.font_weight.is_mixed());
+    assert_eq!(summary.font_weight.uniform(), None);
+    assert_eq!(summary.underline, PropertyValue::Uniform(true));
+    assert_eq!(summary.font_size, PropertyValue::Uniform(16.));
+    // Within the bold range, the weight is uniform.
+    let summary = layout.style_summary(0..4).unwrap();
+    assert_eq!(summary.font_weight.uniform(), Some(&FontWeight::BOLD));
+    assert!(summary.underline.is_mixed());
+    // An empty range reports the style following the caret.
+    let summary = layout.style_summary(5..5).unwrap();
+    assert_eq!(summary.font_weight.uniform(), Some(&FontWeight::NORMAL));
+}
+
+#[test]
+fn adjacent_runs_are_coalesced() {
+    let glyph_runs = |layout: &Layout<Color>| -> Vec<usize> {
+        layout
+            .lines()
+            .map(|line| line.glyph_runs().count())
+            .collect()
+    };
+    let mut cx = TestContext::new();
+    // The words are itemized as separate runs of the same font and script
+    // because their locales differ, while the Hebrew word has a different
+    // bidi level.
+    let text = "abc def \u{5D0}\u{5D1}";
+    let build = |cx: &mut TestContext, max_advance| {
+        cx.layout_with(text, 16., max_advance, |builder| {
+            builder.push(&StyleProperty::Locale(Some("fr")), 4..8);
+        })
+    };
+    let layout = build(&mut cx, None);
+    assert_eq!(layout.runs().count(), 3);
+    assert_eq!(glyph_runs(&layout), vec![3]);
+    cx.lcx.set_run_coalescing_enabled(true);
+    let layout = build(&mut cx, None);
+    assert_eq!(layout.runs().count(), 2);
+    // Glyph runs are still split where the style changes.
+    assert_eq!(glyph_runs(&layout), vec![3]);
+    let run = layout.runs().next().unwrap();
+    assert_eq!(run.text_range(), 0..8);
+    // The clusters of the merged run still map to the source text.
+    let ranges: Vec<_> = run.clusters().map(|cluster| cluster.text_range()).collect();
+    assert_eq!(ranges[3..], [3..4, 4..5, 5..6, 6..7, 7..8]);
+    // Merged runs are still split at line boundaries.
+    let width = cx.layout("def ", 16., None).width() + 1.;
+    let layout = build(&mut cx, Some(width));
+    assert_eq!(
+        line_texts(&layout, text),
+        vec!["abc ", "def ", "\u{5D0}\u{5D1}"]
+    );
+    assert_eq!(glyph_runs(&layout), vec![1, 1, 1]);
+}
+
+#[test]
+fn runs_in_different_scripts_are_not_coalesced() {
+    let mut cx = TestContext::new();
+    cx.lcx.set_run_coalescing_enabled(true);
+    // The Latin and Greek words are drawn with the same font at the same
+    // level, but shaped for different scripts.
+    let text = "abc \u{3B1}\u{3B2}\u{3B3}";
+    let layout = cx.layout(text, 16., None);
+    let ranges: Vec<_> = layout.runs().map(|run| run.text_range()).collect();
+    assert_eq!(ranges, vec![0..4, 4..10]);
+    let font = layout.runs().next().unwrap().font().id();
+    assert!(layout.runs().all(|run| run.font().id() == font));
+    assert_eq!(layout.lines().next().unwrap().glyph_runs().count(), 2);
+}
+
+#[test]
+fn font_sizes_are_clamped_to_bounds() {
+    let mut cx = TestContext::new();
+    let text = "abc def";
+    let expected = cx.layout(text, 12., None);
+    cx.lcx.set_font_size_bounds(Some((12., 48.)));
+    // A size below the minimum is raised to it, and the layout, including
+    // its relative line height, matches one built at the minimum.
+    let layout = cx.layout(text, 6., None);
+    assert_eq!(layout.runs().next().unwrap().font_size(), 12.);
+    assert_eq!(layout.width(), expected.width());
+    assert_eq!(layout.height(), expected.height());
+    // A size above the maximum is lowered to it.
+    let layout = cx.layout_with(text, 16., None, |builder| {
+        builder.push(&StyleProperty::FontSize(100.), 4..7);
+    });
+    let sizes: Vec<_> = layout.runs().map(|run| run.font_size()).collect();
+    assert_eq!(sizes, vec![16., 48.]);
+    // Without bounds, sizes are used as specified.
+    cx.lcx.set_font_size_bounds(None);
+    let layout = cx.layout(text, 6., None);
+    assert_eq!(layout.runs().next().unwrap().font_size(), 6.);
+}
+
+#[cfg(feature = "profiling")]
+#[test]
+fn profile_counts_match_layout() {
+    let mut cx = TestContext::new();
+    let text = "office abc \u{5D0}\u{5D1}\u{5D2} def ghi";
+    let width = cx.layout("office abc ", 16., None).width() + 1.;
+    let mut layout = cx.layout_with(text, 16., Some(width), |builder| {
+        builder.push(&StyleProperty::FontSize(20.), 7..10);
+    });
+    let profile = layout.profile();
+    let glyphs: usize = layout
+        .runs()
+        .map(|run| {
+            run.clusters()
+                .map(|cluster| cluster.glyphs().count())
+                .sum::<usize>()
+        })
+        .sum();
+    assert!(layout.runs().count() > 1);
+    assert!(layout.len() > 1);
+    assert_eq!(profile.runs, layout.runs().count());
+    assert_eq!(profile.glyphs, glyphs);
+    assert_eq!(profile.lines, layout.len());
+    // Breaking the lines again replaces the line count.
+    layout.break_all_lines(None, Alignment::Start);
+    assert_eq!(layout.profile().lines, 1);
+    assert_eq!(layout.profile().runs, layout.runs().count());
+}
@@ -0,0 +1,314 @@
+mod common;
+
+use common::*;
+use parley::context::{ControlCharRendering, MarkHandling};
+use parley::layout::{Alignment, Cluster, WhitespaceMarker};
+use parley::style::{StyleProperty, Wrap};
+use parley::Layout;
+
+/// Returns the source ranges of the clusters overlapping the range.
+fn cluster_ranges(layout: &Layout<Color>, range: std::ops::Range<usize>) -> Vec<(usize, usize)> {
+    layout
+        .clusters_for_source_range(range)
+        .map(|cluster| {
+            let range = cluster.text_range();
+            (range.start, range.end)
+        })
+        .collect()
+}
+
+/// Returns the source offsets of the clusters with the flag set.
+fn flagged<'a>(
+    layout: &'a Layout<Color>,
+    flag: impl Fn(&Cluster<'a, Color>) -> bool,
+) -> Vec<usize> {
+    layout
+        .clusters_for_source_range(0..usize::MAX)
+        .filter(|cluster| flag(cluster))
+        .map(|cluster| cluster.text_range().start)
+        .collect()
+}
+
+#[test]
+fn clusters_for_range_across_spaces_and_lines() {
+    let mut cx = TestContext::new();
+    let text = "one   two three";
+    // Wrap after the run of spaces.
+    let width = cx.layout("one   two ", 16., None).width() + 1.;
+    let mut layout = cx.builder(text, 16.).build();
+    // Before line breaking, the clusters come from the shaped runs.
+    assert_eq!(cluster_ranges(&layout, 2..4), vec![(2, 3), (3, 4)]);
+    layout.break_all_lines(Some(width), Alignment::Start);
+    assert_eq!(line_texts(&layout, text), vec!["one   two ", "three"]);
+    // A highlight covering the spaces and part of each line.
+    let ranges = cluster_ranges(&layout, 2..12);
+    let expected: Vec<_> = (2..12).map(|offset| (offset, offset + 1)).collect();
+    assert_eq!(ranges, expected);
+    assert!(cluster_ranges(&layout, 5..5).is_empty());
+}
+
+#[test]
+fn clusters_for_range_in_ligature_and_bidi() {
+    let mut cx = TestContext::new();
+    // The range starts inside the "ffi" ligature and ends inside the Hebrew
+    // word, whose letters are two bytes long.
+    let text = "office \u{5D0}\u{5D1}";
+    let layout = cx.layout(text, 16., None);
+    assert_eq!(
+        cluster_ranges(&layout, 2..10),
+        vec![(2, 3), (3, 4), (4, 5), (5, 6), (6, 7), (7, 9), (9, 11)]
+    );
+}
+
+#[test]
+fn cluster_break_flags() {
+    let mut cx = TestContext::new();
+    let text = "foo bar\nbaz";
+    let layout = cx.layout(text, 16., None);
+    // Words start at the space and after it, on both sides of the newline.
+    assert_eq!(
+        flagged(&layout, Cluster::is_word_boundary),
+        vec![0, 3, 4, 7, 8]
+    );
+    // Lines may break after the space and must break after the newline,
+    // but not after the last cluster.
+    assert_eq!(
+        flagged(&layout, Cluster::is_line_break_opportunity_after),
+        vec![3, 7]
+    );
+    assert_eq!(flagged(&layout, Cluster::is_mandatory_break_after), vec![7]);
+    assert_eq!(flagged(&layout, Cluster::is_whitespace), vec![3, 7]);
+    // Soft break opportunities are suppressed in no-wrap ranges.
+    let layout = cx.layout_with(text, 16., None, |builder| {
+        builder.push_default(&StyleProperty::Wrap(Wrap::NoWrap));
+    });
+    assert_eq!(
+        flagged(&layout, Cluster::is_line_break_opportunity_after),
+        vec![7]
+    );
+    // Every space is followed by a break opportunity in break-spaces ranges.
+    let text = "a  b";
+    let layout = cx.layout_with(text, 16., None, |builder| {
+        builder.push_default(&StyleProperty::Wrap(Wrap::BreakSpaces));
+    });
+    assert_eq!(
+        flagged(&layout, Cluster::is_line_break_opportunity_after),
+        vec![1, 2]
+    );
+}
+
+#[test]
+fn justified_spaces_report_natural_advance() {
+    let mut cx = TestContext::new();
+    let text = "aa bb cc dd ee";
+    let width = cx.layout("aa bb cc ", 16., None).width() + 10.;
+    let mut layout = cx.layout(text, 16., None);
+    layout.break_all_lines(Some(width), Alignment::Justified);
+    assert_eq!(line_texts(&layout, text), vec!["aa bb cc ", "dd ee"]);
+    let expanded = |layout: &Layout<Color>, range| -> Vec<usize> {
+        layout
+            .clusters_for_source_range(range)
+            .filter(|cluster| cluster.advance() != cluster.natural_advance())
+            .map(|cluster| cluster.text_range().start)
+            .collect()
+    };
+    // Only the inner spaces of the justified line are expanded.
+    assert_eq!(expanded(&layout, 0..text.len()), vec![2, 5]);
+    let space = layout.clusters_for_source_range(2..3).next().unwrap();
+    assert!(space.advance() > space.natural_advance());
+    let plain = cx.layout(text, 16., None);
+    let natural = plain
+        .clusters_for_source_range(2..3)
+        .next()
+        .unwrap()
+        .advance();
+    assert_eq!(space.natural_advance(), natural);
+    // Breaking again without justification restores the natural advances.
+    layout.break_all_lines(Some(width), Alignment::Start);
+    assert!(expanded(&layout, 0..text.len()).is_empty());
+}
+
+#[test]
+fn orphan_marks_are_handled() {
+    let mut cx = TestContext::new();
+    // A combining acute accent with no base at the start of the text.
+    let text = "\u{301}ab";
+    let first = |cx: &mut TestContext| {
+        let layout = cx.layout(text, 16., None);
+        let clusters: Vec<_> = layout.clusters_for_source_range(0..usize::MAX).collect();
+        // The mark keeps its own cluster regardless of its treatment.
+        let ranges: Vec<_> = clusters
+            .iter()
+            .map(|cluster| cluster.text_range())
+            .collect();
+        assert_eq!(ranges, vec![0..2, 2..3, 3..4]);
+        let mark = &clusters[0];
+        let ids: Vec<_> = mark.glyphs().map(|glyph| glyph.id).collect();
+        (ids, mark.advance())
+    };
+    let (accent, advance) = first(&mut cx);
+    assert_eq!(accent.len(), 1);
+    assert_eq!(advance, 0.);
+    let circle = glyph_ids(&cx.layout("\u{25CC}", 16., None))[0];
+    cx.lcx.set_mark_handling(MarkHandling::InsertDottedCircle);
+    let (ids, advance) = first(&mut cx);
+    assert_eq!(ids, vec![circle, accent[0]]);
+    assert!(advance > 0.);
+    cx.lcx.set_mark_handling(MarkHandling::Drop);
+    let (ids, advance) = first(&mut cx);
+    assert!(ids.is_empty());
+    assert_eq!(advance, 0.);
+}
+
+#[test]
+fn control_characters_are_rendered_visibly() {
+    let mut cx = TestContext::new();
+    let text = "a\u{0}b\u{7}";
+    let controls = |cx: &mut TestContext| {
+        let layout = cx.layout(text, 16., None);
+        let clusters: Vec<_> = layout.clusters_for_source_range(0..usize::MAX).collect();
+        // Each control character remains a single cluster.
+        let ranges: Vec<_> = clusters
+            .iter()
+            .map(|cluster| cluster.text_range())
+            .collect();
+        assert_eq!(ranges, vec![0..1, 1..2, 2..3, 3..4]);
+        [&clusters[1], &clusters[3]].map(|cluster| {
+            let ids: Vec<_> = cluster.glyphs().map(|glyph| glyph.id).collect();
+            (ids, cluster.advance())
+        })
+    };
+    // By default, the font renders the controls as invisible.
+    for (ids, advance) in controls(&mut cx) {
+        assert!(ids.is_empty());
+        assert_eq!(advance, 0.);
+    }
+    // None of the available fonts has control pictures, so both are drawn as
+    // the missing glyph, but with a visible advance.
+    cx.lcx
+        .set_control_char_rendering(ControlCharRendering::Pictures);
+    let [nul, bel] = controls(&mut cx);
+    assert_eq!(nul.0.len(), 1);
+    assert!(nul.1 > 0.);
+    assert_eq!(nul, bel);
+    let square = glyph_ids(&cx.layout("\u{25A1}", 16., None));
+    assert_ne!(square, vec![0]);
+    cx.lcx.set_control_char_rendering(ControlCharRendering::Box);
+    let [nul, bel] = controls(&mut cx);
+    assert_eq!(nul.0, square);
+    assert!(nul.1 > 0.);
+    assert_eq!(nul, bel);
+}
+
+#[test]
+fn glyph_source_offsets() {
+    let mut cx = TestContext::new();
+    let glyphs = |cx: &mut TestContext, text: &str| -> Vec<(u16, char)> {
+        let layout = cx.layout(text, 16., None);
+        let mut glyphs = vec![];
+        for cluster in layout.clusters_for_source_range(0..text.len()) {
+            let start = cluster.text_range().start;
+            for glyph in cluster.glyphs() {
+                let ch = text[start + glyph.source_offset()..]
+                    .chars()
+                    .next()
+                    .unwrap();
+                glyphs.push((glyph.id, ch));
+            }
+        }
+        glyphs
+    };
+    let id = |cx: &mut TestContext, text: &str| glyph_ids(&cx.layout(text, 16., None))[0];
+    // The font has no precomposed form of "q" with an acute accent, so the
+    // base and both marks are drawn as separate glyphs of one cluster.
+    let text = "aq\u{301}\u{323}b";
+    let layout = cx.layout(text, 16., None);
+    let ranges: Vec<_> = layout
+        .clusters_for_source_range(0..text.len())
+        .map(|cluster| cluster.text_range())
+        .collect();
+    assert_eq!(ranges, vec![0..1, 1..6, 6..7]);
+    let expected = ['a', 'q', '\u{301}', '\u{323}', 'b']
+        .map(|ch| (id(&mut cx, &ch.to_string()), ch))
+        .to_vec();
+    assert_eq!(glyphs(&mut cx, text), expected);
+    // A base and mark that are composed into a single glyph map to the
+    // base character.
+    let composed = id(&mut cx, "\u{E9}");
+    assert_eq!(glyphs(&mut cx, "e\u{301}"), vec![(composed, 'e')]);
+}
+
+#[test]
+fn revealed_whitespace_is_tagged() {
+    let mut cx = TestContext::new();
+    let text = "a b\tc\u{A0}d\ne";
+    let markers = |layout: &Layout<Color>| -> Vec<(usize, WhitespaceMarker)> {
+        layout
+            .clusters_for_source_range(0..text.len())
+            .filter_map(|cluster| {
+                let marker = cluster.whitespace_marker()?;
+                Some((cluster.text_range().start, marker))
+            })
+            .collect()
+    };
+    let advances = |layout: &Layout<Color>| -> Vec<f32> {
+        layout
+            .clusters_for_source_range(0..text.len())
+            .map(|cluster| cluster.advance())
+            .collect()
+    };
+    let hidden = cx.layout(text, 16., None);
+    assert!(markers(&hidden).is_empty());
+    cx.lcx.set_reveal_whitespace(true);
+    let revealed = cx.layout(text, 16., None);
+    assert_eq!(
+        markers(&revealed),
+        vec![
+            (1, WhitespaceMarker::Space),
+            (3, WhitespaceMarker::Tab),
+            (5, WhitespaceMarker::NoBreakSpace),
+            (8, WhitespaceMarker::Newline),
+        ]
+    );
+    // The advances, including that of the tab, are unchanged.
+    assert_eq!(advances(&revealed), advances(&hidden));
+    assert_eq!(WhitespaceMarker::Tab.symbol(), '\u{2192}');
+}
+
+#[test]
+fn glyph_range_covers_cluster_glyphs() {
+    let mut cx = TestContext::new();
+    // The registered fonts have no Indic script, so a base with two
+    // combining marks stands in for a cluster shaped to several glyphs.
+    let text = "aq\u{301}\u{323} office";
+    let layout = cx.layout(text, 16., None);
+    let run = layout.runs().next().unwrap();
+    let glyphs: Vec<_> = run
+        .clusters()
+        .flat_map(|cluster| cluster.glyphs().map(|glyph| glyph.id).collect::<Vec<_>>())
+        .collect();
+    let mut end = 0;
+    for cluster in run.clusters() {
+        let range = cluster.glyph_range();
+        // The ranges partition the glyphs of the run in logical order.
+        assert_eq!(range.start, end);
+        end = range.end;
+        let ids: Vec<_> = cluster.glyphs().map(|glyph| glyph.id).collect();
+        assert_eq!(glyphs[range], ids[..]);
+    }
+    assert_eq!(end, glyphs.len());
+    let ranges: Vec<_> = run
+        .clusters()
+        .map(|cluster| (cluster.text_range(), cluster.glyph_range()))
+        .collect();
+    // The marks belong to the cluster of their base, which has a glyph for
+    // each character.
+    assert_eq!(ranges[1], (1..6, 1..4));
+    assert_eq!(glyphs[1..4].len(), 3);
+    assert!(glyphs[1..4].contains(&84));
+    // The continuation clusters of the "ffi" ligature have empty ranges
+    // after the glyph of its first cluster.
+    assert_eq!(ranges[4].1.len(), 1);
+    assert!(ranges[5].1.is_empty() && ranges[6].1.is_empty());
+    assert_eq!(ranges[5].1.start, ranges[4].1.end);
+}
@@ -0,0 +1,106 @@
+//! Shared setup for the integration tests.
+//!
+//! Layouts are built with the bundled DejaVu Sans font and fallback is
+//! restricted to it, so results do not depend on the fonts installed on the
+//! system.
+
+#![allow(dead_code)]
+
+use parley::context::RangedBuilder;
+use parley::layout::Alignment;
+use parley::style::{FontFamily, FontStack, StyleProperty};
+use parley::swash::Attributes;
+use parley::{FontContext, Layout, LayoutContext};
+
+pub const FONT_DATA: &[u8] = include_bytes!("../assets/DejaVuSans.ttf");
+pub const CONDENSED_FONT_DATA: &[u8] = include_bytes!("../assets/DejaVuSansCondensed.ttf");
+pub const FAMILY: &str = "DejaVu Sans";
+
+/// Brush used by the tests.
+pub type Color = [u8; 4];
+
+/// Returns a font context with the test fonts registered and the regular
+/// face as the only fallback.
+pub fn font_context() -> FontContext {
+    let mut fcx = FontContext::new();
+    let family = fcx.register_fonts(FONT_DATA.to_vec()).unwrap();
+    assert_eq!(family, FAMILY);
+    // The condensed face is also a member of the family.
+    fcx.register_fonts(CONDENSED_FONT_DATA.to_vec()).unwrap();
+    let regular = fcx
+        .query_font(FontFamily::Named(FAMILY), Attributes::default())
+        .unwrap();
+    fcx.set_fallback_restriction(Some(vec![regular.id()]));
+    fcx
+}
+
+/// Contexts for building layouts with the test font.
+pub struct TestContext {
+    pub fcx: FontContext,
+    pub lcx: LayoutContext<Color>,
+}
+
+impl TestContext {
+    pub fn new() -> Self {
+        Self {
+            fcx: font_context(),
+            lcx: LayoutContext::new(),
+        }
+    }
+
+    /// Returns a builder for the text with the test font and the specified
+    /// font size pushed as defaults.
+    pub fn builder<'a>(
+        &'a mut self,
+        text: &'a str,
+        size: f32,
+    ) -> RangedBuilder<'a, Color, &'a str> {
+        let mut builder = self.lcx.ranged_builder(&mut self.fcx, text, 1.);
+        builder.push_default(&StyleProperty::FontStack(FontStack::Single(
+            FontFamily::Named(FAMILY),
+        )));
+        builder.push_default(&StyleProperty::FontSize(size));
+        builder
+    }
+
+    /// Builds the text with the default styles and breaks the lines.
+    pub fn layout(&mut self, text: &str, size: f32, max_advance: Option<f32>) -> Layout<Color> {
+        self.layout_with(text, size, max_advance, |_| {})
+    }
+
+    /// Builds the text after applying additional styles to the builder and
+    /// breaks the lines.
+    pub fn layout_with(
+        &mut self,
+        text: &str,
+        size: f32,
+        max_advance: Option<f32>,
+        f: impl FnOnce(&mut RangedBuilder<Color, &str>),
+    ) -> Layout<Color> {
+        let mut builder = self.builder(text, size);
+        f(&mut builder);
+        let mut layout = builder.build();
+        layout.break_all_lines(max_advance, Alignment::Start);
+        layout
+    }
+}
+
+/// Returns the source text of each line of the layout.
+pub fn line_texts<'a>(layout: &Layout<Color>, text: &'a str) -> Vec<&'a str> {
+    layout
+        .lines()
+        .map(|line| &text[line.text_range()])
+        .collect()
+}
+
+/// Returns the glyph identifiers of the layout in visual order.
+pub fn glyph_ids(layout: &Layout<Color>) -> Vec<u16> {
+    layout
+        .lines()
+        .flat_map(|line| {
+            line.glyph_runs()
+                .flat_map(|run| run.glyphs().map(|glyph| glyph.id).collect::<Vec<_>>())
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use common::*;
+use parley::layout::{Cursor, Selection};
+use parley::Layout;
+
+/// Latin text around a Hebrew word, whose letters are displayed from right
+/// to left.
+const TEXT: &str = "abc \u{5D0}\u{5D1}\u{5D2} def";
+
+/// Returns the horizontal bounds of the clusters covering the source range.
+fn bounds(layout: &Layout<Color>, range: std::ops::Range<usize>) -> (f32, f32) {
+    let rects = layout.rects_for_source_range(range);
+    let x0 = rects.iter().map(|rect| rect.x0).fold(f64::MAX, f64::min);
+    let x1 = rects.iter().map(|rect| rect.x1).fold(f64::MIN, f64::max);
+    (x0 as f32, x1 as f32)
+}
+
+/// Returns the point a quarter of the way into the cluster from its left
+/// or right edge.
+fn point_in(layout: &Layout<Color>, range: std::ops::Range<usize>, left: bool) -> (f32, f32) {
+    let (x0, x1) = bounds(layout, range);
+    let x = if left {
+        x0 + (x1 - x0) * 0.25
+    } else {
+        x1 - (x1 - x0) * 0.25
+    };
+    (x, layout.height() * 0.5)
+}
+
+#[test]
+fn hit_test_rtl_cluster() {
+    let mut cx = TestContext::new();
+    let layout = cx.layout(TEXT, 16., None);
+    // The first Hebrew letter is displayed rightmost.
+    let (alef, bet) = (bounds(&layout, 4..6), bounds(&layout, 6..8));
+    assert!(bet.1 <= alef.0);
+    let (x, y) = point_in(&layout, 6..8, true);
+    let cursor = Cursor::from_point(&layout, x, y);
+    assert!(cursor.is_rtl);
+    assert_eq!((cursor.text_start, cursor.text_end), (6, 8));
+    // The left half of a right-to-left cluster is nearest its end.
+    assert_eq!(cursor.insert_point, 8);
+    assert_eq!(cursor.offset, bet.0);
+    let (x, y) = point_in(&layout, 6..8, false);
+    let cursor = Cursor::from_point(&layout, x, y);
+    assert_eq!(cursor.insert_point, 6);
+    assert_eq!(cursor.offset, bet.1);
+    // A selection starts at the logical offset of the visual edge.
+    assert_eq!(Selection::from_point(&layout, x, y).focus, 6);
+    let (x, y) = point_in(&layout, 6..8, true);
+    assert_eq!(Selection::from_point(&layout, x, y).focus, 8);
+}
+
+#[test]
+fn drag_from_ltr_into_rtl() {
+    let mut cx = TestContext::new();
+    let layout = cx.layout(TEXT, 16., None);
+    let (x, y) = point_in(&layout, 1..2, true);
+    let mut selection = Selection::from_point(&layout, x, y);
+    assert_eq!(selection.anchor, 1);
+    // Dragging to the left edge of the Hebrew word, which is displayed
+    // leftmost, selects the whole word.
+    let (x, y) = point_in(&layout, 8..10, true);
+    selection.extend_to_point(&layout, x, y);
+    assert_eq!(selection.text_range(), 1..10);
+    let highlight = bounds(&layout, selection.text_range());
+    assert_eq!(
+        highlight,
+        (bounds(&layout, 1..2).0, bounds(&layout, 4..6).1)
+    );
+    // Dragging to its right edge, which is its logical start, selects none
+    // of it.
+    let (x, y) = point_in(&layout, 4..6, false);
+    selection.extend_to_point(&layout, x, y);
+    assert_eq!(selection.text_range(), 1..4);
+    // Within the word, the highlight covers the letters from its logical
+    // start to the letter under the point, apart from the Latin text.
+    let (x, y) = point_in(&layout, 6..8, true);
+    selection.extend_to_point(&layout, x, y);
+    assert_eq!(selection.text_range(), 1..8);
+    let rects: Vec<_> = selection
+        .geometry(&layout)
+        .iter()
+        .map(|rect| (rect.x0 as f32, rect.x1 as f32))
+        .collect();
+    assert_eq!(
+        rects,
+        vec![
+            (bounds(&layout, 1..2).0, bounds(&layout, 3..4).1),
+            (bounds(&layout, 6..8).0, bounds(&layout, 4..6).1),
+        ]
+    );
+}
+
+#[test]
+fn drag_past_line_ends() {
+    let mut cx = TestContext::new();
+    let text = "\u{5D0}\u{5D1}\u{5D2}";
+    let layout = cx.layout(text, 16., None);
+    let mut selection = Selection::from_point(&layout, -10., 1.);
+    // The left end of a right-to-left line is the end of the text.
+    assert_eq!(selection.anchor, text.len());
+    selection.extend_to_point(&layout, layout.width() + 10., 1.);
+    assert_eq!(selection.text_range(), 0..text.len());
+}
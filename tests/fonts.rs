@@ -0,0 +1,364 @@
+mod common;
+
+use common::*;
+use parley::context::RangedBuilder;
+use parley::layout::{Alignment, Baseline, GlyphSet, ShapingIssueKind};
+use parley::style::{
+    FontFamily, FontSettings, FontStack, FontStretch, FontStyle, FontVariation, FontWeight,
+    NumberShaping, OpticalSizing, StyleProperty,
+};
+use parley::swash::scale::ScaleContext;
+use parley::swash::{self, Attributes};
+use parley::{FontContext, LayoutContext};
+
+/// Variable font with a weight axis from 100 to 900 and a default of 400.
+/// Generated by `assets/make_variable_font.py`.
+const VARIABLE_FONT_DATA: &[u8] = include_bytes!("assets/ParleyVariableTest.ttf");
+
+/// Variable font with an optical size axis from 8 to 72 and a default of 8.
+/// Generated by `assets/make_variable_font.py`.
+const OPTICAL_SIZE_FONT_DATA: &[u8] = include_bytes!("assets/ParleyOpticalSizeTest.ttf");
+
+/// Font with hanging baselines of 600 units for the default script and 700
+/// units for Devanagari, at 1000 units per em. Generated by
+/// `assets/make_baseline_font.py`.
+const BASELINE_FONT_DATA: &[u8] = include_bytes!("assets/ParleyBaselineTest.ttf");
+
+#[test]
+fn family_fonts_lists_registered_fonts() {
+    let fcx = font_context();
+    assert!(!fcx.family_fonts(FAMILY).is_empty());
+    assert!(fcx.family_fonts("No Such Family").is_empty());
+}
+
+#[test]
+fn fallback_is_restricted_to_allowed_fonts() {
+    let mut fcx = FontContext::new();
+    fcx.register_fonts(FONT_DATA.to_vec()).unwrap();
+    let allowed = fcx.family_fonts(FAMILY);
+    fcx.set_fallback_restriction(Some(allowed.clone()));
+    let mut lcx = LayoutContext::<Color>::new();
+    // Neither the missing family nor the restricted font supports the
+    // ideograph or the private use character.
+    let text = "abc \u{65E5} \u{E000}";
+    let mut builder = lcx.ranged_builder(&mut fcx, text, 1.);
+    builder.push_default(&StyleProperty::FontStack(FontStack::Single(
+        FontFamily::Named("No Such Family"),
+    )));
+    let mut layout = builder.build();
+    layout.break_all_lines(None, Alignment::Start);
+    assert_eq!(layout.len(), 1);
+    for run in layout.runs() {
+        assert!(allowed.contains(&run.font().id()));
+    }
+    for (id, _) in layout.used_fonts() {
+        assert!(allowed.contains(&id));
+    }
+}
+
+#[test]
+fn used_fonts_reports_glyphs_per_font() {
+    let mut cx = TestContext::new();
+    let text = "abc abd";
+    let mut layout = cx.layout_with(text, 16., None, |builder| {
+        builder.push(&StyleProperty::FontStretch(FontStretch::CONDENSED), 4..7);
+    });
+    layout.break_all_lines(None, Alignment::Start);
+    let used = layout.used_fonts();
+    assert_eq!(used.len(), 2);
+    let glyphs = |range: std::ops::Range<usize>| {
+        let mut set = GlyphSet::new();
+        for run in layout.runs() {
+            for cluster in run.clusters() {
+                if range.contains(&cluster.text_range().start) {
+                    set.extend(cluster.glyphs().map(|glyph| glyph.id));
+                }
+            }
+        }
+        set
+    };
+    let runs: Vec<_> = layout.runs().collect();
+    let regular = runs[0].font().id();
+    let condensed = runs.last().unwrap().font().id();
+    assert_ne!(regular, condensed);
+    for (id, set) in &used {
+        if *id == regular {
+            assert_eq!(*set, glyphs(0..4));
+        } else {
+            assert_eq!(*id, condensed);
+            assert_eq!(*set, glyphs(4..7));
+        }
+    }
+    let font = cx.fcx.font(condensed).unwrap();
+    assert_eq!(font.id(), condensed);
+}
+
+#[test]
+fn static_width_face_is_registered_once() {
+    let cx = TestContext::new();
+    let condensed = cx.fcx.family_fonts("DejaVu Sans Condensed");
+    assert_eq!(condensed.len(), 1);
+    // The face is a member of the typographic family under the same
+    // identifier.
+    let family = cx.fcx.family_fonts(FAMILY);
+    assert_eq!(family.len(), 2);
+    assert!(family.contains(&condensed[0]));
+}
+
+#[test]
+fn stretch_selects_condensed_face() {
+    let mut cx = TestContext::new();
+    let layout = cx.layout_with("abc", 16., None, |builder| {
+        builder.push_default(&StyleProperty::FontStretch(FontStretch::CONDENSED));
+    });
+    let condensed = cx.fcx.family_fonts("DejaVu Sans Condensed");
+    for run in layout.runs() {
+        assert_eq!(run.font().id(), condensed[0]);
+    }
+}
+
+#[test]
+fn font_metrics_from_tables() {
+    let mut cx = TestContext::new();
+    let font = cx
+        .fcx
+        .query_font(FontFamily::Named(FAMILY), Attributes::default())
+        .unwrap();
+    // DejaVu Sans has 2048 units per em, an ascent of 1901, a descent of
+    // 483, an average width of 1038 and a space advance of 651.
+    let scale = 16. / 2048.;
+    let metrics = font.metrics(16.);
+    assert_eq!(metrics.size, 16.);
+    assert_eq!(metrics.ascent, 1901. * scale);
+    assert_eq!(metrics.descent, 483. * scale);
+    assert_eq!(metrics.leading, 0.);
+    assert_eq!(metrics.average_width, 1038. * scale);
+    assert_eq!(metrics.space_advance, 651. * scale);
+    assert!(!metrics.is_monospace);
+    // The space advance matches that of a shaped space.
+    let layout = cx.layout("a a", 16., None);
+    let space = layout.runs().next().unwrap().get(1).unwrap().advance();
+    assert_eq!(space, metrics.space_advance);
+    let run = layout.runs().next().unwrap();
+    assert_eq!(run.metrics().ascent, metrics.ascent);
+}
+
+#[test]
+fn private_use_character_is_diagnosed() {
+    let mut cx = TestContext::new();
+    let text = "a\u{E000}b";
+    // Diagnostics are only collected when enabled.
+    assert!(cx.layout(text, 16., None).shaping_diagnostics().is_empty());
+    cx.lcx.set_diagnostics_enabled(true);
+    let layout = cx.layout(text, 16., None);
+    let issues = layout.shaping_diagnostics();
+    assert_eq!(issues.len(), 1);
+    let issue = &issues[0];
+    assert_eq!(issue.text_range, 1..4);
+    assert_eq!(issue.ch, '\u{E000}');
+    assert_eq!(issue.kind, ShapingIssueKind::FallbackExhausted);
+    // The font stack is searched first, followed by the fallback fonts.
+    assert_eq!(issue.families[0], FAMILY);
+    // Only the private-use character is shaped to .notdef; the text after
+    // it is still shaped.
+    let ids = glyph_ids(&layout);
+    assert_eq!(ids.len(), 3);
+    assert_eq!(ids.iter().filter(|&&id| id == 0).count(), 1);
+    assert_ne!(ids[2], 0);
+}
+
+#[test]
+fn registered_variation_defaults_are_applied() {
+    let coords = |fcx: &mut FontContext, family: &str, variations: Option<&str>| {
+        let mut lcx = LayoutContext::<Color>::new();
+        let mut builder = lcx.ranged_builder(fcx, "AA", 1.);
+        builder.push_default(&StyleProperty::FontStack(FontStack::Single(
+            FontFamily::Named(family),
+        )));
+        if let Some(variations) = variations {
+            builder.push(
+                &StyleProperty::FontVariations(FontSettings::Source(variations)),
+                1..2,
+            );
+        }
+        let layout = builder.build();
+        layout
+            .runs()
+            .map(|run| run.normalized_coords().to_vec())
+            .collect::<Vec<_>>()
+    };
+    // Without defaults, the font is used at its default instance.
+    let mut fcx = FontContext::new();
+    let family = fcx.register_fonts(VARIABLE_FONT_DATA.to_vec()).unwrap();
+    assert_eq!(coords(&mut fcx, &family, None), vec![vec![0]]);
+    // A weight of 700 is 0.6 of the way from the default to the maximum.
+    let mut fcx = FontContext::new();
+    let wght = FontVariation {
+        tag: swash::tag_from_bytes(b"wght"),
+        value: 700.,
+    };
+    let family = fcx
+        .register_fonts_with_variations(VARIABLE_FONT_DATA.to_vec(), &[wght])
+        .unwrap();
+    assert_eq!(family, "Parley Variable Test");
+    assert_eq!(coords(&mut fcx, &family, None), vec![vec![9831]]);
+    // Variations of a range take precedence over the defaults.
+    assert_eq!(
+        coords(&mut fcx, &family, Some("\"wght\" 900")),
+        vec![vec![9831], vec![16384]]
+    );
+}
+
+#[test]
+fn optical_size_follows_font_size() {
+    let mut fcx = FontContext::new();
+    let family = fcx.register_fonts(OPTICAL_SIZE_FONT_DATA.to_vec()).unwrap();
+    let mut coords = |size: f32, scale: f32, f: &dyn Fn(&mut RangedBuilder<Color, &str>)| {
+        let mut lcx = LayoutContext::<Color>::new();
+        let mut builder = lcx.ranged_builder(&mut fcx, "AA", scale);
+        builder.push_default(&StyleProperty::FontStack(FontStack::Single(
+            FontFamily::Named(&family),
+        )));
+        builder.push_default(&StyleProperty::FontSize(size));
+        f(&mut builder);
+        let layout = builder.build();
+        layout
+            .runs()
+            .map(|run| run.normalized_coords().to_vec())
+            .collect::<Vec<_>>()
+    };
+    // The optical size is set to the font size, which for 16 is an eighth
+    // and for 40 is half of the way from the default to the maximum.
+    assert_eq!(coords(16., 1., &|_| {}), vec![vec![2048]]);
+    assert_eq!(coords(40., 1., &|_| {}), vec![vec![8192]]);
+    // The scale of the layout does not change the optical size.
+    assert_eq!(coords(16., 2., &|_| {}), vec![vec![2048]]);
+    // Disabling optical sizing uses the default instance.
+    let none = |builder: &mut RangedBuilder<Color, &str>| {
+        builder.push(&StyleProperty::OpticalSizing(OpticalSizing::None), 1..2);
+    };
+    assert_eq!(coords(16., 1., &none), vec![vec![2048], vec![0]]);
+    // Explicit variations take precedence.
+    let explicit = |builder: &mut RangedBuilder<Color, &str>| {
+        builder.push_default(&StyleProperty::FontVariations(FontSettings::Source(
+            "\"opsz\" 72",
+        )));
+    };
+    assert_eq!(coords(16., 1., &explicit), vec![vec![16384]]);
+}
+
+#[test]
+fn synthesized_oblique_reports_shear() {
+    let mut cx = TestContext::new();
+    let text = "lll";
+    let upright = cx.layout(text, 32., None);
+    let run = upright.runs().next().unwrap();
+    assert_eq!(run.oblique_shear(), 0.);
+    assert!(!run.is_emboldened());
+    // The registered DejaVu Sans has no italic or bold face, so both are
+    // synthesized.
+    let styled = cx.layout_with(text, 32., None, |builder| {
+        builder.push_default(&StyleProperty::FontStyle(FontStyle::Italic));
+        builder.push_default(&StyleProperty::FontWeight(FontWeight::BOLD));
+    });
+    let run = styled.runs().next().unwrap();
+    assert!(run.oblique_shear() > 0.1);
+    assert!(run.is_emboldened());
+    // The slanted tops of the glyphs extend the ink to the right.
+    let mut scx = ScaleContext::new();
+    let (upright, styled) = (upright.ink_bounds(&mut scx), styled.ink_bounds(&mut scx));
+    assert!(styled.width() > upright.width());
+    assert!(styled.x1 > upright.x1);
+}
+
+#[test]
+fn national_number_shaping_substitutes_digits() {
+    let mut cx = TestContext::new();
+    let shaped = |cx: &mut TestContext, text: &str, locale, shaping| {
+        let layout = cx.layout_with(text, 16., None, |builder| {
+            builder.push_default(&StyleProperty::Locale(locale));
+            builder.push_default(&StyleProperty::NumberShaping(shaping));
+        });
+        let ranges: Vec<_> = layout
+            .runs()
+            .flat_map(|run| {
+                run.clusters()
+                    .map(|cluster| cluster.text_range())
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        (glyph_ids(&layout), ranges)
+    };
+    let european = glyph_ids(&cx.layout("123", 16., None));
+    let arabic_indic = glyph_ids(&cx.layout("\u{661}\u{662}\u{663}", 16., None));
+    assert_ne!(european, arabic_indic);
+    // The digits are drawn in the national form while the clusters still
+    // map to the ASCII source text.
+    let (ids, ranges) = shaped(&mut cx, "123", Some("ar"), NumberShaping::National);
+    assert_eq!(ids, arabic_indic);
+    assert_eq!(ranges, vec![0..1, 1..2, 2..3]);
+    // Without a locale that uses national digits, the text is unchanged.
+    let (ids, _) = shaped(&mut cx, "123", Some("en"), NumberShaping::National);
+    assert_eq!(ids, european);
+    let (ids, _) = shaped(&mut cx, "123", Some("ar"), NumberShaping::Auto);
+    assert_eq!(ids, european);
+    // Nominal shaping maps national digits back to European ones.
+    let (ids, ranges) = shaped(
+        &mut cx,
+        "\u{661}\u{662}\u{663}",
+        None,
+        NumberShaping::Nominal,
+    );
+    assert_eq!(ids, european);
+    assert_eq!(ranges, vec![0..2, 2..4, 4..6]);
+    // Contextual shaping follows the script of the preceding text.
+    let (ids, _) = shaped(&mut cx, "\u{645} 12", None, NumberShaping::Contextual);
+    assert!(arabic_indic[..2].iter().all(|id| ids.contains(id)));
+    let (ids, _) = shaped(&mut cx, "x 12", None, NumberShaping::Contextual);
+    assert!(european[..2].iter().all(|id| ids.contains(id)));
+}
+
+#[test]
+fn baseline_offsets_from_base_table() {
+    let mut fcx = FontContext::new();
+    let family = fcx.register_fonts(BASELINE_FONT_DATA.to_vec()).unwrap();
+    let mut lcx = LayoutContext::<Color>::new();
+    let mut builder = lcx.ranged_builder(&mut fcx, "aa\u{915}\u{915}", 1.);
+    builder.push_default(&StyleProperty::FontStack(FontStack::Single(
+        FontFamily::Named(&family),
+    )));
+    builder.push_default(&StyleProperty::FontSize(20.));
+    let layout = builder.build();
+    let runs: Vec<_> = layout.runs().collect();
+    assert_eq!(runs.len(), 2);
+    let (latin, devanagari) = (&runs[0], &runs[1]);
+    // The Latin run uses the default script of the table.
+    assert_eq!(latin.baseline_offset(Baseline::Hanging), 12.);
+    assert_eq!(devanagari.baseline_offset(Baseline::Hanging), 14.);
+    assert!((devanagari.baseline_offset(Baseline::Ideographic) + 2.4).abs() < 0.001);
+    assert_eq!(devanagari.baseline_offset(Baseline::Alphabetic), 0.);
+    // Aligning on the hanging baseline moves the Devanagari run down by the
+    // difference between the offsets.
+    let shift =
+        devanagari.baseline_offset(Baseline::Hanging) - latin.baseline_offset(Baseline::Hanging);
+    assert_eq!(shift, 2.);
+    // Baselines missing from the table are approximated from the metrics.
+    let metrics = devanagari.metrics();
+    assert_eq!(
+        devanagari.baseline_offset(Baseline::Mathematical),
+        metrics.ascent * 0.5
+    );
+    // Without a table, all baselines are approximated.
+    let mut cx = TestContext::new();
+    let layout = cx.layout("abc", 16., None);
+    let run = layout.runs().next().unwrap();
+    assert_eq!(run.baseline_offset(Baseline::Alphabetic), 0.);
+    assert_eq!(
+        run.baseline_offset(Baseline::Hanging),
+        run.metrics().ascent * 0.8
+    );
+    assert_eq!(
+        run.baseline_offset(Baseline::Ideographic),
+        -run.metrics().descent
+    );
+}
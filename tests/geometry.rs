@@ -0,0 +1,536 @@
+mod common;
+
+use common::*;
+use parley::layout::{
+    scroll_to_make_visible, Affinity, Alignment, AlignmentAnchor, CaretShape, Cursor, GlyphHit,
+    PathSide,
+};
+use parley::style::StyleProperty;
+use parley::swash::scale::ScaleContext;
+use peniko::kurbo::{Arc, BezPath, Point, Rect, Shape, Vec2};
+use std::f64::consts::PI;
+
+#[test]
+fn source_range_rects_per_line() {
+    let mut cx = TestContext::new();
+    let text = "see the link text here";
+    // Wrap after "link ".
+    let width = cx.layout("see the link ", 16., None).width() + 1.;
+    let layout = cx.layout(text, 16., Some(width));
+    assert_eq!(
+        line_texts(&layout, text),
+        vec!["see the link ", "text here"]
+    );
+    let rects = layout.rects_for_source_range(8..17);
+    assert_eq!(rects.len(), 2);
+    let lines: Vec<_> = layout.lines().collect();
+    for (rect, line) in rects.iter().zip(&lines) {
+        let metrics = line.metrics();
+        assert_eq!(rect.height() as f32, metrics.size());
+        assert_eq!(
+            rect.y0 as f32,
+            metrics.baseline - metrics.ascent - metrics.leading * 0.5
+        );
+    }
+    assert!(rects[0].y1 <= rects[1].y0);
+    assert!(rects[1].x0 == 0.);
+    assert!(layout.rects_for_source_range(3..3).is_empty());
+}
+
+#[test]
+fn source_range_rects_split_by_bidi() {
+    let mut cx = TestContext::new();
+    // The range covers the end of the Hebrew word and the following Latin
+    // word, which are not visually adjacent.
+    let text = "abc \u{5D0}\u{5D1}\u{5D2} def";
+    let layout = cx.layout(text, 16., None);
+    let rects = layout.rects_for_source_range(6..text.len());
+    assert_eq!(rects.len(), 2);
+    assert!(rects[0].x1 <= rects[1].x0);
+}
+
+#[test]
+fn caret_and_range_heights_agree() {
+    let mut cx = TestContext::new();
+    let text = "abc def";
+    let layout = cx.layout(text, 16., None);
+    let caret = Cursor::from_position(&layout, 2, true).caret_rect(&layout);
+    let rect = layout.rects_for_source_range(0..3)[0];
+    assert_eq!((caret.y0, caret.y1), (rect.y0, rect.y1));
+    let prefix = layout.visible_prefix_bounds(3);
+    assert_eq!((prefix.y0, prefix.y1), (rect.y0, rect.y1));
+}
+
+#[test]
+fn caret_at_wrap_boundary() {
+    let mut cx = TestContext::new();
+    let text = "aaa bbb";
+    let width = cx.layout("aaa ", 16., None).width() + 1.;
+    let layout = cx.layout(text, 16., Some(width));
+    assert_eq!(line_texts(&layout, text), vec!["aaa ", "bbb"]);
+    let lines: Vec<_> = layout.lines().collect();
+    let line_rect = |index: usize| layout.rects_for_source_range(lines[index].text_range())[0];
+    // Trailing the space, the caret is at the end of the first line.
+    let caret = Cursor::from_position(&layout, 3, false).caret_rect(&layout);
+    let first = line_rect(0);
+    assert_eq!(
+        (caret.x0, caret.y0, caret.y1),
+        (first.x1, first.y0, first.y1)
+    );
+    // Leading the next word, it is at the start of the second line.
+    let caret = Cursor::from_position(&layout, 4, true).caret_rect(&layout);
+    let second = line_rect(1);
+    assert_eq!((caret.x0, caret.y0, caret.y1), (0., second.y0, second.y1));
+    assert_eq!(caret.width(), 0.);
+}
+
+#[test]
+fn caret_on_empty_line() {
+    let mut cx = TestContext::new();
+    let text = "a\n\nb";
+    let layout = cx.layout(text, 16., None);
+    assert_eq!(layout.len(), 3);
+    let line = layout.get(1).unwrap();
+    let metrics = line.metrics();
+    let top = metrics.baseline - metrics.ascent - metrics.leading * 0.5;
+    for leading in [true, false] {
+        let caret = Cursor::from_position(&layout, 2, leading).caret_rect(&layout);
+        assert_eq!(caret.x0, 0.);
+        assert_eq!(caret.y0 as f32, top);
+        assert_eq!(caret.height() as f32, metrics.size());
+    }
+}
+
+#[test]
+fn caret_follows_rtl_hit_test() {
+    let mut cx = TestContext::new();
+    let text = "abc \u{5D0}\u{5D1}\u{5D2} def";
+    let layout = cx.layout(text, 16., None);
+    let rect = layout.rects_for_source_range(6..8)[0];
+    let y = layout.height() * 0.5;
+    // The caret is drawn on the edge of the cluster nearest the point.
+    for (x, edge) in [(rect.x0 + 1., rect.x0), (rect.x1 - 1., rect.x1)] {
+        let cursor = Cursor::from_point(&layout, x as f32, y);
+        assert!(cursor.is_rtl);
+        let caret = cursor.caret_rect(&layout);
+        assert_eq!(caret.x0 as f32, cursor.offset);
+        assert_eq!(caret.x0, edge);
+    }
+}
+
+#[test]
+fn rtl_caret_is_placed_by_direction() {
+    let mut cx = TestContext::new();
+    let text = "abc \u{5D0}\u{5D1}\u{5D2} def";
+    let layout = cx.layout(text, 16., None);
+    let alef = layout.rects_for_source_range(4..6)[0];
+    // The leading edge of a right-to-left cluster is its right edge, and the
+    // trailing edge is its left edge.
+    let leading = Cursor::from_position(&layout, 4, true);
+    assert!(leading.is_rtl);
+    assert_eq!(leading.caret_rect(&layout).x0, alef.x1);
+    assert_eq!(leading.offset as f64, alef.x1);
+    let trailing = Cursor::from_position(&layout, 4, false);
+    assert_eq!(trailing.caret_rect(&layout).x0, alef.x0);
+    assert_eq!(trailing.offset as f64, alef.x0);
+}
+
+#[test]
+fn baselines_snap_to_grid() {
+    let mut cx = TestContext::new();
+    let text = "small BIG small\nsmall";
+    let build = |cx: &mut TestContext, text, max_advance| {
+        cx.layout_with(text, 16., max_advance, |builder| {
+            builder.push(&StyleProperty::FontSize(32.), 6..9);
+        })
+    };
+    let width = build(&mut cx, "small BIG ", None).width() + 1.;
+    let mut layout = build(&mut cx, text, Some(width));
+    assert_eq!(layout.len(), 3);
+    let natural: Vec<_> = layout.lines().map(|line| line.metrics().baseline).collect();
+    let (pitch, offset) = (24., 4.);
+    layout.snap_to_baseline_grid(pitch, offset);
+    let mut prev: Option<(f32, f32)> = None;
+    for (line, natural) in layout.lines().zip(natural) {
+        let baseline = line.metrics().baseline;
+        let steps = (baseline - offset) / pitch;
+        assert!((steps - steps.round()).abs() < 1e-4, "{}", baseline);
+        // Baselines only move down, and never by less than the line above.
+        let shift = baseline - natural;
+        assert!(shift >= 0.);
+        if let Some((prev_baseline, prev_shift)) = prev {
+            assert!(baseline > prev_baseline);
+            assert!(shift >= prev_shift);
+        }
+        prev = Some((baseline, shift));
+    }
+    let last = layout.lines().last().unwrap();
+    let last = last.metrics();
+    assert!(layout.height() >= last.baseline + last.descent);
+}
+
+#[test]
+fn reveal_prefix_of_clusters() {
+    let mut cx = TestContext::new();
+    let text = "hello\nworld";
+    let layout = cx.layout(text, 16., None);
+    let line_height = layout.lines().next().unwrap().metrics().size();
+    let mut width = |text| cx.layout(text, 16., None).width() as f64;
+    assert_eq!(layout.cluster_count_to_offset(3), 3);
+    let bounds = layout.visible_prefix_bounds(3);
+    assert_eq!(bounds.x0, 0.);
+    assert!((bounds.x1 - width("hel")).abs() < 0.01);
+    assert!((bounds.height() as f32 - line_height).abs() < 0.01);
+    // Revealing past the newline spans both lines, and the caret follows
+    // the revealed text onto the second line.
+    assert_eq!(layout.cluster_count_to_offset(8), 8);
+    let bounds = layout.visible_prefix_bounds(8);
+    assert!((bounds.height() as f32 - layout.height()).abs() < 0.01);
+    assert!(bounds.x1 >= width("hello"));
+    let caret = Cursor::from_position(&layout, 8, true).caret_rect(&layout);
+    assert!(caret.y0 as f32 >= line_height - 0.01);
+    assert!((caret.x0 - width("wo")).abs() < 0.01);
+    // Revealing everything reaches the end of the text.
+    assert_eq!(layout.cluster_count_to_offset(100), text.len());
+}
+
+#[test]
+fn reveal_prefix_across_wrap() {
+    let mut cx = TestContext::new();
+    let text = "hello world";
+    let max_advance = cx.layout("hello ", 16., None).width() + 1.;
+    let layout = cx.layout(text, 16., Some(max_advance));
+    assert_eq!(line_texts(&layout, text), vec!["hello ", "world"]);
+    let second = layout.lines().nth(1).unwrap();
+    let top = (second.metrics().baseline - second.metrics().ascent) as f64;
+    let bounds = layout.visible_prefix_bounds(8);
+    assert!(bounds.y1 > top);
+    let caret =
+        Cursor::from_position(&layout, layout.cluster_count_to_offset(8), true).caret_rect(&layout);
+    assert!(caret.y0 >= top - 1.);
+    let wo = cx.layout("wo", 16., None).width() as f64;
+    assert!((caret.x0 - wo).abs() < 0.01);
+}
+
+#[test]
+fn runs_with_origin_follow_advances() {
+    let mut cx = TestContext::new();
+    let text = "ab cd\nef";
+    // The brush of the second word splits the first line into two runs.
+    let layout = cx.layout_with(text, 16., None, |builder| {
+        builder.push(&StyleProperty::Brush([255, 0, 0, 255]), 3..5);
+    });
+    let lines: Vec<_> = layout.lines().collect();
+    let runs: Vec<_> = lines[0].runs_with_origin().collect();
+    assert_eq!(runs.len(), 2);
+    let (first, first_origin) = &runs[0];
+    let (second, second_origin) = &runs[1];
+    assert_eq!(first_origin.x, 0.);
+    assert_eq!(second_origin.x, first.advance() as f64);
+    // Both runs are drawn on the baseline of the line.
+    let baseline = lines[0].metrics().baseline as f64;
+    assert_eq!(first_origin.y, baseline);
+    assert_eq!(second_origin.y, baseline);
+    let glyph = second.positioned_glyphs().next().unwrap();
+    assert_eq!(glyph.x as f64, second_origin.x);
+    // Each line has its own baseline.
+    let (_, origin) = lines[1].runs_with_origin().next().unwrap();
+    assert_eq!(origin.x, 0.);
+    assert_eq!(origin.y, lines[1].metrics().baseline as f64);
+    assert!(origin.y > baseline);
+}
+
+#[test]
+fn caret_for_offset_uses_affinity() {
+    let mut cx = TestContext::new();
+    let text = "aaa b\u{E9}b\nccc";
+    let width = cx.layout("aaa ", 16., None).width() + 1.;
+    let layout = cx.layout(text, 16., Some(width));
+    assert_eq!(line_texts(&layout, text), vec!["aaa ", "b\u{E9}b\n", "ccc"]);
+    let lines: Vec<_> = layout.lines().collect();
+    let line_rect = |index: usize| layout.rects_for_source_range(lines[index].text_range())[0];
+    let (first, second, third) = (line_rect(0), line_rect(1), line_rect(2));
+    // At the soft wrap, the affinity selects the end of the first line or
+    // the start of the second.
+    let caret = layout.caret_for_offset(4, Affinity::Upstream);
+    assert_eq!(
+        (caret.x0, caret.y0, caret.y1),
+        (first.x1, first.y0, first.y1)
+    );
+    let caret = layout.caret_for_offset(4, Affinity::Downstream);
+    assert_eq!((caret.x0, caret.y0, caret.y1), (0., second.y0, second.y1));
+    assert_eq!(caret.width(), 0.);
+    // The same rectangles are produced by the equivalent cursors.
+    assert_eq!(
+        caret,
+        Cursor::from_position(&layout, 4, true).caret_rect(&layout)
+    );
+    let upstream = Cursor::from_offset(&layout, 4, Affinity::Upstream);
+    assert_eq!((upstream.text_start, upstream.insert_point), (3, 4));
+    assert_eq!(
+        upstream.caret_rect(&layout),
+        layout.caret_for_offset(4, Affinity::Upstream)
+    );
+    // An offset inside a character snaps to its start.
+    let start = Cursor::from_position(&layout, 5, true).caret_rect(&layout);
+    for affinity in [Affinity::Upstream, Affinity::Downstream] {
+        assert_eq!(layout.caret_for_offset(6, affinity), start);
+    }
+    // After the newline, the caret is at the start of the next line.
+    let newline = text.find('\n').unwrap() + 1;
+    for affinity in [Affinity::Upstream, Affinity::Downstream] {
+        let caret = layout.caret_for_offset(newline, affinity);
+        assert_eq!((caret.x0, caret.y0, caret.y1), (0., third.y0, third.y1));
+    }
+}
+
+#[test]
+fn caret_for_offset_in_rtl_text() {
+    let mut cx = TestContext::new();
+    let text = "abc \u{5D0}\u{5D1}\u{5D2} def";
+    let layout = cx.layout(text, 16., None);
+    let rect = |range| layout.rects_for_source_range(range)[0];
+    let (space, alef, bet) = (rect(3..4), rect(4..6), rect(6..8));
+    // The caret before a right-to-left cluster is on its right edge, so at
+    // the start of the Hebrew word the affinity selects either end of it.
+    let caret = layout.caret_for_offset(4, Affinity::Downstream);
+    assert_eq!(caret.x0, alef.x1);
+    let caret = layout.caret_for_offset(4, Affinity::Upstream);
+    assert_eq!(caret.x0, space.x1);
+    let caret = layout.caret_for_offset(6, Affinity::Downstream);
+    assert_eq!(caret.x0, bet.x1);
+}
+
+#[test]
+fn caret_shape_sets_width() {
+    let mut cx = TestContext::new();
+    let layout = cx.layout("abc", 16., None);
+    let line = layout.caret_for_offset(1, Affinity::Downstream);
+    let x = line.x0;
+    let shaped =
+        |width, shape| layout.caret_for_offset_with_shape(1, Affinity::Downstream, width, shape);
+    // The rectangle has the configured width on the side of the insertion
+    // edge given by the shape, and the vertical extent of the line caret.
+    let caret = shaped(2., CaretShape::Line);
+    assert_eq!(caret, line);
+    let caret = shaped(2., CaretShape::Centered);
+    assert_eq!((caret.x0, caret.x1), (x - 1., x + 1.));
+    assert_eq!((caret.y0, caret.y1), (line.y0, line.y1));
+    let caret = shaped(3., CaretShape::Left);
+    assert_eq!((caret.x0, caret.x1), (x - 3., x));
+    assert_eq!(caret.width(), 3.);
+    // A negative width is treated as zero.
+    assert_eq!(shaped(-1., CaretShape::Centered).width(), 0.);
+    // The cursor produces the same rectangle.
+    let cursor = Cursor::from_position(&layout, 1, true);
+    assert_eq!(
+        cursor.caret_rect_with_shape(&layout, 2., CaretShape::Centered),
+        shaped(2., CaretShape::Centered)
+    );
+    assert_eq!(CaretShape::default(), CaretShape::Line);
+}
+
+#[test]
+fn scroll_cursor_below_viewport_into_view() {
+    let mut cx = TestContext::new();
+    let text = "one\ntwo\nthree\nfour\nfive";
+    let layout = cx.layout(text, 16., None);
+    let caret = layout.caret_for_offset(text.len(), Affinity::Downstream);
+    let height = caret.height();
+    // The viewport shows the first two lines, so the caret on the last line
+    // is revealed by scrolling down until its bottom and the margin fit.
+    let viewport = Rect::new(0., 0., 200., height * 2.);
+    let delta = scroll_to_make_visible(caret, viewport, 4.);
+    assert_eq!(delta, Vec2::new(0., caret.y1 + 4. - viewport.y1));
+    assert!(delta.y > 0.);
+    // Once scrolled, the caret is visible.
+    let scrolled = viewport + delta;
+    assert_eq!(scroll_to_make_visible(caret, scrolled, 4.), Vec2::ZERO);
+    // A caret above the viewport scrolls up to its top.
+    let first = layout.caret_for_offset(0, Affinity::Downstream);
+    assert_eq!(
+        scroll_to_make_visible(first, scrolled, 0.),
+        Vec2::new(0., first.y0 - scrolled.y0)
+    );
+    // A target taller than the viewport is aligned to its top.
+    let all = Rect::new(0., 0., 10., layout.height() as f64);
+    let delta = scroll_to_make_visible(all, scrolled, 0.);
+    assert_eq!(delta, Vec2::new(0., -scrolled.y0));
+}
+
+#[test]
+fn align_to_centers_lines_on_the_origin() {
+    let mut cx = TestContext::new();
+    let text = "short\nmuch longer line";
+    let mut layout = cx.layout(text, 16., None);
+    layout.break_all_lines(None, Alignment::Middle);
+    // Without a maximum advance, the lines are not aligned when broken.
+    for line in layout.lines() {
+        assert_eq!(line.runs_with_origin().next().unwrap().1.x, 0.);
+    }
+    layout.align_to(0., AlignmentAnchor::Center);
+    let extents: Vec<_> = layout
+        .lines()
+        .map(|line| {
+            let runs: Vec<_> = line.runs_with_origin().collect();
+            let (last, origin) = runs.last().unwrap();
+            (runs[0].1.x, origin.x + last.advance() as f64)
+        })
+        .collect();
+    // Each line extends equally to the left and right of the origin.
+    for &(start, end) in &extents {
+        assert!(start < 0.);
+        assert!((start + end).abs() < 1e-3, "{} {}", start, end);
+    }
+    assert!(extents[1].0 < extents[0].0);
+    // Ending the box at the origin centers the lines on the middle of the
+    // box, at negative positions.
+    layout.align_to(200., AlignmentAnchor::End);
+    for line in layout.lines() {
+        let runs: Vec<_> = line.runs_with_origin().collect();
+        let (last, origin) = runs.last().unwrap();
+        let (start, end) = (runs[0].1.x, origin.x + last.advance() as f64);
+        assert!(start > -200. && end < 0.);
+        assert!((start + end + 200.).abs() < 1e-3, "{} {}", start, end);
+    }
+}
+
+#[test]
+fn hit_test_glyph_in_cluster() {
+    let mut cx = TestContext::new();
+    let id = |cx: &mut TestContext, text: &str| glyph_ids(&cx.layout(text, 32., None))[0];
+    let (base, mark) = (id(&mut cx, "q"), id(&mut cx, "\u{323}"));
+    // The dot below is drawn under the middle of the "q", within the same
+    // cluster.
+    let text = "aq\u{323}b";
+    let layout = cx.layout(text, 32., None);
+    let rect = layout.rects_for_source_range(1..4)[0];
+    let y = layout.height() as f32 * 0.5;
+    let mut scx = ScaleContext::new();
+    let mut hit = |x: f64| layout.hit_test_glyph(&mut scx, x as f32, y).unwrap();
+    let left = hit(rect.x0 + 2.);
+    assert_eq!((left.glyph.id, left.glyph_index), (base, 0));
+    assert_eq!(left.x as f64, rect.x0);
+    let middle = hit((rect.x0 + rect.x1) * 0.5);
+    assert_eq!((middle.glyph.id, middle.glyph_index), (mark, 1));
+    let right = hit(rect.x1 - 2.);
+    assert_eq!((right.glyph.id, right.glyph_index), (base, 0));
+    // All three are glyphs of the same cluster.
+    let cluster = |hit: &GlyphHit| hit.path.cluster(&layout).unwrap().text_range();
+    for hit in [&left, &middle, &right] {
+        assert_eq!(cluster(hit), 1..4);
+    }
+    // Outside the cluster, the glyph of the neighbouring cluster is hit.
+    assert_eq!(hit(rect.x0 - 2.).glyph.id, id(&mut cx, "a"));
+    assert!(layout.hit_test_glyph(&mut scx, -10., y).is_none());
+}
+
+#[test]
+fn place_on_straight_path_matches_layout() {
+    let mut cx = TestContext::new();
+    let layout = cx.layout("abc", 16., None);
+    let line = layout.lines().next().unwrap();
+    let baseline = line.metrics().baseline as f64;
+    let glyphs: Vec<_> = line
+        .glyph_runs()
+        .flat_map(|run| run.positioned_glyphs().collect::<Vec<_>>())
+        .collect();
+    let mut path = BezPath::new();
+    path.move_to((10., 50.));
+    path.line_to((500., 50.));
+    let placed = layout.place_on_path(&path, 0., PathSide::Left);
+    assert_eq!(placed.len(), 3);
+    // Along a horizontal line, the glyphs are only translated so that the
+    // baseline follows the path.
+    for (placed, glyph) in placed.iter().zip(&glyphs) {
+        assert_eq!(placed.glyph.id, glyph.id);
+        let origin = placed.transform * Point::ORIGIN;
+        let expected = Point::new(10. + glyph.x as f64, 50. + glyph.y as f64 - baseline);
+        assert!(
+            (origin - expected).hypot() < 1e-3,
+            "{:?} {:?}",
+            origin,
+            expected
+        );
+        assert!(placed.transform.as_coeffs()[1].abs() < 1e-6);
+    }
+    // The start offset shifts the glyphs along the path, and glyphs beyond
+    // the end are dropped.
+    let last_center = glyphs[2].x as f64 + glyphs[2].advance as f64 * 0.5;
+    let placed = layout.place_on_path(&path, 490. - last_center + 1., PathSide::Left);
+    assert_eq!(placed.len(), 2);
+    assert!(layout
+        .place_on_path(&path, -100., PathSide::Left)
+        .is_empty());
+    // On the right side, the text runs from the end of the path, upside
+    // down.
+    let placed = layout.place_on_path(&path, 0., PathSide::Right);
+    let origin = placed[0].transform * Point::ORIGIN;
+    let expected = Point::new(
+        500. - glyphs[0].x as f64,
+        50. - (glyphs[0].y as f64 - baseline),
+    );
+    assert!((origin - expected).hypot() < 1e-3);
+    assert!((placed[0].transform.as_coeffs()[0] + 1.).abs() < 1e-6);
+}
+
+#[test]
+fn place_on_arc_follows_tangent() {
+    let mut cx = TestContext::new();
+    let layout = cx.layout("abc", 16., None);
+    let center = Point::new(100., 100.);
+    let radius = 50.;
+    // The upper half of a circle, from left to right.
+    let arc = Arc {
+        center,
+        radii: Vec2::new(radius, radius),
+        start_angle: PI,
+        sweep_angle: PI,
+        x_rotation: 0.,
+    };
+    let path = arc.into_path(1e-4);
+    let placed = layout.place_on_path(&path, 0., PathSide::Left);
+    assert_eq!(placed.len(), 3);
+    let mut previous_angle = -PI;
+    for placed in &placed {
+        // The center of the advance of each glyph lies on the arc.
+        let glyph = placed.glyph;
+        let center_point = placed.transform
+            * Point::new(glyph.advance as f64 * 0.5 - glyph.x as f64, -glyph.y as f64);
+        assert!(((center_point - center).hypot() - radius).abs() < 1e-2);
+        // The glyphs are rotated clockwise along the arc.
+        let coeffs = placed.transform.as_coeffs();
+        let angle = coeffs[1].atan2(coeffs[0]);
+        assert!(angle > previous_angle);
+        assert!(angle < 0.);
+        previous_angle = angle;
+    }
+}
+
+#[test]
+fn first_and_last_baselines() {
+    let mut cx = TestContext::new();
+    let text = "ab cd\nef";
+    let layout = cx.layout_with(text, 16., None, |builder| {
+        builder.push(&StyleProperty::FontSize(32.), 3..5);
+    });
+    let lines: Vec<_> = layout.lines().collect();
+    assert_eq!(lines.len(), 2);
+    // The larger text on the first line pushes its baseline down to the
+    // ascent of the larger font.
+    let runs: Vec<_> = layout.runs().collect();
+    let (small, large) = (runs[0].metrics().ascent, runs[1].metrics().ascent);
+    assert!(large > small);
+    assert_eq!(layout.first_baseline(), lines[0].metrics().baseline);
+    assert!(layout.first_baseline() >= large);
+    assert_eq!(layout.last_baseline(), lines[1].metrics().baseline);
+    assert!(layout.last_baseline() > layout.first_baseline());
+    // Empty text has a single line with the metrics of the default style.
+    let empty = cx.layout("", 16., None);
+    let single = cx.layout("a", 16., None);
+    assert_eq!(empty.first_baseline(), single.first_baseline());
+    assert_eq!(empty.last_baseline(), empty.first_baseline());
+    // Before line breaking, there are no baselines.
+    let unbroken = cx.builder(text, 16.).build();
+    assert_eq!(unbroken.first_baseline(), 0.);
+}
@@ -0,0 +1,272 @@
+mod common;
+
+use common::*;
+use parley::context::{ShapeRunParams, ShapedRun};
+use parley::layout::Alignment;
+use parley::style::{
+    Direction, FontFamily, FontFeature, FontSettings, FontStack, FontVariantLigatures,
+    NumericFeature, SpacingValue, StyleProperty,
+};
+use parley::swash::text::Script;
+use parley::swash::{self, Attributes};
+use parley::{FontContext, Layout, LayoutContext};
+
+/// Font whose `frac` feature forms "1/2" into a single glyph. Generated by
+/// `assets/make_fraction_font.py`.
+const FRACTION_FONT_DATA: &[u8] = include_bytes!("assets/ParleyFractionTest.ttf");
+
+/// Returns the glyph identifiers and advances of the clusters starting in
+/// the range.
+fn clusters(layout: &Layout<Color>, range: std::ops::Range<usize>) -> Vec<(Vec<u16>, f32)> {
+    let mut clusters = vec![];
+    for run in layout.runs() {
+        for cluster in run.clusters() {
+            if range.contains(&cluster.text_range().start) {
+                let glyphs = cluster.glyphs().map(|glyph| glyph.id).collect();
+                clusters.push((glyphs, cluster.advance()));
+            }
+        }
+    }
+    clusters
+}
+
+fn unligated(cx: &mut TestContext, text: &str, spacing: f32) -> Layout<Color> {
+    cx.layout_with(text, 16., None, |builder| {
+        builder.push_default(&StyleProperty::LetterSpacing(SpacingValue::Absolute(
+            spacing,
+        )));
+        builder.push_default(&StyleProperty::FontVariantLigatures(FontVariantLigatures {
+            common: false,
+            ..Default::default()
+        }));
+    })
+}
+
+/// Returns the glyph identifiers of the text with ligatures kept in the
+/// first word only.
+fn glyph_ids_of(cx: &mut TestContext, text: &str) -> Vec<u16> {
+    let mut ids = glyph_ids(&cx.layout("fit ", 16., None));
+    ids.extend(glyph_ids(&unligated(cx, &text[4..], 0.)));
+    ids
+}
+
+#[test]
+fn split_ligature_for_highlight() {
+    let mut cx = TestContext::new();
+    let text = "fit office";
+    let mut layout = cx.layout(text, 16., None);
+    // "ffi" is a single ligature glyph.
+    assert_eq!(clusters(&layout, 5..8)[1].0, vec![]);
+    let fit = clusters(&layout, 0..3);
+    // Highlight the first "f" of "office".
+    layout.split_ligatures_at(&[5, 6]);
+    layout.break_all_lines(None, Alignment::Start);
+    let plain = unligated(&mut cx, text, 0.);
+    assert_eq!(clusters(&layout, 4..10), clusters(&plain, 4..10));
+    for (glyphs, _) in clusters(&layout, 4..10) {
+        assert_eq!(glyphs.len(), 1);
+    }
+    // Other words keep their ligatures.
+    assert_eq!(clusters(&layout, 0..3), fit);
+    assert!(layout.width() > cx.layout(text, 16., None).width());
+    assert!((layout.runs().map(|run| run.advance()).sum::<f32>() - layout.width()).abs() < 0.01);
+    // The highlighted character has its own glyph to color.
+    let rect = layout.rects_for_source_range(5..6)[0];
+    assert!((rect.width() as f32 - clusters(&layout, 5..6)[0].1).abs() < 0.01);
+    let glyph_ids = glyph_ids(&layout);
+    assert_eq!(glyph_ids, glyph_ids_of(&mut cx, text));
+}
+
+#[test]
+fn split_keeps_spacing() {
+    let mut cx = TestContext::new();
+    let text = "office";
+    let mut layout = cx.layout_with(text, 16., None, |builder| {
+        builder.push_default(&StyleProperty::LetterSpacing(SpacingValue::Absolute(2.)));
+    });
+    layout.split_ligatures_at(&[2]);
+    layout.break_all_lines(None, Alignment::Start);
+    let plain = unligated(&mut cx, text, 2.);
+    assert_eq!(clusters(&layout, 0..6), clusters(&plain, 0..6));
+    assert!((layout.width() - plain.width()).abs() < 0.01);
+}
+
+#[test]
+fn offsets_outside_ligatures_are_ignored() {
+    let mut cx = TestContext::new();
+    let text = "office";
+    let mut layout = cx.layout(text, 16., None);
+    let before = clusters(&layout, 0..6);
+    // Offsets at the bounds of the ligature do not split it.
+    layout.split_ligatures_at(&[0, 1, 4, 6]);
+    layout.break_all_lines(None, Alignment::Start);
+    assert_eq!(clusters(&layout, 0..6), before);
+}
+
+#[test]
+fn shape_run_forms_ligatures() {
+    let mut fcx = font_context();
+    let font = fcx
+        .query_font(FontFamily::Named(FAMILY), Attributes::default())
+        .unwrap();
+    let mut lcx = LayoutContext::<Color>::new();
+    let mut params = ShapeRunParams {
+        text: "office",
+        font: &font,
+        size: 16.,
+        direction: Direction::LeftToRight,
+        script: Script::Latin,
+        locale: None,
+        features: &[],
+        variations: &[],
+    };
+    let clusters = |run: &ShapedRun| -> Vec<(std::ops::Range<usize>, usize, usize)> {
+        run.clusters
+            .iter()
+            .map(|cluster| {
+                let glyphs = cluster.glyph_range.len();
+                (cluster.text_range.clone(), cluster.component_count, glyphs)
+            })
+            .collect()
+    };
+    // "ffi" forms a single glyph spanning three characters.
+    let run = lcx.shape_run(&params);
+    assert_eq!(
+        clusters(&run),
+        vec![(0..1, 1, 1), (1..4, 3, 1), (4..5, 1, 1), (5..6, 1, 1)]
+    );
+    let total = run
+        .clusters
+        .iter()
+        .map(|cluster| cluster.advance)
+        .sum::<f32>();
+    assert!((run.advance - total).abs() < 0.01);
+    // The glyphs match those of a full layout.
+    let mut cx = TestContext::new();
+    let layout = cx.layout("office", 16., None);
+    let ids: Vec<_> = run.glyphs.iter().map(|glyph| glyph.id).collect();
+    assert_eq!(ids, glyph_ids(&layout));
+    // "fi" forms a ligature on its own.
+    params.text = "fit";
+    let run = lcx.shape_run(&params);
+    assert_eq!(clusters(&run), vec![(0..2, 2, 1), (2..3, 1, 1)]);
+    // Disabling standard ligatures shapes each character separately.
+    let liga = [FontFeature {
+        tag: swash::tag_from_bytes(b"liga"),
+        value: 0,
+    }];
+    params.text = "office";
+    params.features = &liga;
+    let run = lcx.shape_run(&params);
+    assert_eq!(run.glyphs.len(), 6);
+    assert!(run
+        .clusters
+        .iter()
+        .all(|cluster| cluster.component_count == 1));
+}
+
+#[test]
+fn applied_features_omit_unsupported() {
+    let mut cx = TestContext::new();
+    let text = "office";
+    let layout = cx.layout_with(text, 16., None, |builder| {
+        builder.push(
+            &StyleProperty::FontFeatures(FontSettings::Source("\"liga\" 0, \"smcp\" 1")),
+            0..3,
+        );
+    });
+    let runs: Vec<_> = layout.runs().collect();
+    assert_eq!(runs.len(), 2);
+    // The font has common ligatures but no small capitals, so only the
+    // disabling of ligatures is reported.
+    let liga = FontFeature {
+        tag: swash::tag_from_bytes(b"liga"),
+        value: 0,
+    };
+    assert_eq!(runs[0].applied_features(), [liga]);
+    assert!(runs[1].applied_features().is_empty());
+    // The "ff" pair is not ligated in the first run.
+    assert_eq!(
+        runs[0]
+            .clusters()
+            .filter(|cluster| cluster.glyphs().count() == 1)
+            .count(),
+        3
+    );
+}
+
+#[test]
+fn fraction_feature_forms_fraction() {
+    let mut fcx = FontContext::new();
+    let family = fcx.register_fonts(FRACTION_FONT_DATA.to_vec()).unwrap();
+    let mut lcx = LayoutContext::<Color>::new();
+    let features = [NumericFeature::Fractions.enabled()];
+    let text = "1/2 1/2";
+    let mut builder = lcx.ranged_builder(&mut fcx, text, 1.);
+    builder.push_default(&StyleProperty::FontStack(FontStack::Single(
+        FontFamily::Named(&family),
+    )));
+    builder.push(
+        &StyleProperty::FontFeatures(FontSettings::List(&features)),
+        0..3,
+    );
+    let layout = builder.build();
+    // The first "1/2" is shaped as the single fraction glyph, which spans
+    // the clusters of its characters, while the second is unchanged.
+    let (one, slash, two, half) = (1, 2, 3, 4);
+    let first = clusters(&layout, 0..3);
+    assert_eq!(first[0].0, vec![half]);
+    assert!(first[1..].iter().all(|(glyphs, _)| glyphs.is_empty()));
+    let second: Vec<_> = clusters(&layout, 4..7)
+        .into_iter()
+        .map(|(glyphs, _)| glyphs)
+        .collect();
+    assert_eq!(second, vec![vec![one], vec![slash], vec![two]]);
+    let run = layout.runs().next().unwrap();
+    assert_eq!(run.applied_features(), features);
+}
+
+#[test]
+fn disabling_ligatures_keeps_required_ligatures() {
+    let mut cx = TestContext::new();
+    let with = |cx: &mut TestContext, text: &str, ligatures| {
+        cx.layout_with(text, 16., None, |builder| {
+            builder.push_default(&StyleProperty::FontVariantLigatures(ligatures));
+        })
+    };
+    // Lam followed by alef forms a required ligature, which is kept when
+    // all optional ligatures and alternates are disabled.
+    let lam_alef = "\u{644}\u{627}";
+    let none = FontVariantLigatures::none();
+    let count = |layout: &Layout<Color>| glyph_ids(layout).len();
+    assert_eq!(count(&cx.layout(lam_alef, 16., None)), 1);
+    assert_eq!(count(&with(&mut cx, lam_alef, none)), 1);
+    // It is only broken by disabling the required ligature feature itself.
+    let rlig = cx.layout_with(lam_alef, 16., None, |builder| {
+        builder.push_default(&StyleProperty::FontFeatures(FontSettings::Source(
+            "\"rlig\" 0",
+        )));
+    });
+    assert_eq!(count(&rlig), 2);
+    // Common ligatures are disabled.
+    assert_eq!(count(&cx.layout("fi", 16., None)), 1);
+    assert_eq!(count(&with(&mut cx, "fi", none)), 2);
+    // Only the settings that differ from the defaults of shaping are
+    // applied.
+    let tags = |ligatures: FontVariantLigatures| {
+        ligatures
+            .features()
+            .map(|feature| (feature.tag.to_be_bytes(), feature.value))
+            .collect::<Vec<_>>()
+    };
+    assert!(tags(FontVariantLigatures::default()).is_empty());
+    assert_eq!(
+        tags(none),
+        vec![(*b"liga", 0), (*b"clig", 0), (*b"calt", 0)]
+    );
+    let discretionary = FontVariantLigatures {
+        discretionary: true,
+        ..Default::default()
+    };
+    assert_eq!(tags(discretionary), vec![(*b"dlig", 1)]);
+}
@@ -0,0 +1,751 @@
+mod common;
+
+use common::*;
+use parley::layout::{Alignment, Cursor, LastLineAlignment, LineWidthsOverflow};
+use parley::style::{
+    FontStack, HangingEnd, HangingPunctuation, LineBreakStrictness, OverflowWrap, StyleProperty,
+    Wrap,
+};
+use parley::swash::scale::ScaleContext;
+use parley::Layout;
+
+/// Returns the source offsets of the clusters preceded by a soft line break
+/// opportunity.
+fn soft_breaks(
+    cx: &mut TestContext,
+    text: &str,
+    strictness: Option<LineBreakStrictness>,
+) -> Vec<usize> {
+    let layout = cx.layout_with(text, 16., None, |builder| {
+        if let Some(strictness) = strictness {
+            builder.push_default(&StyleProperty::LineBreak(strictness));
+        }
+    });
+    let mut offsets = vec![];
+    for line in layout.lines() {
+        for run in line.runs() {
+            for cluster in run.clusters() {
+                if cluster.is_soft_line_break() {
+                    offsets.push(cluster.text_range().start);
+                }
+            }
+        }
+    }
+    offsets
+}
+
+#[test]
+fn auto_strictness_is_untailored() {
+    let mut cx = TestContext::new();
+    // Small kana and the prolonged sound mark.
+    let text = "\u{65E5}\u{3083}\u{30FC}";
+    let strict = soft_breaks(&mut cx, text, Some(LineBreakStrictness::Strict));
+    assert_eq!(strict, vec![]);
+    assert_eq!(soft_breaks(&mut cx, text, None), strict);
+    assert_eq!(
+        soft_breaks(&mut cx, text, Some(LineBreakStrictness::Auto)),
+        strict
+    );
+    assert_eq!(
+        soft_breaks(&mut cx, text, Some(LineBreakStrictness::Normal)),
+        vec![3, 6]
+    );
+}
+
+#[test]
+fn loose_strictness_breaks_before_iteration_marks() {
+    let mut cx = TestContext::new();
+    let text = "\u{4EBA}\u{3005}";
+    assert_eq!(
+        soft_breaks(&mut cx, text, Some(LineBreakStrictness::Normal)),
+        vec![]
+    );
+    assert_eq!(
+        soft_breaks(&mut cx, text, Some(LineBreakStrictness::Loose)),
+        vec![3]
+    );
+}
+
+#[test]
+fn truncate_stops_after_last_width() {
+    let mut cx = TestContext::new();
+    let text = "aaa bbb ccc ddd";
+    let width = cx.layout(text, 16., None).width() * 0.3;
+    let widths = [width, width];
+    let mut layout = cx.layout(text, 16., None);
+    layout.break_all_lines_with_widths(&widths, LineWidthsOverflow::Truncate, Alignment::End);
+    assert_eq!(line_texts(&layout, text), vec!["aaa ", "bbb "]);
+    assert_eq!(layout.overflow_offset(), Some(8));
+    // The kept lines match those of an untruncated layout.
+    let mut full = cx.layout(text, 16., None);
+    full.break_all_lines_with_widths(&widths, LineWidthsOverflow::RepeatLast, Alignment::End);
+    assert_eq!(full.overflow_offset(), None);
+    for (line, full_line) in layout.lines().zip(full.lines()) {
+        assert_eq!(line.text_range(), full_line.text_range());
+        assert_eq!(line.metrics().offset, full_line.metrics().offset);
+        assert_eq!(line.metrics().baseline, full_line.metrics().baseline);
+    }
+    assert!(layout.height() < full.height());
+}
+
+#[test]
+fn truncate_keeps_text_that_fits() {
+    let mut cx = TestContext::new();
+    let text = "aaa bbb";
+    let mut layout = cx.layout(text, 16., None);
+    layout.break_all_lines_with_widths(&[1000.], LineWidthsOverflow::Truncate, Alignment::Start);
+    assert_eq!(line_texts(&layout, text), vec![text]);
+    assert_eq!(layout.overflow_offset(), None);
+}
+
+#[test]
+fn ligature_is_not_broken_in_later_run() {
+    let mut cx = TestContext::new();
+    // The word has no break opportunity, so it is only broken because
+    // overflowing words may be broken, and its "ffi" ligature is in the
+    // second run.
+    let text = "aoffice";
+    let build = |cx: &mut TestContext, max_advance| {
+        cx.layout_with(text, 16., max_advance, |builder| {
+            builder.push_default(&StyleProperty::OverflowWrap(OverflowWrap::Anywhere));
+            builder.push(&StyleProperty::FontSize(24.), 0..1);
+        })
+    };
+    let layout = build(&mut cx, None);
+    assert_eq!(layout.runs().count(), 2);
+    let rect = |range| layout.rects_for_source_range(range)[0];
+    let ligature = rect(2..5);
+    // The line ends within the ligature, so it is moved to the next line
+    // as a whole.
+    let max_advance = (ligature.x0 + ligature.width() * 0.8) as f32;
+    let layout = build(&mut cx, Some(max_advance));
+    assert_eq!(line_texts(&layout, text), vec!["ao", "ffice"]);
+}
+
+#[test]
+fn reflow_matches_rebuilt_layout() {
+    let mut cx = TestContext::new();
+    let text = "aaa bbb ccc ddd eee";
+    let wide = cx.layout(text, 16., None).width();
+    let mut layout = cx.layout(text, 16., None);
+    layout.break_all_lines(Some(wide * 0.6), Alignment::Middle);
+    let caret = Cursor::from_position(&layout, 12, true).caret_rect(&layout);
+    layout.reflow(Some(wide * 0.3));
+    let mut rebuilt = cx.layout(text, 16., None);
+    rebuilt.break_all_lines(Some(wide * 0.3), Alignment::Middle);
+    assert_eq!(line_texts(&layout, text), line_texts(&rebuilt, text));
+    assert!(layout.len() > 2);
+    for (line, rebuilt_line) in layout.lines().zip(rebuilt.lines()) {
+        // The alignment of the previous pass is kept.
+        assert_eq!(line.metrics().offset, rebuilt_line.metrics().offset);
+        assert_eq!(line.metrics().baseline, rebuilt_line.metrics().baseline);
+    }
+    // The cursor at the same offset follows the text to its new line.
+    let reflowed = Cursor::from_position(&layout, 12, true).caret_rect(&layout);
+    assert_eq!(
+        reflowed,
+        Cursor::from_position(&rebuilt, 12, true).caret_rect(&rebuilt)
+    );
+    assert!(reflowed.y0 > caret.y0);
+}
+
+#[test]
+fn min_width_is_widest_unbreakable_word() {
+    let mut cx = TestContext::new();
+    let text = "a supercalifragilistic word";
+    let layout = cx.layout(text, 16., None);
+    let word = cx.layout("supercalifragilistic", 16., None).width();
+    let min_width = layout.min_width_without_overflow();
+    assert!((min_width - word).abs() < 0.01);
+    // Breaking at the minimum width puts the long word on its own line,
+    // with no line overflowing.
+    let mut layout = cx.layout(text, 16., Some(min_width + 0.01));
+    assert_eq!(
+        line_texts(&layout, text),
+        vec!["a ", "supercalifragilistic ", "word"]
+    );
+    for line in layout.lines() {
+        assert!(line.metrics().advance - line.metrics().trailing_whitespace <= min_width + 0.01);
+    }
+    // The result does not depend on line breaking.
+    layout.break_all_lines(Some(10.), Alignment::Start);
+    assert_eq!(layout.min_width_without_overflow(), min_width);
+}
+
+#[test]
+fn overflow_wrap_breaks_long_words() {
+    let mut cx = TestContext::new();
+    let text = "a supercalifragilistic word";
+    let word = cx.layout("supercalifragilistic", 16., None).width();
+    let max_advance = word * 0.4;
+    // By default, the long word overflows the line rather than being broken.
+    let layout = cx.layout(text, 16., Some(max_advance));
+    assert_eq!(
+        line_texts(&layout, text),
+        vec!["a ", "supercalifragilistic ", "word"]
+    );
+    for overflow_wrap in [OverflowWrap::BreakWord, OverflowWrap::Anywhere] {
+        let build = |cx: &mut TestContext, max_advance| {
+            cx.layout_with(text, 16., max_advance, |builder| {
+                builder.push_default(&StyleProperty::OverflowWrap(overflow_wrap));
+            })
+        };
+        // The minimum width is that of the widest letter.
+        let layout = build(&mut cx, None);
+        let widest = layout
+            .runs()
+            .flat_map(|run| {
+                run.clusters()
+                    .map(|cluster| cluster.advance())
+                    .collect::<Vec<_>>()
+            })
+            .fold(0f32, f32::max);
+        assert!((layout.min_width_without_overflow() - widest).abs() < 0.01);
+        // The word is broken so that no line overflows, and other words are
+        // still moved to the next line whole.
+        let layout = build(&mut cx, Some(max_advance));
+        let lines = line_texts(&layout, text);
+        assert_eq!(lines[0], "a ");
+        assert!(lines.len() > 3);
+        assert_eq!(lines[lines.len() - 1], "word");
+        assert_eq!(lines.concat(), text);
+        for line in layout.lines() {
+            let metrics = line.metrics();
+            assert!(metrics.advance - metrics.trailing_whitespace <= max_advance);
+        }
+    }
+}
+
+#[test]
+fn no_break_spaces_are_kept_together() {
+    let mut cx = TestContext::new();
+    let text = "x a\u{A0}\u{A0}b";
+    let word = cx.layout("a\u{A0}\u{A0}b", 16., None).width();
+    let full = cx.layout(text, 16., None).width();
+    // Wherever the line overflows within the word, including at either
+    // no-break space, the line breaks at the regular space and the word is
+    // moved to the next line whole.
+    for step in 0..8 {
+        let max_advance = word + (full - word) * step as f32 / 8.;
+        let layout = cx.layout(text, 16., Some(max_advance));
+        assert_eq!(line_texts(&layout, text), vec!["x ", "a\u{A0}\u{A0}b"]);
+    }
+    let layout = cx.layout(text, 16., Some(full));
+    assert_eq!(line_texts(&layout, text), vec![text]);
+}
+
+#[test]
+fn no_wrap_range_stays_on_one_line() {
+    let mut cx = TestContext::new();
+    let text = "aaa bbb ccc ddd eee";
+    let build = |cx: &mut TestContext, max_advance| {
+        cx.layout_with(text, 16., max_advance, |builder| {
+            builder.push(&StyleProperty::Wrap(Wrap::NoWrap), 4..11);
+        })
+    };
+    let phrase = cx.layout("bbb ccc", 16., None).width();
+    let layout = build(&mut cx, Some(phrase * 1.1));
+    assert_eq!(
+        line_texts(&layout, text),
+        vec!["aaa ", "bbb ccc ", "ddd eee"]
+    );
+    // A phrase wider than the line overflows rather than being broken, while
+    // the text around it still wraps.
+    let layout = build(&mut cx, Some(phrase * 0.5));
+    assert_eq!(
+        line_texts(&layout, text),
+        vec!["aaa ", "bbb ccc ", "ddd ", "eee"]
+    );
+    assert!(layout.lines().nth(1).unwrap().metrics().advance > phrase * 0.5);
+    assert!((layout.min_width_without_overflow() - phrase).abs() < 0.01);
+}
+
+#[test]
+fn break_within_height_reports_continuation() {
+    let mut cx = TestContext::new();
+    let text = "aaa bbb ccc ddd";
+    let max_advance = cx.layout(text, 16., None).width() * 0.3;
+    let line_height = cx.layout("aaa", 16., None).height();
+    let mut layout = cx.layout(text, 16., None);
+    layout.break_all_lines_within(Some(max_advance), line_height * 2.5, Alignment::Start);
+    assert_eq!(line_texts(&layout, text), vec!["aaa ", "bbb "]);
+    assert!(layout.height() <= line_height * 2.5);
+    assert_eq!(layout.overflow_offset(), Some(8));
+    // Continuing from the offset in the next region lays out the rest.
+    let rest = &text[8..];
+    let mut next = cx.layout(rest, 16., None);
+    next.break_all_lines_within(Some(max_advance), line_height * 2.5, Alignment::Start);
+    assert_eq!(line_texts(&next, rest), vec!["ccc ", "ddd"]);
+    assert_eq!(next.overflow_offset(), None);
+    // The first line is kept even if it does not fit.
+    layout.break_all_lines_within(Some(max_advance), 1., Alignment::Start);
+    assert_eq!(line_texts(&layout, text), vec!["aaa "]);
+    assert_eq!(layout.overflow_offset(), Some(4));
+    // Breaking again without a height limit clears the overflow.
+    layout.break_all_lines(Some(max_advance), Alignment::Start);
+    assert_eq!(layout.len(), 4);
+    assert_eq!(layout.overflow_offset(), None);
+}
+
+#[test]
+fn stop_hangs_past_line_end() {
+    let mut cx = TestContext::new();
+    let text = "aaa bbb.";
+    let width = cx.layout("aaa bbb", 16., None).width() + 0.5;
+    let build = |cx: &mut TestContext, end| {
+        let mut layout = cx.layout_with(text, 16., None, |builder| {
+            builder.push_default(&StyleProperty::HangingPunctuation(HangingPunctuation {
+                end,
+                ..Default::default()
+            }));
+        });
+        layout.break_all_lines(Some(width), Alignment::End);
+        layout
+    };
+    // Without hanging, the period does not fit and moves the last word to
+    // the next line.
+    let layout = build(&mut cx, HangingEnd::None);
+    assert_eq!(line_texts(&layout, text), vec!["aaa ", "bbb."]);
+    // The hanging period is excluded from the line when aligning it, so the
+    // word before it is flush with the right edge.
+    let layout = build(&mut cx, HangingEnd::Allow);
+    assert_eq!(line_texts(&layout, text), vec![text]);
+    let rect = |range| layout.rects_for_source_range(range)[0];
+    assert!((rect(6..7).x1 as f32 - width).abs() < 0.01);
+    assert!(rect(7..8).x1 as f32 > width);
+    assert!(layout.width() <= width);
+}
+
+#[test]
+fn opening_bracket_hangs_at_paragraph_start() {
+    let mut cx = TestContext::new();
+    let text = "(aaa) bbb\n(ccc)";
+    let layout = cx.layout_with(text, 16., Some(1000.), |builder| {
+        builder.push_default(&StyleProperty::HangingPunctuation(HangingPunctuation {
+            first: true,
+            ..Default::default()
+        }));
+    });
+    // The bracket starting each paragraph sits left of the line box, so the
+    // word after it starts at the left edge.
+    for start in [0, 10] {
+        let bracket = layout.rects_for_source_range(start..start + 1)[0];
+        assert!(bracket.x0 < 0.);
+        assert!(bracket.x1.abs() < 0.01);
+        let word = layout.rects_for_source_range(start + 1..start + 4)[0];
+        assert!(word.x0.abs() < 0.01);
+    }
+}
+
+#[test]
+fn optical_alignment_makes_ink_flush() {
+    let mut cx = TestContext::new();
+    let text = "bob bob";
+    let width = 400.;
+    let build = |cx: &mut TestContext, optical, alignment| {
+        let mut layout = cx.layout_with(text, 32., None, |builder| {
+            builder.push_default(&StyleProperty::OpticalAlignment(optical));
+        });
+        layout.break_all_lines(Some(width), alignment);
+        layout
+    };
+    let mut scx = ScaleContext::new();
+    // The side bearing of the first glyph separates its ink from the left
+    // margin unless the line is optically aligned.
+    let natural = build(&mut cx, false, Alignment::Start).ink_bounds(&mut scx);
+    assert!(natural.x0 > 1.);
+    let optical = build(&mut cx, true, Alignment::Start).ink_bounds(&mut scx);
+    assert!(optical.x0.abs() < 0.01, "{}", optical.x0);
+    assert!((optical.width() - natural.width()).abs() < 0.01);
+    // The right side bearing of the last glyph is removed in the same way.
+    let natural = build(&mut cx, false, Alignment::End).ink_bounds(&mut scx);
+    assert!(natural.x1 < width as f64 - 1.);
+    let optical = build(&mut cx, true, Alignment::End).ink_bounds(&mut scx);
+    assert!((optical.x1 - width as f64).abs() < 0.01, "{}", optical.x1);
+}
+
+#[test]
+fn unchanged_lines_keep_their_ids() {
+    let mut cx = TestContext::new();
+    let text = "title\naaa bbb ccc ddd";
+    let wide = cx.layout(text, 16., None).width();
+    let mut layout = cx.layout(text, 16., Some(wide));
+    let ids: Vec<_> = layout.lines().map(|line| line.id()).collect();
+    let run_ids: Vec<_> = layout
+        .lines()
+        .next()
+        .unwrap()
+        .runs()
+        .map(|run| run.id())
+        .collect();
+    assert_eq!(ids.len(), 2);
+    // A separately built layout of the same text has the same ids.
+    let rebuilt = cx.layout(text, 16., Some(wide));
+    assert_eq!(
+        rebuilt.lines().map(|line| line.id()).collect::<Vec<_>>(),
+        ids
+    );
+    // Styling another line does not change the ids, even though it changes
+    // the indices of the styles.
+    let styled = cx.layout_with(text, 16., Some(wide), |builder| {
+        builder.push(&StyleProperty::Underline(true), 0..2);
+    });
+    assert_eq!(styled.lines().nth(1).unwrap().id(), ids[1]);
+    // After a narrower reflow, the first paragraph keeps its line and run
+    // ids while the rewrapped lines get new ones.
+    layout.reflow(Some(wide * 0.5));
+    assert_eq!(layout.len(), 3);
+    let first = layout.lines().next().unwrap();
+    assert_eq!(first.id(), ids[0]);
+    assert_eq!(
+        first.runs().map(|run| run.id()).collect::<Vec<_>>(),
+        run_ids
+    );
+    assert!(layout.lines().skip(1).all(|line| line.id() != ids[1]));
+}
+
+#[test]
+fn last_line_alignment_with_start() {
+    let mut cx = TestContext::new();
+    let text = "aaa bbb ccc\nddd eee fff";
+    let width = cx.layout("aaa bbb ", 16., None).width() + 1.;
+    let mut offsets = |forced_breaks| {
+        let mut layout = cx.layout(text, 16., None);
+        let last_line = LastLineAlignment {
+            alignment: Some(Alignment::Middle),
+            forced_breaks,
+        };
+        layout.break_all_lines_with_last_line(Some(width), Alignment::Start, last_line);
+        assert_eq!(
+            line_texts(&layout, text),
+            vec!["aaa bbb ", "ccc\n", "ddd eee ", "fff"]
+        );
+        let centered = |line: parley::layout::Line<Color>| {
+            let metrics = line.metrics();
+            let content = metrics.advance - metrics.trailing_whitespace;
+            (metrics.offset - (width - content) * 0.5).abs() < 0.01
+        };
+        let lines: Vec<_> = layout
+            .lines()
+            .map(|line| (line.metrics().offset == 0., centered(line)))
+            .collect();
+        // Reflowing keeps the last line alignment.
+        let before: Vec<_> = layout.lines().map(|line| line.metrics().offset).collect();
+        layout.reflow(Some(width));
+        let after: Vec<_> = layout.lines().map(|line| line.metrics().offset).collect();
+        assert_eq!(before, after);
+        lines
+    };
+    // Only the final line of the block is centered.
+    assert_eq!(
+        offsets(false),
+        vec![(true, false), (true, false), (true, false), (false, true)]
+    );
+    // Lines ending in a forced break can be treated as last lines too.
+    assert_eq!(
+        offsets(true),
+        vec![(true, false), (false, true), (true, false), (false, true)]
+    );
+}
+
+#[test]
+fn break_before_width_finds_last_fitting_opportunity() {
+    let mut cx = TestContext::new();
+    let text = "aaa bbb ccc\nddd";
+    let layout = cx.layout(text, 16., None);
+    let aaa_bbb = cx.layout("aaa bbb", 16., None).width();
+    // The trailing space after "bbb" does not count toward the width.
+    assert_eq!(layout.break_before_width(0, aaa_bbb + 0.5), Some(8));
+    assert_eq!(layout.break_before_width(0, aaa_bbb - 0.5), Some(4));
+    // Nothing fits before the first opportunity.
+    assert_eq!(layout.break_before_width(0, 1.), None);
+    // A mandatory break is returned as soon as it is reached.
+    assert_eq!(layout.break_before_width(8, 1000.), Some(12));
+    assert_eq!(layout.break_before_width(12, 1000.), Some(text.len()));
+    // The result matches the line breaker.
+    let wrapped = cx.layout(text, 16., Some(aaa_bbb + 0.5));
+    let second = wrapped.lines().nth(1).unwrap().text_range().start;
+    assert_eq!(second, 8);
+}
+
+#[test]
+fn streamed_lines_match_final_layout() {
+    let mut cx = TestContext::new();
+    let text = "aaa bbb ccc ddd\neee fff ggg hhh iii";
+    let width = cx.layout("aaa bbb ", 16., None).width() + 1.;
+    let snapshot = |line: &parley::layout::Line<Color>| {
+        let metrics = line.metrics();
+        let glyphs: usize = line.glyph_runs().map(|run| run.glyphs().count()).sum();
+        (line.text_range(), metrics.offset, metrics.baseline, glyphs)
+    };
+    for alignment in [Alignment::Middle, Alignment::Justified] {
+        let mut layout = cx.layout(text, 16., None);
+        let mut streamed = vec![];
+        layout.break_all_lines_streaming(Some(width), alignment, |line| {
+            streamed.push(snapshot(&line));
+        });
+        assert_eq!(streamed.len(), layout.len());
+        assert!(streamed.len() > 3);
+        // Lines are aligned before they are streamed.
+        let lines: Vec<_> = layout.lines().map(|line| snapshot(&line)).collect();
+        assert_eq!(streamed, lines);
+        let mut rebuilt = cx.layout(text, 16., None);
+        rebuilt.break_all_lines(Some(width), alignment);
+        let rebuilt: Vec<_> = rebuilt.lines().map(|line| snapshot(&line)).collect();
+        assert_eq!(streamed, rebuilt);
+    }
+}
+
+#[test]
+fn line_text_ranges_are_contiguous() {
+    let mut cx = TestContext::new();
+    let text = "one two  three four\nfive six seven";
+    let width = cx.layout("one two  ", 16., None).width() + 1.;
+    let layout = cx.layout(text, 16., Some(width));
+    assert!(layout.len() > 3);
+    let ranges: Vec<_> = layout.lines().map(|line| line.text_range()).collect();
+    // The lines cover the whole text without gaps or overlaps.
+    assert_eq!(ranges[0].start, 0);
+    assert_eq!(ranges.last().unwrap().end, text.len());
+    for pair in ranges.windows(2) {
+        assert!(!pair[0].is_empty());
+        assert_eq!(pair[0].end, pair[1].start);
+    }
+    // A line ending at a soft wrap keeps its trailing spaces, and the
+    // following line starts at the next word.
+    assert_eq!(&text[ranges[0].clone()], "one two  ");
+    assert!(text[ranges[1].clone()].starts_with("three"));
+    // The line ending at the newline includes it.
+    let newline = text.find('\n').unwrap();
+    assert!(ranges.iter().any(|range| range.end == newline + 1));
+}
+
+#[test]
+fn preserved_spaces_wrap_with_break_spaces() {
+    let mut cx = TestContext::new();
+    let text = "ab      cd";
+    let wrap = StyleProperty::Wrap(Wrap::BreakSpaces);
+    let width = cx
+        .layout_with("ab   ", 16., None, |builder| builder.push_default(&wrap))
+        .width()
+        + 1.;
+    let layout = cx.layout_with(text, 16., Some(width), |builder| {
+        builder.push_default(&wrap)
+    });
+    // The spaces that do not fit wrap to the next line instead of hanging
+    // past the end of the first.
+    assert_eq!(line_texts(&layout, text), vec!["ab   ", "   cd"]);
+    for line in layout.lines() {
+        let metrics = line.metrics();
+        assert!(metrics.advance <= width);
+        assert_eq!(metrics.trailing_whitespace, 0.);
+    }
+    // By default, trailing spaces hang past the line end.
+    let layout = cx.layout(text, 16., Some(width));
+    let metrics = layout.lines().next().unwrap().metrics().clone();
+    assert!(metrics.trailing_whitespace > 0.);
+    assert!(metrics.advance > width);
+}
+
+#[test]
+fn marker_body_lines_hang_past_marker() {
+    let mut cx = TestContext::new();
+    let marker = "1. ";
+    let text = "1. alpha beta gamma delta epsilon zeta";
+    let marker_advance = cx.layout(marker, 16., None).full_width();
+    let marker_width = marker_advance + 20.;
+    let max = cx.layout("alpha beta ", 16., None).width() + marker_width + 1.;
+    let mut layout = cx.layout(text, 16., None);
+    layout.break_all_lines_with_marker(marker.len(), marker_width, Some(max), Alignment::Start);
+    let lines = line_texts(&layout, text);
+    assert_eq!(lines[0], "1. alpha beta ");
+    assert!(lines.len() > 2);
+    let x = |offset: usize| layout.rects_for_source_range(offset..offset + 1)[0].x0 as f32;
+    // The marker is placed at the end of its region, where the body begins.
+    assert!((x(0) - 20.).abs() < 1e-3);
+    assert!((x(marker.len()) - marker_width).abs() < 1e-3);
+    // The wrapped lines of the body align under its first line and stay
+    // within the maximum advance.
+    for line in layout.lines().skip(1) {
+        assert!((x(line.text_range().start) - marker_width).abs() < 1e-3);
+    }
+    assert!(layout.width() <= max);
+}
+
+#[test]
+fn layout_with_marker_aligns_body_lines() {
+    let mut cx = TestContext::new();
+    let (marker, body) = ("\u{2022} ", "alpha beta gamma delta epsilon zeta");
+    let marker_width = 30.;
+    let max = cx.layout("alpha beta ", 16., None).width() + marker_width + 1.;
+    let layout = cx.lcx.layout_with_marker(
+        &mut cx.fcx,
+        marker,
+        body,
+        1.,
+        marker_width,
+        Some(max),
+        Alignment::Start,
+        |builder| {
+            builder.push_default(&StyleProperty::FontStack(FontStack::Source(FAMILY)));
+            builder.push_default(&StyleProperty::FontSize(16.));
+        },
+    );
+    let text = [marker, body].concat();
+    let lines = line_texts(&layout, &text);
+    assert_eq!(lines[0], "\u{2022} alpha beta ");
+    assert!(lines.len() > 2);
+    let x = |offset: usize| layout.rects_for_source_range(offset..offset + 1)[0].x0 as f32;
+    // The body begins at the end of the marker region on the first line,
+    // and its wrapped lines align under it.
+    assert!((x(marker.len()) - marker_width).abs() < 1e-3);
+    for line in layout.lines().skip(1) {
+        assert!((x(line.text_range().start) - marker_width).abs() < 1e-3);
+    }
+}
+
+#[test]
+fn measure_at_increasing_widths() {
+    let mut cx = TestContext::new();
+    let text = "the quick brown fox jumps over the lazy dog";
+    let mut layout = cx.layout(text, 16., None);
+    let full = layout.width();
+    let widths = [40., 80., 120., 200., full + 1.];
+    let sizes = layout.measure_at_widths(&widths);
+    assert_eq!(sizes.len(), widths.len());
+    // Wider boxes never need more lines.
+    for pair in sizes.windows(2) {
+        assert!(pair[1].height <= pair[0].height);
+    }
+    assert!(sizes[0].height > sizes[4].height);
+    // Each size matches a layout broken at that width.
+    for (size, width) in sizes.iter().zip(widths) {
+        let broken = cx.layout(text, 16., Some(width));
+        assert_eq!(size.width, broken.width() as f64);
+        assert_eq!(size.height, broken.height() as f64);
+    }
+    // The lines are left broken at the last width.
+    assert_eq!(layout.len(), 1);
+}
+
+#[test]
+fn forced_breaks_start_first_lines() {
+    let mut cx = TestContext::new();
+    let text = "aaaaa (bbb\n(ccc";
+    let width = cx.layout("aaaaa ", 16., None).width() + 1.;
+    let build = |cx: &mut TestContext| {
+        cx.layout_with(text, 16., Some(width), |builder| {
+            builder.push_default(&StyleProperty::HangingPunctuation(HangingPunctuation {
+                first: true,
+                ..Default::default()
+            }));
+        })
+    };
+    let first_lines = |layout: &Layout<Color>| {
+        layout
+            .lines()
+            .map(|line| line.is_first_line())
+            .collect::<Vec<_>>()
+    };
+    // By default, the line after the newline is a first line, so its
+    // opening bracket hangs like that of the first line. The soft wrap does
+    // not start a first line.
+    let layout = build(&mut cx);
+    assert_eq!(line_texts(&layout, text), vec!["aaaaa ", "(bbb\n", "(ccc"]);
+    assert_eq!(first_lines(&layout), vec![true, false, true]);
+    assert!(layout.rects_for_source_range(6..7)[0].x0.abs() < 0.01);
+    assert!(layout.rects_for_source_range(11..12)[0].x0 < 0.);
+    // Otherwise, only the first line of the layout is a first line.
+    cx.lcx.set_forced_breaks_start_first_line(false);
+    let layout = build(&mut cx);
+    assert_eq!(first_lines(&layout), vec![true, false, false]);
+    assert!(layout.rects_for_source_range(11..12)[0].x0.abs() < 0.01);
+}
+
+#[test]
+fn word_segments_box_each_word() {
+    let mut cx = TestContext::new();
+    let text = "foo bar baz";
+    let layout = cx.layout(text, 16., None);
+    let segments: Vec<_> = layout.word_segments().collect();
+    let ranges: Vec<_> = segments.iter().map(|(range, _)| range.clone()).collect();
+    // Each word is a segment with its trailing space.
+    assert_eq!(ranges, vec![0..4, 4..8, 8..11]);
+    let mut x = 0.;
+    for (range, rects) in &segments {
+        assert_eq!(rects.len(), 1);
+        let rect = rects[0];
+        assert_eq!(rect, layout.rects_for_source_range(range.clone())[0]);
+        assert!((rect.x0 - x).abs() < 0.01);
+        let width = cx.layout(&text[range.clone()], 16., None).full_width();
+        assert!((rect.width() as f32 - width).abs() < 0.01);
+        x = rect.x1;
+    }
+    // A segment broken across lines has a rectangle per line.
+    let text = "a supercalifragilistic";
+    let width = cx.layout("supercalifragilistic", 16., None).width() * 0.6;
+    let layout = cx.layout_with(text, 16., Some(width), |builder| {
+        builder.push_default(&StyleProperty::OverflowWrap(OverflowWrap::Anywhere))
+    });
+    let segments: Vec<_> = layout.word_segments().collect();
+    assert_eq!(segments.len(), 2);
+    assert_eq!(segments[1].0, 2..text.len());
+    let rects = &segments[1].1;
+    assert_eq!(rects.len(), 2);
+    assert!(rects[1].y0 >= rects[0].y1 - 0.01);
+}
+
+#[test]
+fn break_spaces_queries_match_line_breaking() {
+    let mut cx = TestContext::new();
+    let text = "ab   cd  ef gh    ij";
+    let wrap = StyleProperty::Wrap(Wrap::BreakSpaces);
+    let build = |cx: &mut TestContext, max_advance| {
+        cx.layout_with(text, 16., max_advance, |builder| {
+            builder.push_default(&wrap)
+        })
+    };
+    let layout = build(&mut cx, None);
+    let width = layout.width();
+    let min_width = layout.min_width_without_overflow();
+    // Every space is followed by a break opportunity, and spaces do not
+    // hang, so the widest segment includes its trailing space.
+    let segment = cx
+        .layout_with("gh ", 16., None, |builder| builder.push_default(&wrap))
+        .width();
+    assert!((min_width - segment).abs() < 0.01);
+    for step in 0..8 {
+        let max_advance = min_width + (width - min_width) * step as f32 / 8.;
+        let layout = build(&mut cx, Some(max_advance));
+        let starts: Vec<_> = layout.lines().map(|line| line.text_range().start).collect();
+        let mut opportunities = vec![];
+        for line in layout.lines() {
+            assert!(line.metrics().advance <= max_advance);
+            for run in line.runs() {
+                for cluster in run.clusters() {
+                    if cluster.is_line_break_opportunity_after() {
+                        opportunities.push(cluster.text_range().end);
+                    }
+                }
+            }
+        }
+        // The segments end at the break opportunities and at the end of
+        // the text, and no segment is broken across lines.
+        let segments: Vec<_> = layout.word_segments().collect();
+        let mut ends: Vec<_> = segments.iter().map(|(range, _)| range.end).collect();
+        assert_eq!(ends.pop(), Some(text.len()));
+        assert_eq!(ends, opportunities);
+        assert!(segments.iter().all(|(_, rects)| rects.len() == 1));
+        // Each line ends at the last opportunity at which its content fits.
+        for (index, start) in starts.iter().enumerate() {
+            assert!(*start == 0 || opportunities.contains(start));
+            let end = starts.get(index + 1).copied().unwrap_or(text.len());
+            assert_eq!(layout.break_before_width(*start, max_advance), Some(end));
+        }
+    }
+    // Below the minimum width, the widest segment overflows.
+    let layout = build(&mut cx, Some(min_width - 1.));
+    assert!(layout
+        .lines()
+        .any(|line| line.metrics().advance > min_width - 1.));
+}
@@ -0,0 +1,117 @@
+mod common;
+
+use common::*;
+use parley::layout::Alignment;
+use parley::style::{FontFamily, FontPalette, FontStack, PaletteOverride, StyleProperty};
+use parley::swash::scale::ScaleContext;
+use parley::{FontContext, Layout, LayoutContext};
+
+/// Color font in which "A" is drawn as two layers, using palette entries 0
+/// and 1. Generated by `assets/make_color_font.py`.
+const COLOR_FONT_DATA: &[u8] = include_bytes!("assets/ParleyColorTest.ttf");
+const COLOR_FAMILY: &str = "Parley Color Test";
+
+const SUNRISE: [[u8; 4]; 2] = [[255, 0, 0, 255], [255, 200, 0, 255]];
+const MIDNIGHT: [[u8; 4]; 2] = [[0, 0, 128, 255], [128, 0, 255, 255]];
+
+fn color_layout(palettes: &[(FontPalette, std::ops::Range<usize>)]) -> Layout<Color> {
+    color_layout_with(palettes, &[])
+}
+
+fn color_layout_with(
+    palettes: &[(FontPalette, std::ops::Range<usize>)],
+    overrides: &[PaletteOverride],
+) -> Layout<Color> {
+    let mut fcx = FontContext::new();
+    let family = fcx.register_fonts(COLOR_FONT_DATA.to_vec()).unwrap();
+    assert_eq!(family, COLOR_FAMILY);
+    let mut lcx = LayoutContext::<Color>::new();
+    let mut builder = lcx.ranged_builder(&mut fcx, "AA", 1.);
+    builder.push_default(&StyleProperty::FontStack(FontStack::Single(
+        FontFamily::Named(COLOR_FAMILY),
+    )));
+    builder.push_default(&StyleProperty::FontSize(20.));
+    builder.push_default(&StyleProperty::FontPaletteOverrides(overrides));
+    for (palette, range) in palettes {
+        builder.push(&StyleProperty::FontPalette(palette.clone()), range.clone());
+    }
+    let mut layout = builder.build();
+    layout.break_all_lines(None, Alignment::Start);
+    layout
+}
+
+/// Returns the colors of the layers of each glyph, in visual order.
+fn layer_colors(layout: &Layout<Color>) -> Vec<Vec<Option<[u8; 4]>>> {
+    let mut scx = ScaleContext::new();
+    let mut colors = vec![];
+    for line in layout.lines() {
+        for glyph_run in line.glyph_runs() {
+            let run = glyph_run.run();
+            let font = run.font().as_ref();
+            let mut scaler = scx.builder(font).size(run.font_size()).build();
+            for glyph in glyph_run.glyphs() {
+                let outline = scaler.scale_color_outline(glyph.id).unwrap();
+                colors.push(
+                    (0..outline.len())
+                        .map(|index| {
+                            let index = outline.get(index).unwrap().color_index().unwrap();
+                            glyph_run.style().palette_color(&font, index)
+                        })
+                        .collect(),
+                );
+            }
+        }
+    }
+    colors
+}
+
+fn some(colors: [[u8; 4]; 2]) -> Vec<Option<[u8; 4]>> {
+    colors.iter().copied().map(Some).collect()
+}
+
+#[test]
+fn palette_index_selects_layer_colors() {
+    let layout = color_layout(&[(FontPalette::Index(1), 1..2)]);
+    assert_eq!(layer_colors(&layout), vec![some(SUNRISE), some(MIDNIGHT)]);
+    // A missing palette falls back to the default.
+    let layout = color_layout(&[(FontPalette::Index(5), 0..2)]);
+    assert_eq!(layer_colors(&layout), vec![some(SUNRISE); 2]);
+}
+
+#[test]
+fn palette_by_name_and_usability() {
+    let layout = color_layout(&[(FontPalette::Named("Midnight".into()), 0..1)]);
+    assert_eq!(layer_colors(&layout), vec![some(MIDNIGHT), some(SUNRISE)]);
+    let layout = color_layout(&[
+        (FontPalette::Dark, 0..1),
+        (FontPalette::Named("No Such Palette".into()), 1..2),
+    ]);
+    assert_eq!(layer_colors(&layout), vec![some(MIDNIGHT), some(SUNRISE)]);
+}
+
+#[test]
+fn palette_overrides_replace_entries() {
+    let green = [0, 255, 0, 255];
+    let overrides = [
+        PaletteOverride {
+            index: 1,
+            color: [0, 0, 0, 255],
+        },
+        PaletteOverride {
+            index: 1,
+            color: green,
+        },
+        PaletteOverride {
+            index: 7,
+            color: green,
+        },
+    ];
+    let layout = color_layout_with(&[(FontPalette::Light, 0..2)], &overrides);
+    let colors = layer_colors(&layout);
+    assert_eq!(colors, vec![vec![Some(SUNRISE[0]), Some(green)]; 2]);
+    // Entries outside the palette are only available when overridden.
+    let glyph_run = layout.lines().next().unwrap().glyph_runs().next().unwrap();
+    let font = glyph_run.run().font().as_ref();
+    assert_eq!(glyph_run.style().palette_color(&font, 7), Some(green));
+    assert_eq!(glyph_run.style().palette_color(&font, 2), None);
+}
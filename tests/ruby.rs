@@ -0,0 +1,86 @@
+mod common;
+
+use common::*;
+use parley::layout::Alignment;
+use parley::Layout;
+
+fn annotation(cx: &mut TestContext, text: &str) -> Layout<Color> {
+    cx.layout(text, 8., None)
+}
+
+fn run_advances(layout: &Layout<Color>) -> f32 {
+    layout.runs().map(|run| run.advance()).sum()
+}
+
+#[test]
+fn wide_annotation_pads_base() {
+    let mut cx = TestContext::new();
+    let text = "ab cd";
+    let mut layout = cx.layout(text, 16., None);
+    let width = layout.width();
+    let ruby = annotation(&mut cx, "wide annotation text");
+    let ruby_width = ruby.width();
+    let base_width = layout.rects_for_source_range(3..5)[0].width() as f32;
+    assert!(ruby_width > base_width);
+    layout.push_ruby(3..5, ruby);
+    layout.break_all_lines(None, Alignment::Start);
+    let padded = width - base_width + ruby_width;
+    assert!((layout.width() - padded).abs() < 0.01);
+    // The advances of the runs include the padding.
+    assert!((run_advances(&layout) - padded).abs() < 0.01);
+    let annotations: Vec<_> = layout.ruby_annotations().collect();
+    assert_eq!(annotations.len(), 1);
+    assert_eq!(annotations[0].text_range, 3..5);
+    let base = layout.rects_for_source_range(3..5)[0];
+    assert!((annotations[0].x - base.x0 as f32).abs() < 0.01);
+}
+
+#[test]
+fn pushing_again_replaces_annotation() {
+    let mut cx = TestContext::new();
+    let text = "ab cd";
+    let mut layout = cx.layout(text, 16., None);
+    let ruby = annotation(&mut cx, "wide annotation text");
+    layout.push_ruby(3..5, ruby.clone());
+    layout.break_all_lines(None, Alignment::Start);
+    let width = layout.width();
+    layout.push_ruby(3..5, ruby);
+    layout.break_all_lines(None, Alignment::Start);
+    assert!((layout.width() - width).abs() < 0.01);
+    assert!((run_advances(&layout) - width).abs() < 0.01);
+    assert_eq!(layout.ruby_annotations().count(), 1);
+    // A narrow annotation overlapping the base removes the padding.
+    let narrow = annotation(&mut cx, "x");
+    layout.push_ruby(4..5, narrow);
+    layout.break_all_lines(None, Alignment::Start);
+    let plain = cx.layout(text, 16., None);
+    assert!((layout.width() - plain.width()).abs() < 0.01);
+    let ranges: Vec<_> = layout.ruby_annotations().map(|r| r.text_range).collect();
+    assert_eq!(ranges, vec![4..5]);
+}
+
+#[test]
+fn no_break_within_base() {
+    let mut cx = TestContext::new();
+    let text = "aaaa bbbb cccc";
+    let mut layout = cx.layout(text, 16., None);
+    let ruby = annotation(&mut cx, "r");
+    layout.push_ruby(0..9, ruby);
+    let width = layout.rects_for_source_range(0..4)[0].width() as f32;
+    layout.break_all_lines(Some(width * 1.5), Alignment::Start);
+    assert_eq!(line_texts(&layout, text), vec!["aaaa bbbb ", "cccc"]);
+}
+
+#[test]
+fn annotations_are_ordered_by_base() {
+    let mut cx = TestContext::new();
+    let text = "ab cd ef";
+    let mut layout = cx.layout(text, 16., None);
+    for range in [6..8, 0..2, 3..5].iter() {
+        let ruby = annotation(&mut cx, "r");
+        layout.push_ruby(range.clone(), ruby);
+    }
+    layout.break_all_lines(None, Alignment::Start);
+    let ranges: Vec<_> = layout.ruby_annotations().map(|r| r.text_range).collect();
+    assert_eq!(ranges, vec![0..2, 3..5, 6..8]);
+}
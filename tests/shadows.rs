@@ -0,0 +1,97 @@
+mod common;
+
+use common::*;
+use parley::style::{Shadow, StyleProperty};
+use parley::swash::scale::ScaleContext;
+
+fn shadow(x: f32, y: f32, blur_radius: f32) -> Shadow<Color> {
+    Shadow {
+        x,
+        y,
+        blur_radius,
+        brush: Some([0, 0, 0, 128]),
+    }
+}
+
+#[test]
+fn shadows_survive_run_splitting() {
+    let mut cx = TestContext::new();
+    let text = "ab cd ef";
+    let shadows = [shadow(1., 2., 3.)];
+    // Wrap after "ab cd " and change the font size inside the shadowed
+    // range, so that it spans several runs and lines.
+    let width = cx
+        .layout_with("ab cd ", 16., None, |builder| {
+            builder.push(&StyleProperty::FontSize(20.), 3..5);
+        })
+        .width()
+        + 1.;
+    let layout = cx.layout_with(text, 16., Some(width), |builder| {
+        builder.push(&StyleProperty::TextShadow(&shadows), 1..7);
+        builder.push(&StyleProperty::FontSize(20.), 3..5);
+    });
+    assert_eq!(line_texts(&layout, text), vec!["ab cd ", "ef"]);
+    // Every glyph in the range keeps the shadows of its style.
+    for run in layout.runs() {
+        for cluster in run.clusters() {
+            for glyph in cluster.glyphs() {
+                let style = &layout.styles()[glyph.style_index()];
+                if (1..7).contains(&cluster.text_range().start) {
+                    assert_eq!(style.text_shadow, shadows);
+                } else {
+                    assert!(style.text_shadow.is_empty());
+                }
+            }
+        }
+    }
+    // The range is split into "b ", "cd" and " " on the first line and
+    // "e" on the second.
+    let shadowed: Vec<_> = layout
+        .lines()
+        .flat_map(|line| {
+            line.glyph_runs()
+                .map(|glyph_run| glyph_run.shadows().to_vec())
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    assert_eq!(
+        shadowed,
+        vec![
+            vec![],
+            shadows.to_vec(),
+            shadows.to_vec(),
+            shadows.to_vec(),
+            shadows.to_vec(),
+            vec![],
+        ]
+    );
+}
+
+#[test]
+fn shadows_expand_ink_bounds() {
+    let mut cx = TestContext::new();
+    let text = "ink";
+    let mut scx = ScaleContext::new();
+    let plain = cx.layout(text, 16., None);
+    let ink = plain.ink_bounds(&mut scx);
+    assert!(ink.width() > 0. && ink.height() > 0.);
+    assert!(ink.x0 >= 0. && ink.x1 <= plain.width() as f64);
+    assert!(ink.y0 >= 0. && ink.y1 <= plain.height() as f64);
+    let shadows = [shadow(3., 4., 2.)];
+    let layout = cx.layout_with(text, 16., None, |builder| {
+        builder.push_default(&StyleProperty::TextShadow(&shadows));
+    });
+    // The shadow extends the ink by its offset and blur radius to the right
+    // and bottom, and stays within it to the left and top.
+    let bounds = layout.ink_bounds(&mut scx);
+    let expected = (ink.x0, ink.y0, ink.x1 + 5., ink.y1 + 6.);
+    let actual = (bounds.x0, bounds.y0, bounds.x1, bounds.y1);
+    assert!((actual.0 - expected.0).abs() < 1e-3, "{:?}", actual);
+    assert!((actual.1 - expected.1).abs() < 1e-3, "{:?}", actual);
+    assert!((actual.2 - expected.2).abs() < 1e-3, "{:?}", actual);
+    assert!((actual.3 - expected.3).abs() < 1e-3, "{:?}", actual);
+    assert!(cx
+        .layout(" ", 16., None)
+        .ink_bounds(&mut scx)
+        .is_zero_area());
+}
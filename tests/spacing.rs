@@ -0,0 +1,216 @@
+mod common;
+
+use common::*;
+use parley::layout::{Cluster, Run};
+use parley::style::{FontSettings, SpacingValue, StyleProperty};
+use parley::Layout;
+
+/// Returns the extra advance produced by the spacing style.
+fn extra_advance(
+    cx: &mut TestContext,
+    text: &str,
+    size: f32,
+    property: StyleProperty<'static, Color>,
+) -> f32 {
+    let plain = cx.layout(text, size, None).width();
+    let spaced = cx
+        .layout_with(text, size, None, |builder| builder.push_default(&property))
+        .width();
+    spaced - plain
+}
+
+#[test]
+fn em_spacing_scales_with_font_size() {
+    let mut cx = TestContext::new();
+    let text = "abcd efg";
+    for spacing in [
+        StyleProperty::LetterSpacing(SpacingValue::Em(0.1)),
+        StyleProperty::WordSpacing(SpacingValue::Em(0.25)),
+    ] {
+        let small = extra_advance(&mut cx, text, 16., spacing.clone());
+        let large = extra_advance(&mut cx, text, 32., spacing);
+        assert!(small > 0.);
+        assert!((large - small * 2.).abs() < 0.01, "{} {}", small, large);
+    }
+    // Percentages are hundredths of an em.
+    let em = extra_advance(
+        &mut cx,
+        text,
+        20.,
+        StyleProperty::LetterSpacing(SpacingValue::Em(0.5)),
+    );
+    let percent = extra_advance(
+        &mut cx,
+        text,
+        20.,
+        StyleProperty::LetterSpacing(SpacingValue::Percent(50.)),
+    );
+    assert!((em - percent).abs() < 0.01);
+    // Absolute spacing does not depend on the font size.
+    let absolute = StyleProperty::LetterSpacing(SpacingValue::Absolute(2.));
+    let small = extra_advance(&mut cx, text, 16., absolute.clone());
+    let large = extra_advance(&mut cx, text, 32., absolute);
+    assert!((large - small).abs() < 0.01);
+}
+
+#[test]
+fn spacing_is_refreshed_after_style_change() {
+    let mut cx = TestContext::new();
+    let text = "aaaa bbbb";
+    // Only the first word is spaced.
+    let layout = cx.layout_with(text, 16., None, |builder| {
+        builder.push(&StyleProperty::LetterSpacing(SpacingValue::Em(0.5)), 0..4);
+    });
+    let plain = cx.layout(text, 16., None);
+    let advances = |layout: &Layout<Color>| -> Vec<f32> {
+        layout
+            .runs()
+            .flat_map(|run| {
+                run.clusters()
+                    .map(|cluster| cluster.advance())
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    };
+    let (spaced, plain) = (advances(&layout), advances(&plain));
+    for (index, (spaced, plain)) in spaced.iter().zip(&plain).enumerate() {
+        if index < 4 {
+            assert!((spaced - plain - 8.).abs() < 0.01);
+        } else {
+            assert!((spaced - plain).abs() < 0.01);
+        }
+    }
+}
+
+#[test]
+fn kerning_can_be_disabled() {
+    let mut cx = TestContext::new();
+    let text = "AV";
+    let kerned = cx.layout(text, 32., None).width();
+    let unkerned = cx
+        .layout_with(text, 32., None, |builder| {
+            builder.push_default(&StyleProperty::Kerning(false));
+        })
+        .width();
+    assert!(unkerned > kerned + 1., "{} {}", unkerned, kerned);
+    // Explicit feature settings take precedence.
+    let explicit = cx
+        .layout_with(text, 32., None, |builder| {
+            builder.push_default(&StyleProperty::Kerning(false));
+            builder.push_default(&StyleProperty::FontFeatures(FontSettings::Source(
+                "\"kern\" 1",
+            )));
+        })
+        .width();
+    assert_eq!(explicit, kerned);
+    // Tracking is applied on top of the unkerned advances.
+    let tracked = cx
+        .layout_with(text, 32., None, |builder| {
+            builder.push_default(&StyleProperty::Kerning(false));
+            builder.push_default(&StyleProperty::LetterSpacing(SpacingValue::Absolute(3.)));
+        })
+        .width();
+    assert!((tracked - unkerned - 6.).abs() < 0.01);
+}
+
+#[test]
+fn advance_adjuster_kerns_pairs() {
+    let mut cx = TestContext::new();
+    let text = "AVAV";
+    let build = |cx: &mut TestContext, split: bool| {
+        cx.layout_with(text, 16., None, |builder| {
+            if split {
+                // A different size puts the first "V" in a separate run.
+                builder.push(&StyleProperty::FontSize(16.5), 1..2);
+            }
+        })
+    };
+    let x = |layout: &Layout<Color>, offset: usize| {
+        layout.rects_for_source_range(offset..offset + 1)[0].x0 as f32
+    };
+    let natural = build(&mut cx, false);
+    let split = build(&mut cx, true);
+    // Pairs are identified by the source text of the clusters.
+    let adjust = move |_: &Run<Color>, a: &Cluster<Color>, b: &Cluster<Color>| {
+        if (&text[a.text_range()], &text[b.text_range()]) == ("A", "V") {
+            -2.
+        } else {
+            0.
+        }
+    };
+    cx.lcx.set_advance_adjuster(false, adjust);
+    let adjusted = build(&mut cx, false);
+    assert!((natural.width() - adjusted.width() - 4.).abs() < 0.01);
+    for (offset, shift) in [(1, 2.), (2, 2.), (3, 4.)] {
+        assert!((x(&natural, offset) - x(&adjusted, offset) - shift).abs() < 0.01);
+    }
+    // Pairs spanning two runs are only adjusted when opted in.
+    let adjusted = build(&mut cx, true);
+    assert!((split.width() - adjusted.width() - 2.).abs() < 0.01);
+    cx.lcx.set_advance_adjuster(true, adjust);
+    let adjusted = build(&mut cx, true);
+    assert!((split.width() - adjusted.width() - 4.).abs() < 0.01);
+    cx.lcx.clear_advance_adjuster();
+    assert_eq!(build(&mut cx, false).width(), natural.width());
+}
+
+#[test]
+fn negative_tracking_respects_advance_floor() {
+    let mut cx = TestContext::new();
+    let text = "AVA";
+    let advances = |cx: &mut TestContext| -> Vec<f32> {
+        cx.layout_with(text, 16., None, |builder| {
+            builder.push_default(&StyleProperty::LetterSpacing(SpacingValue::Absolute(-12.)));
+        })
+        .clusters_for_source_range(0..text.len())
+        .map(|cluster| cluster.advance())
+        .collect()
+    };
+    let kerned: Vec<_> = cx
+        .layout(text, 16., None)
+        .clusters_for_source_range(0..text.len())
+        .map(|cluster| cluster.advance())
+        .collect();
+    // The "AV" pair is kerned before spacing is applied.
+    let single = cx.layout("A", 16., None).width();
+    assert!(kerned[0] < single);
+    // Without a floor, the tracking makes the advances negative.
+    let tracked = advances(&mut cx);
+    for (tracked, kerned) in tracked.iter().zip(&kerned) {
+        assert!((tracked - (kerned - 12.)).abs() < 0.01);
+    }
+    assert!(tracked.iter().all(|&advance| advance < 0.));
+    cx.lcx.set_spacing_advance_floor(Some(2.));
+    assert_eq!(advances(&mut cx), vec![2.; 3]);
+    // Advances that are below the floor without spacing are not raised.
+    cx.lcx.set_spacing_advance_floor(Some(100.));
+    assert_eq!(advances(&mut cx), kerned);
+}
+
+#[test]
+fn word_spacing_only_widens_spaces() {
+    let mut cx = TestContext::new();
+    let text = "a b\tc\u{3000}d\u{A0}e";
+    let advances = |layout: &Layout<Color>| -> Vec<(usize, f32)> {
+        layout
+            .clusters_for_source_range(0..text.len())
+            .map(|cluster| (cluster.text_range().start, cluster.advance()))
+            .collect()
+    };
+    let plain = advances(&cx.layout(text, 16., None));
+    let spaced = advances(&cx.layout_with(text, 16., None, |builder| {
+        builder.push_default(&StyleProperty::WordSpacing(SpacingValue::Absolute(5.)));
+    }));
+    let widened: Vec<_> = plain
+        .iter()
+        .zip(&spaced)
+        .filter(|(plain, spaced)| plain != spaced)
+        .map(|((offset, plain), (_, spaced))| {
+            assert!((spaced - plain - 5.).abs() < 0.01);
+            text[*offset..].chars().next().unwrap()
+        })
+        .collect();
+    // Only the space and no-break space are widened, and not the tab or
+    // the ideographic space.
+    assert_eq!(widened, vec![' ', '\u{A0}']);
+}